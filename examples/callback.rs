@@ -0,0 +1,65 @@
+extern crate vkrs;
+extern crate clap;
+extern crate reqwest;
+
+use std::thread;
+use std::time::Duration;
+use clap::{Arg, App};
+use vkrs::callback::Callback;
+
+// Requests as sent by VK, to check the server without public address
+fn send_fake_requests(url: &str, secret: &str) {
+    let client = reqwest::Client::new();
+    let requests = [
+        format!(r#"{{"type": "confirmation", "group_id": 1, "secret": "{}"}}"#, secret),
+        format!(r#"{{"type": "group_join", "object": {{"user_id": 1, "join_type": "join"}}, "group_id": 1, "secret": "{}"}}"#, secret),
+        r#"{"type": "group_join", "object": {"user_id": 1, "join_type": "join"}, "group_id": 1, "secret": "wrong"}"#.to_owned(),
+    ];
+
+    for body in &requests {
+        match client.post(url).body(body.clone()).send().and_then(|mut resp| resp.text().map(|text| (resp.status(), text))) {
+            Ok((status, text)) => println!("VK <- {} {:?}", status, text),
+            Err(err) => println!("VK <- error: {}", err),
+        }
+    }
+}
+
+fn main() {
+    let args = App::new("callback")
+        .author("Konstantin Stepanov <me@kstep.me>")
+        .version("0.1.0")
+        .about("Print VK Callback API events")
+        .arg(Arg::with_name("confirmation")
+             .required(true)
+             .help("Confirmation code"))
+        .arg(Arg::with_name("secret")
+             .long("secret")
+             .takes_value(true)
+             .help("Secret key"))
+        .arg(Arg::with_name("listen")
+             .long("listen")
+             .takes_value(true)
+             .default_value("127.0.0.1:8080")
+             .help("Address to listen on"))
+        .arg(Arg::with_name("fake")
+             .long("fake")
+             .help("Send fake VK requests to the server"))
+        .get_matches();
+
+    let mut callback = Callback::new(args.value_of("confirmation").unwrap());
+    if let Some(secret) = args.value_of("secret") {
+        callback.secret(secret);
+    }
+
+    let addr = args.value_of("listen").unwrap().to_owned();
+    if args.is_present("fake") {
+        let url = format!("http://{}/", addr);
+        let secret = args.value_of("secret").unwrap_or("").to_owned();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            send_fake_requests(&url, &secret);
+        });
+    }
+
+    callback.serve(&*addr, |event| println!("{:?}", event)).unwrap();
+}
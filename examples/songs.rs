@@ -7,6 +7,7 @@ mod helpers;
 use helpers::{fetch_access_token, get_access_token};
 
 use clap::{Arg, App};
+use vkrs::adapters;
 use vkrs::auth::AccessToken;
 use vkrs::audio::{self, Audio};
 use vkrs::api::{self, Client, Collection};
@@ -15,7 +16,7 @@ fn print_m3u(songs: &[Audio]) {
     println!("#EXTM3U");
     for song in songs {
         println!("#EXTINF:{},{} - {} ({}_{})", song.duration, song.artist, song.title, song.owner_id, song.id);
-        println!("{}", song.url);
+        println!("{}", adapters::empty_link::as_str(&song.url));
     }
 }
 
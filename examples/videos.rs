@@ -7,6 +7,7 @@ mod helpers;
 use helpers::{fetch_access_token, get_access_token};
 
 use clap::{Arg, App};
+use vkrs::adapters;
 use vkrs::api::{self, Client, Collection};
 use vkrs::auth::AccessToken;
 use vkrs::video::{self, Video};
@@ -14,7 +15,7 @@ use vkrs::video::{self, Video};
 fn print_videos(videos: &[Video]) {
     for video in videos {
         println!("Title: {}", video.title);
-        println!("Player: {}", video.player);
+        println!("Player: {}", adapters::empty_link::as_str(&video.player));
         if let Some(ref files) = video.files {
             println!("Files: {:?}", files);
         }
@@ -4,9 +4,9 @@ use api::{Bool, Collection, Duration, Id, Profile, Timestamp};
 use users::{Sex, Status as Relation, User, UserOptionField};
 use serde_json::value::Value;
 use serde_json::ser::to_string as json_to_string;
-use serde::de;
+use serde::{de, ser};
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
 pub struct Counters {
     #[serde(default)]
     pub friends: usize,
@@ -28,19 +28,19 @@ pub struct Counters {
     pub sdk: usize,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Contacts {
     pub found: Vec<User>,
     pub other: Vec<Contact>,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Contact {
     pub contact: String,
     pub common_count: usize,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct PushSettings {
     pub disabled: Bool,
     pub disabled_until: Timestamp,
@@ -48,7 +48,7 @@ pub struct PushSettings {
 }
 
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Offer {
     pub id: String,
     pub title: String,
@@ -61,7 +61,7 @@ pub struct Offer {
     pub price: u32,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Info {
     pub country: String,
     pub https_required: Bool,
@@ -71,13 +71,13 @@ pub struct Info {
     pub lang: u16,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct ChangedToken {
     pub token: String,
     pub secret: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct ProfileInfo {
     pub first_name: String,
     pub last_name: String,
@@ -95,20 +95,20 @@ pub struct ProfileInfo {
     pub name_request: Option<NameChangeRequest>,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct ChangedProfileInfo {
     pub changed: Bool,
     pub name_request: Option<NameChangeRequest>,
 }
 
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct GeoInfo {
     pub id: Id,
     pub title: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct NameChangeRequest {
     id: Id,
     status: NameChangeStatus,
@@ -370,6 +370,12 @@ impl<'de> de::Deserialize<'de> for BirthdateVisibility {
     }
 }
 
+impl ser::Serialize for BirthdateVisibility {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u8(*self as u8)
+    }
+}
+
 impl AsRef<str> for BirthdateVisibility {
     fn as_ref(&self) -> &str {
         match *self {
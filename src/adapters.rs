@@ -0,0 +1,339 @@
+//! Serde adapters for values VK encodes as numbers and strings: flags, dates and links.
+//!
+//! By default `Flag`, `Time` and `Link` are raw `Bool`, `Timestamp` and `String` values as sent by VK.
+//! With `native-types` feature enabled they are `bool`, `chrono::DateTime<Utc>` and `url::Url` instead.
+//! Either way adapters read and write them in VK format (`0`/`1`, unix time and string),
+//! and the same format is used for request parameters of these types.
+
+#[cfg(not(feature = "native-types"))]
+use std::convert::TryFrom;
+use std::result::Result as StdResult;
+use serde::{de, ser};
+#[cfg(feature = "native-types")]
+use chrono::{DateTime, TimeZone, Utc};
+#[cfg(feature = "native-types")]
+use url::Url;
+#[cfg(not(feature = "native-types"))]
+use api::{Bool, Timestamp};
+
+#[cfg(not(feature = "native-types"))]
+pub type Flag = Bool;
+#[cfg(feature = "native-types")]
+pub type Flag = bool;
+
+#[cfg(not(feature = "native-types"))]
+pub type Time = Timestamp;
+#[cfg(feature = "native-types")]
+pub type Time = DateTime<Utc>;
+
+#[cfg(not(feature = "native-types"))]
+pub type Link = String;
+#[cfg(feature = "native-types")]
+pub type Link = Url;
+
+/// Link VK may send as empty string: `String` as is by default, `Option<Url>` with `native-types`
+#[cfg(not(feature = "native-types"))]
+pub type EmptyLink = String;
+#[cfg(feature = "native-types")]
+pub type EmptyLink = Option<Url>;
+
+/// `0` or `1`, also accepts `true` and `false`
+pub mod flag {
+    use super::*;
+
+    struct FlagVisitor;
+
+    impl<'de> de::Visitor<'de> for FlagVisitor {
+        type Value = Flag;
+        fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            f.write_str("0, 1 or boolean")
+        }
+        fn visit_bool<E: de::Error>(self, value: bool) -> StdResult<Flag, E> {
+            Ok(from_bool(value))
+        }
+        fn visit_u64<E: de::Error>(self, value: u64) -> StdResult<Flag, E> {
+            Ok(from_bool(value != 0))
+        }
+        fn visit_i64<E: de::Error>(self, value: i64) -> StdResult<Flag, E> {
+            Ok(from_bool(value != 0))
+        }
+    }
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> StdResult<Flag, D::Error> {
+        d.deserialize_any(FlagVisitor)
+    }
+
+    #[cfg(not(feature = "native-types"))]
+    pub fn serialize<S: ser::Serializer>(value: &Flag, s: S) -> StdResult<S::Ok, S::Error> {
+        s.serialize_u8(*value)
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn serialize<S: ser::Serializer>(value: &Flag, s: S) -> StdResult<S::Ok, S::Error> {
+        s.serialize_u8(*value as u8)
+    }
+
+    #[cfg(not(feature = "native-types"))]
+    pub fn from_bool(value: bool) -> Flag {
+        value as Flag
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn from_bool(value: bool) -> Flag {
+        value
+    }
+
+    #[cfg(not(feature = "native-types"))]
+    pub fn to_param(value: &Flag) -> String {
+        value.to_string()
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn to_param(value: &Flag) -> String {
+        (if *value { "1" } else { "0" }).to_owned()
+    }
+}
+
+/// Unix time in seconds
+pub mod time {
+    use super::*;
+
+    #[cfg(not(feature = "native-types"))]
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> StdResult<Time, D::Error> {
+        de::Deserialize::deserialize(d)
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> StdResult<Time, D::Error> {
+        let secs: i64 = de::Deserialize::deserialize(d)?;
+        from_secs(secs).ok_or_else(|| de::Error::invalid_value(de::Unexpected::Signed(secs), &"unix time"))
+    }
+
+    #[cfg(not(feature = "native-types"))]
+    pub fn serialize<S: ser::Serializer>(value: &Time, s: S) -> StdResult<S::Ok, S::Error> {
+        s.serialize_u64(*value)
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn serialize<S: ser::Serializer>(value: &Time, s: S) -> StdResult<S::Ok, S::Error> {
+        s.serialize_i64(value.timestamp())
+    }
+
+    #[cfg(not(feature = "native-types"))]
+    pub fn to_param(value: &Time) -> String {
+        value.to_string()
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn to_param(value: &Time) -> String {
+        value.timestamp().to_string()
+    }
+
+    /// Time from unix time in seconds, `None` if it is out of range
+    #[cfg(not(feature = "native-types"))]
+    pub fn from_secs(secs: i64) -> Option<Time> {
+        Time::try_from(secs).ok()
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn from_secs(secs: i64) -> Option<Time> {
+        Utc.timestamp_opt(secs, 0).single()
+    }
+
+    /// Unix time in seconds
+    #[cfg(not(feature = "native-types"))]
+    pub fn to_secs(value: &Time) -> i64 {
+        i64::try_from(*value).unwrap_or(i64::MAX)
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn to_secs(value: &Time) -> i64 {
+        value.timestamp()
+    }
+}
+
+/// Absolute URL string
+pub mod link {
+    use super::*;
+
+    #[cfg(not(feature = "native-types"))]
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> StdResult<Link, D::Error> {
+        de::Deserialize::deserialize(d)
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> StdResult<Link, D::Error> {
+        let url: String = de::Deserialize::deserialize(d)?;
+        Url::parse(&url).map_err(de::Error::custom)
+    }
+
+    pub fn serialize<S: ser::Serializer>(value: &Link, s: S) -> StdResult<S::Ok, S::Error> {
+        s.serialize_str(value.as_str())
+    }
+}
+
+/// Optional `flag`
+pub mod option_flag {
+    use super::*;
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> StdResult<Option<Flag>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrap(#[serde(with = "super::flag")] Flag);
+        de::Deserialize::deserialize(d).map(|value: Option<Wrap>| value.map(|Wrap(value)| value))
+    }
+
+    pub fn serialize<S: ser::Serializer>(value: &Option<Flag>, s: S) -> StdResult<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Wrap<'a>(#[serde(with = "super::flag")] &'a Flag);
+        match *value {
+            Some(ref value) => s.serialize_some(&Wrap(value)),
+            None => s.serialize_none(),
+        }
+    }
+}
+
+/// Optional `time`
+pub mod option_time {
+    use super::*;
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> StdResult<Option<Time>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrap(#[serde(with = "super::time")] Time);
+        de::Deserialize::deserialize(d).map(|value: Option<Wrap>| value.map(|Wrap(value)| value))
+    }
+
+    pub fn serialize<S: ser::Serializer>(value: &Option<Time>, s: S) -> StdResult<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Wrap<'a>(#[serde(with = "super::time")] &'a Time);
+        match *value {
+            Some(ref value) => s.serialize_some(&Wrap(value)),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn to_param(value: &Option<Time>) -> String {
+        value.as_ref().map(super::time::to_param).unwrap_or_default()
+    }
+}
+
+/// Optional `link`, empty string is read as `None`
+pub mod option_link {
+    use super::*;
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> StdResult<Option<Link>, D::Error> {
+        let value: Option<String> = de::Deserialize::deserialize(d)?;
+        match value {
+            Some(ref url) if !url.is_empty() => {
+                super::link::deserialize(de::IntoDeserializer::<D::Error>::into_deserializer(&**url)).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn serialize<S: ser::Serializer>(value: &Option<Link>, s: S) -> StdResult<S::Ok, S::Error> {
+        match *value {
+            Some(ref value) => s.serialize_some(value.as_str()),
+            None => s.serialize_none(),
+        }
+    }
+}
+
+/// `link` that can be empty, written back as empty string
+pub mod empty_link {
+    use super::*;
+
+    #[cfg(not(feature = "native-types"))]
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> StdResult<EmptyLink, D::Error> {
+        de::Deserialize::deserialize(d)
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(d: D) -> StdResult<EmptyLink, D::Error> {
+        super::option_link::deserialize(d)
+    }
+
+    pub fn serialize<S: ser::Serializer>(value: &EmptyLink, s: S) -> StdResult<S::Ok, S::Error> {
+        s.serialize_str(as_str(value))
+    }
+
+    #[cfg(not(feature = "native-types"))]
+    pub fn as_str(value: &EmptyLink) -> &str {
+        value
+    }
+
+    #[cfg(feature = "native-types")]
+    pub fn as_str(value: &EmptyLink) -> &str {
+        value.as_ref().map_or("", Url::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Wire {
+        #[serde(with = "flag")]
+        flag: Flag,
+        #[serde(with = "time")]
+        time: Time,
+        #[serde(with = "link")]
+        link: Link,
+        #[serde(default, with = "option_flag")]
+        option_flag: Option<Flag>,
+        #[serde(default, with = "option_time")]
+        option_time: Option<Time>,
+        #[serde(default, with = "option_link")]
+        option_link: Option<Link>,
+    }
+
+    const WIRE: &str = r#"{
+        "flag": 1,
+        "time": 1500000000,
+        "link": "https://vk.com/id1",
+        "option_flag": 0,
+        "option_time": 1400000000,
+        "option_link": "https://vk.com/club1"
+    }"#;
+
+    #[test]
+    fn wire_format_round_trip() {
+        let wire: Wire = serde_json::from_str(WIRE).unwrap();
+        let value = serde_json::to_value(&wire).unwrap();
+        assert_eq!(value, serde_json::from_str::<Value>(WIRE).unwrap());
+        assert_eq!(serde_json::from_value::<Wire>(value).unwrap(), wire);
+    }
+
+    #[test]
+    fn flag_accepts_booleans() {
+        let wire: Wire = serde_json::from_str(&WIRE.replace("\"flag\": 1", "\"flag\": true")).unwrap();
+        assert_eq!(wire.flag, flag::from_bool(true));
+        assert_eq!(serde_json::to_value(&wire).unwrap()["flag"], 1);
+    }
+
+    #[test]
+    fn empty_option_link_is_none() {
+        let wire: Wire = serde_json::from_str(&WIRE.replace("\"https://vk.com/club1\"", "\"\"")).unwrap();
+        assert_eq!(wire.option_link, None);
+        assert_eq!(serde_json::to_value(&wire).unwrap()["option_link"], Value::Null);
+    }
+
+    #[test]
+    fn missing_options_are_none() {
+        let wire: Wire = serde_json::from_str(r#"{"flag": 0, "time": 0, "link": "https://vk.com/"}"#).unwrap();
+        assert_eq!((wire.option_flag, wire.option_time, wire.option_link), (None, None, None));
+    }
+
+    #[test]
+    fn params_are_in_wire_format() {
+        let wire: Wire = serde_json::from_str(WIRE).unwrap();
+        assert_eq!(flag::to_param(&wire.flag), "1");
+        assert_eq!(time::to_param(&wire.time), "1500000000");
+        assert_eq!(option_time::to_param(&wire.option_time), "1400000000");
+        assert_eq!(option_time::to_param(&None), "");
+        assert_eq!(time::from_secs(1500000000), Some(wire.time));
+        assert_eq!(time::to_secs(&wire.time), 1500000000);
+        assert_eq!(time::from_secs(-1).is_some(), cfg!(feature = "native-types"));
+    }
+}
@@ -1,13 +1,18 @@
+use std::convert::TryFrom;
 use std::fmt;
+use std::marker::PhantomData;
 use std::error::Error as StdError;
 use std::result::Result as StdResult;
-use serde::de;
-use serde_json::{self, Error as JsonError};
+use std::str::FromStr;
+use serde::{de, ser};
+use serde_json::{self, Error as JsonError, Value};
 use url::{ParseError as UrlError, Url};
 use oauth2::token::Token;
 pub use reqwest::{Client as HttpClient, Error as HttpError};
 
 use auth::{AccessToken, OAuth, Permissions};
+use upload::{Form, UploadServerError};
+use users::User;
 
 pub const VK_DOMAIN: &'static str = "api.vk.com";
 pub const VK_PATH: &'static str = "method";
@@ -16,22 +21,76 @@ lazy_static! {
     static ref VK_BASE_API_URL: Url = Url::parse(&format!("https://{}/{}/", VK_DOMAIN, VK_PATH)).unwrap();
 }
 
-use audio::Audio;
+pub use groups::Group;
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Collection<T> {
     pub count: u32,
     pub items: Vec<T>
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RichCollection<T> where {
     pub count: u32,
     pub items: Vec<T>,
-    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub profiles: Vec<User>,
+    #[serde(default)]
     pub groups: Vec<Group>,
 }
 
+impl<T: Clone> Clone for RichCollection<T> {
+    fn clone(&self) -> RichCollection<T> {
+        RichCollection {
+            count: self.count,
+            items: self.items.clone(),
+            profiles: self.profiles.clone(),
+            groups: self.groups.clone(),
+        }
+    }
+}
+
+impl<T> RichCollection<T> {
+    /// Profile or community of `owner`
+    pub fn author<'a>(&'a self, owner: Owner) -> Option<Author<'a>> {
+        find_author(&self.profiles, &self.groups, owner)
+    }
+}
+
+impl<T: Authored> RichCollection<T> {
+    /// Items paired with their authors, `None` if author is missing from response
+    pub fn items_with_authors<'a>(&'a self) -> Vec<(&'a T, Option<Author<'a>>)> {
+        self.items.iter().map(|item| (item, self.author(item.author_id()))).collect()
+    }
+}
+
+/// Author of an object in extended response: user profile or community
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Author<'a> {
+    Profile(&'a User),
+    Group(&'a Group),
+}
+
+/// Object with author, which extended responses include in `profiles` or `groups`
+pub trait Authored {
+    /// `from_id` or `owner_id`
+    fn author_id(&self) -> Owner;
+}
+
+/// Find profile or community of `owner` in `profiles` and `groups` of extended response
+pub fn find_author<'a>(profiles: &'a [User], groups: &'a [Group], owner: Owner) -> Option<Author<'a>> {
+    match owner {
+        Owner::User(id) => profiles.iter().find(|user| user.id == id as i64).map(Author::Profile),
+        Owner::Group(id) => groups.iter().find(|group| group.id == id).map(Author::Group),
+    }
+}
+
+impl Authored for Comment {
+    fn author_id(&self) -> Owner {
+        self.from_id
+    }
+}
+
 impl<T: Clone> Clone for Collection<T> {
     fn clone(&self) -> Collection<T> {
         Collection {
@@ -41,61 +100,41 @@ impl<T: Clone> Clone for Collection<T> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct ApiError {
     pub error_code: ErrorCode,
     pub error_msg: String,
     pub request_params: Vec<KeyVal>
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct KeyVal {
     pub key: String,
     pub value: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Copy, Clone)]
 pub struct LikesCount {
     pub user_likes: u32,
     pub count: u32,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Profile {
-    id: i64,
-    first_name: String,
-    last_name: String,
-}
-
-// TODO: maybe move to groups?
-// TODO: must be much more fields
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
-pub struct Group {
-    id: i64,
-    name: String,
-    screen_name: String,
-    is_closed: Bool,
-    #[serde(default)]
-    is_admin: Bool,
-    #[serde(default)]
-    is_member: Bool,
-    #[serde(rename="type")]
-    kind: String,
-    photo_50: String, // URL
-    photo_100: String, // URL
-    photo_200: String, // URL
-    status_audio: Option<Audio>,
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Copy, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
 pub struct AlbumId {
     pub album_id: Id,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Comment {
     pub id: Id,
-    pub from_id: Id,
+    pub from_id: Owner,
     pub date: Timestamp,
     pub text: String,
     pub likes: Option<LikesCount>,
@@ -108,8 +147,104 @@ pub type Timestamp = u64;
 pub type Duration = u32;
 pub type Bool = u8;
 
+/// Owner of wall, photo, video or other object: user or community.
+///
+/// Converts from `OwnerId`, negative for communities, and back with `TryFrom`. Request setters take `Owner`
+/// rather than `OwnerId`, so that a community can't be passed as a user by a missing minus sign.
+#[derive(Copy, Eq, Clone, PartialEq, Hash, Debug)]
+pub enum Owner {
+    User(Id),
+    Group(Id),
+}
+
+impl Owner {
+    /// User or community id, always positive
+    pub fn id(&self) -> Id {
+        match *self {
+            Owner::User(id) | Owner::Group(id) => id,
+        }
+    }
+
+    pub fn is_group(&self) -> bool {
+        match *self {
+            Owner::User(_) => false,
+            Owner::Group(_) => true,
+        }
+    }
+}
+
+/// User with zero id, which VK treats as current user or missing owner, same as zero `OwnerId`
+impl Default for Owner {
+    fn default() -> Owner {
+        Owner::User(0)
+    }
+}
+
+impl From<OwnerId> for Owner {
+    fn from(owner_id: OwnerId) -> Owner {
+        if owner_id < 0 {
+            Owner::Group(owner_id.unsigned_abs())
+        } else {
+            Owner::User(owner_id as Id)
+        }
+    }
+}
+
+/// Fails with `OwnerIdOverflow` if id doesn't fit `OwnerId`: user id above `i64::MAX`
+/// or community id above `2^63`, which VK never returns, but `Owner` can still hold
+impl TryFrom<Owner> for OwnerId {
+    type Error = OwnerIdOverflow;
+    fn try_from(owner: Owner) -> StdResult<OwnerId, OwnerIdOverflow> {
+        match owner {
+            Owner::User(id) => OwnerId::try_from(id).map_err(|_| OwnerIdOverflow(owner)),
+            Owner::Group(id) => OwnerId::try_from(-i128::from(id)).map_err(|_| OwnerIdOverflow(owner)),
+        }
+    }
+}
+
+/// `Owner` with id out of `OwnerId` range
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct OwnerIdOverflow(pub Owner);
+
+impl fmt::Display for OwnerIdOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "owner id {} is out of range", self.0)
+    }
+}
+
+impl StdError for OwnerIdOverflow {}
+
+/// Written as signed id without conversion, so it never fails, even for out of range ids
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Owner::User(id) => write!(f, "{}", id),
+            Owner::Group(id) => write!(f, "-{}", id),
+        }
+    }
+}
+
+impl FromStr for Owner {
+    type Err = ParseRefError;
+    fn from_str(s: &str) -> StdResult<Owner, ParseRefError> {
+        s.parse::<OwnerId>().map(From::from).map_err(|_| ParseRefError)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Owner {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> StdResult<Owner, D::Error> {
+        OwnerId::deserialize(d).map(From::from)
+    }
+}
+
+impl ser::Serialize for Owner {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> StdResult<S::Ok, S::Error> {
+        OwnerId::try_from(*self).map_err(ser::Error::custom).and_then(|owner_id| s.serialize_i64(owner_id))
+    }
+}
+
 #[derive(Copy, Eq, Clone, PartialEq, Debug, Default)]
-pub struct FullId(pub OwnerId, pub Id);
+pub struct FullId(pub Owner, pub Id);
 
 impl fmt::Display for FullId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -117,18 +252,47 @@ impl fmt::Display for FullId {
     }
 }
 
+impl From<(Owner, Id)> for FullId {
+    fn from(pair: (Owner, Id)) -> FullId {
+        FullId(pair.0, pair.1)
+    }
+}
+
 impl From<(OwnerId, Id)> for FullId {
     fn from(pair: (OwnerId, Id)) -> FullId {
-        FullId(pair.0, pair.1)
+        FullId(pair.0.into(), pair.1)
     }
 }
 
-impl Into<(OwnerId, Id)> for FullId {
-    fn into(self) -> (OwnerId, Id) {
+impl Into<(Owner, Id)> for FullId {
+    fn into(self) -> (Owner, Id) {
         (self.0, self.1)
     }
 }
 
+impl FromStr for FullId {
+    type Err = ParseRefError;
+    fn from_str(s: &str) -> StdResult<FullId, ParseRefError> {
+        let mut parts = s.splitn(2, '_');
+        match (parts.next().map(str::parse), parts.next().map(str::parse)) {
+            (Some(Ok(owner_id)), Some(Ok(id))) => Ok(FullId(owner_id, id)),
+            _ => Err(ParseRefError),
+        }
+    }
+}
+
+/// Error parsing object reference like `FullId`, `Attachment` or `ObjectRef`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ParseRefError;
+
+impl fmt::Display for ParseRefError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid VK object reference")
+    }
+}
+
+impl StdError for ParseRefError {}
+
 pub struct Client {
     client: HttpClient,
 }
@@ -139,6 +303,9 @@ pub enum Error {
     Url(UrlError),
     Http(HttpError),
     Json(JsonError),
+    Upload(UploadServerError),
+    /// Long poll server failure code, which can't be recovered by refreshing server key
+    LongPoll(u32),
 }
 
 impl ::std::fmt::Display for Error {
@@ -148,6 +315,8 @@ impl ::std::fmt::Display for Error {
             Error::Http(ref err) => err.fmt(f),
             Error::Json(ref err) => err.fmt(f),
             Error::Url(ref err) => err.fmt(f),
+            Error::Upload(ref err) => err.fmt(f),
+            Error::LongPoll(code) => write!(f, "long poll server failed with code {}", code),
         }
     }
 }
@@ -170,6 +339,12 @@ impl From<JsonError> for Error {
     }
 }
 
+impl From<UploadServerError> for Error {
+    fn from(err: UploadServerError) -> Error {
+        Error::Upload(err)
+    }
+}
+
 impl From<UrlError> for Error {
     fn from(err: UrlError) -> Error {
         Error::Url(err)
@@ -206,6 +381,24 @@ impl Client {
             .and_then(|resp| serde_json::from_reader::<_, ApiResponse<T::Response>>(resp).map_err(Error::Json))
             .and_then(|resp| Into::<ApiResult<T::Response>>::into(resp).map_err(Error::Api))
     }
+
+    pub fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Post multipart form to upload server URL, as returned by one of `*.get*UploadServer` requests
+    pub fn upload<T: de::DeserializeOwned>(&self, url: &str, form: Form) -> Result<T> {
+        self.client
+            .post(url)
+            .multipart(form)
+            .send()
+            .map_err(Error::Http)
+            .and_then(|resp| serde_json::from_reader::<_, Value>(resp).map_err(Error::Json))
+            .and_then(|value| match value.get("error") {
+                Some(error) => Err(Error::Upload(error.into())),
+                None => serde_json::from_value(value).map_err(Error::Json),
+            })
+    }
 }
 
 /// Trait for things that can be posted to VK API directly
@@ -224,9 +417,138 @@ pub trait Request {
 
 }
 
+/// Response object with a fixed set of optional fields, like `users::UserOptionField` or `groups::GroupField`.
+///
+/// Unlike `users::User` or `groups::Group`, which have all optional fields as `Option`,
+/// an implementing type can declare fields it requests as required.
+///
+/// Nothing checks that `fields()` covers all required fields, and VK omits fields
+/// a user hasn't filled in, like `city`, even when they are requested.
+/// Only declare fields VK always returns as required, and keep the rest `Option`,
+/// or decoding will fail on real data:
+///
+/// ```rust,ignore
+/// #[derive(Deserialize)]
+/// struct Named {
+///     id: Id,
+///     first_name: String,
+///     last_name: String,
+///     domain: String,
+///     city: Option<users::City>,
+/// }
+///
+/// impl Fields for Named {
+///     type Field = UserOptionField;
+///     fn fields() -> &'static [UserOptionField] {
+///         &[UserOptionField::Domain, UserOptionField::City]
+///     }
+/// }
+///
+/// let users: Vec<Named> = api.call(None, &Typed::<_, Named>::new(users::Get::new().user_ids(&[1])))?;
+/// ```
+pub trait Fields: de::DeserializeOwned {
+    type Field: 'static;
+
+    /// Optional fields to request, all of them are expected in response
+    fn fields() -> &'static [Self::Field];
+}
+
+/// Request returning objects with optional `fields`, which can be typed with `Typed`
+pub trait FieldsRequest<T>: Request + Clone {
+    type Field: 'static;
+    /// Response with objects of type `T`
+    type Typed: de::DeserializeOwned;
+
+    fn set_fields(&mut self, fields: &'static [Self::Field]);
+}
+
+/// Request with `fields` set from `T::fields()`, returning objects of type `T`, see `Fields`
+#[derive(Debug, Clone)]
+pub struct Typed<R, T> {
+    request: R,
+    object: PhantomData<T>,
+}
+
+impl<R: FieldsRequest<T>, T: Fields<Field = R::Field>> Typed<R, T> {
+    pub fn new(request: &R) -> Typed<R, T> {
+        let mut request = request.clone();
+        request.set_fields(T::fields());
+        Typed {
+            request,
+            object: PhantomData,
+        }
+    }
+
+    pub fn request(&self) -> &R {
+        &self.request
+    }
+}
+
+impl<R: FieldsRequest<T>, T: Fields<Field = R::Field>> Request for Typed<R, T> {
+    type Response = R::Typed;
+
+    fn method_name() -> &'static str {
+        R::method_name()
+    }
+
+    fn to_query_string(&self) -> String {
+        self.request.to_query_string()
+    }
+
+    fn permissions() -> Permissions {
+        R::permissions()
+    }
+
+    fn to_url(&self) -> Url {
+        self.request.to_url()
+    }
+}
+
+/// Request with extended response, which can be sent in extended mode with `Extended`
+pub trait ExtendedRequest: Request + Clone {
+    /// Response with authors of returned objects in `profiles` and `groups`
+    type Extended: de::DeserializeOwned;
+}
+
+/// Request sent with `extended=1`, returning `R::Extended`, like `RichCollection` instead of `Collection`
+#[derive(Debug, Clone)]
+pub struct Extended<R> {
+    request: R,
+}
+
+impl<R: ExtendedRequest> Extended<R> {
+    pub fn new(request: &R) -> Extended<R> {
+        Extended { request: request.clone() }
+    }
+
+    pub fn request(&self) -> &R {
+        &self.request
+    }
+}
+
+impl<R: ExtendedRequest> Request for Extended<R> {
+    type Response = R::Extended;
+
+    fn method_name() -> &'static str {
+        R::method_name()
+    }
+
+    fn to_query_string(&self) -> String {
+        format!("{}&extended=1", self.request.to_query_string())
+    }
+
+    fn permissions() -> Permissions {
+        R::permissions()
+    }
+
+    fn to_url(&self) -> Url {
+        self.request.to_url()
+    }
+}
+
 type ApiResult<T> = StdResult<T, ApiError>;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ApiResponse<T> {
     Response(T),
@@ -420,6 +742,12 @@ impl<'de> de::Deserialize<'de> for ErrorCode {
     }
 }
 
+impl ser::Serialize for ErrorCode {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> StdResult<S::Ok, S::Error> {
+        s.serialize_u32((*self).into())
+    }
+}
+
 impl StdError for ApiError {
     fn description(&self) -> &str {
         &*self.error_msg
@@ -516,15 +844,313 @@ enum_str! { AttachmentKind {
     Document = "doc",
 }}
 
-#[derive(Eq, Copy, Clone, PartialEq, Debug)]
+#[derive(Eq, Clone, PartialEq, Debug)]
 pub struct Attachment {
     pub kind: AttachmentKind,
-    pub owner_id: OwnerId,
+    pub owner_id: Owner,
     pub media_id: Id,
+    pub access_key: Option<String>,
 }
 
 impl fmt::Display for Attachment {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}{}_{}", self.kind.as_ref(), self.owner_id, self.media_id)
+        write!(f, "{}{}_{}", self.kind.as_ref(), self.owner_id, self.media_id)?;
+        if let Some(ref access_key) = self.access_key {
+            write!(f, "_{}", access_key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `photo-1_2` and `photo-1_2_accesskey` forms, empty access key like in `photo-1_2_` is rejected
+impl FromStr for Attachment {
+    type Err = ParseRefError;
+    fn from_str(s: &str) -> StdResult<Attachment, ParseRefError> {
+        let split = s.find(|c: char| c == '-' || c.is_ascii_digit()).ok_or(ParseRefError)?;
+        let (kind, id) = s.split_at(split);
+        let mut parts = id.splitn(3, '_');
+        match (parts.next().map(str::parse), parts.next().map(str::parse), parts.next()) {
+            (Some(Ok(owner_id)), Some(Ok(media_id)), access_key) if access_key != Some("") => Ok(Attachment {
+                kind: kind.parse().map_err(|_| ParseRefError)?,
+                owner_id,
+                media_id,
+                access_key: access_key.map(ToOwned::to_owned),
+            }),
+            _ => Err(ParseRefError),
+        }
+    }
+}
+
+/// Reference to VK object, as found in vk.com links, see `ObjectRef::from_url()`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ObjectRef {
+    /// `id123`
+    User(Id),
+    /// `club123`, `public123` or `event123`
+    Group(Id),
+    /// Short name of user, community or application, use `utils::ResolveScreenName` to get its id
+    ScreenName(String),
+    /// `wall-1_2`
+    Wall(FullId),
+    /// `wall-1_2?reply=3`
+    WallComment(FullId, Id),
+    /// `photo-1_2`
+    Photo(FullId),
+    /// `video-1_2`
+    Video(FullId),
+    /// `audio-1_2`
+    Audio(FullId),
+    /// `doc-1_2`
+    Doc(FullId),
+    /// `album-1_2`, system albums have negative ids, as in `photos::Photo::album_id`
+    Album(Owner, i64),
+    /// `topic-1_2`
+    Topic(FullId),
+}
+
+impl ObjectRef {
+    /// Parse vk.com URL, with or without scheme, like `https://vk.com/wall-1_2`,
+    /// `m.vk.com/photo1_2` or `vk.com/feed?w=wall-1_2`
+    pub fn from_url(url: &str) -> StdResult<ObjectRef, ParseRefError> {
+        let url = if url.contains("://") {
+            Url::parse(url)
+        } else {
+            Url::parse(&format!("https://{}", url))
+        }.map_err(|_| ParseRefError)?;
+
+        match url.host_str() {
+            Some(host) if host == "vk.com" || host.ends_with(".vk.com") => (),
+            _ => return Err(ParseRefError),
+        }
+
+        // Objects opened in popup over other page, like `vk.com/feed?w=wall-1_2`
+        if let Some((_, popup)) = url.query_pairs().find(|(key, _)| key == "w" || key == "z") {
+            if let Ok(object) = popup.split('/').next().unwrap_or("").parse() {
+                return Ok(object);
+            }
+        }
+
+        let object: ObjectRef = url.path().trim_matches('/').parse()?;
+        match object {
+            ObjectRef::Wall(post) => match url.query_pairs().find(|(key, _)| key == "reply") {
+                Some((_, reply)) => reply.parse().map(|comment_id| ObjectRef::WallComment(post, comment_id)).map_err(|_| ParseRefError),
+                None => Ok(ObjectRef::Wall(post)),
+            },
+            object => Ok(object),
+        }
+    }
+
+    /// Id to use in `GetById`-like requests, like `wall::GetById` or `photos::GetById`
+    pub fn full_id(&self) -> Option<FullId> {
+        match *self {
+            ObjectRef::Wall(id) |
+            ObjectRef::WallComment(id, _) |
+            ObjectRef::Photo(id) |
+            ObjectRef::Video(id) |
+            ObjectRef::Audio(id) |
+            ObjectRef::Doc(id) |
+            ObjectRef::Topic(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Object as attachment for `wall::Post` or `messages::SendMessage`
+    pub fn attachment(&self) -> Option<Attachment> {
+        let (kind, id) = match *self {
+            ObjectRef::Photo(id) => (AttachmentKind::Photo, id),
+            ObjectRef::Video(id) => (AttachmentKind::Video, id),
+            ObjectRef::Audio(id) => (AttachmentKind::Audio, id),
+            ObjectRef::Doc(id) => (AttachmentKind::Document, id),
+            _ => return None,
+        };
+        Some(Attachment {
+            kind,
+            owner_id: id.0,
+            media_id: id.1,
+            access_key: None,
+        })
+    }
+}
+
+/// vk.com sections, which can't be screen names
+const RESERVED_PATHS: &[&str] = &["feed", "im", "settings", "friends", "groups", "photos", "albums", "video", "audio",
+                                  "audios", "docs", "apps", "games", "market", "fave", "search", "edit", "login", "mail",
+                                  "notifications", "support", "services", "bugs", "dev", "about", "blog", "jobs", "terms",
+                                  "privacy", "stickers", "gifts", "wall", "away", "write"];
+
+/// Whether object id follows type prefix, like in `wall-1_2` or `id1`, then object reference must be valid
+fn starts_with_id(s: &str) -> bool {
+    s.starts_with(|c: char| c == '-' || c.is_ascii_digit())
+}
+
+/// Parses path part of vk.com URL, like `wall-1_2`, `id123` or `durov`.
+///
+/// vk.com sections like `feed` or `im` and malformed object references like `wall1_` are rejected.
+impl FromStr for ObjectRef {
+    type Err = ParseRefError;
+    fn from_str(s: &str) -> StdResult<ObjectRef, ParseRefError> {
+        let is_screen_name = !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        if !is_screen_name && !s.contains('-') {
+            return Err(ParseRefError);
+        }
+
+        for &(prefix, kind) in &[("wall", ObjectRef::Wall as fn(FullId) -> ObjectRef),
+                                 ("photo", ObjectRef::Photo),
+                                 ("video", ObjectRef::Video),
+                                 ("audio", ObjectRef::Audio),
+                                 ("doc", ObjectRef::Doc),
+                                 ("topic", ObjectRef::Topic)] {
+            if let Some(id) = s.strip_prefix(prefix).filter(|id| starts_with_id(id)) {
+                return id.parse().map(kind);
+            }
+        }
+
+        if let Some(album) = s.strip_prefix("album").filter(|id| starts_with_id(id)) {
+            let mut parts = album.splitn(2, '_');
+            return match (parts.next().map(str::parse), parts.next()) {
+                (Some(Ok(owner_id)), Some(album_id)) => {
+                    // Profile, wall and saved photos albums
+                    let album_id = match album_id {
+                        "0" => Ok(-6),
+                        "00" => Ok(-7),
+                        "000" => Ok(-15),
+                        _ => album_id.parse().map_err(|_| ParseRefError),
+                    }?;
+                    Ok(ObjectRef::Album(owner_id, album_id))
+                }
+                _ => Err(ParseRefError),
+            };
+        }
+
+        if let Some(id) = s.strip_prefix("id").filter(|id| starts_with_id(id)) {
+            return id.parse().map(ObjectRef::User).map_err(|_| ParseRefError);
+        }
+        for prefix in &["club", "public", "event"] {
+            if let Some(id) = s.strip_prefix(prefix).filter(|id| starts_with_id(id)) {
+                return id.parse().map(ObjectRef::Group).map_err(|_| ParseRefError);
+            }
+        }
+
+        if is_screen_name && !RESERVED_PATHS.contains(&s) {
+            Ok(ObjectRef::ScreenName(s.to_owned()))
+        } else {
+            Err(ParseRefError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use super::*;
+    use wall;
+
+    const COMMENTS: &str = r#"{
+        "count": 4,
+        "items": [
+            {"id": 1, "from_id": 5, "date": 1500000000, "text": "user"},
+            {"id": 2, "from_id": -7, "date": 1500000001, "text": "community"},
+            {"id": 3, "from_id": -8, "date": 1500000002, "text": "missing community"},
+            {"id": 4, "from_id": 0, "date": 1500000003, "text": "no author"}
+        ],
+        "profiles": [{"id": 5, "first_name": "Pavel", "last_name": "Durov", "screen_name": "durov"}],
+        "groups": [{"id": 7, "name": "VK API", "screen_name": "apiclub", "is_closed": 0, "type": "group"}]
+    }"#;
+
+    #[test]
+    fn items_with_authors() {
+        let comments: RichCollection<Comment> = serde_json::from_str(COMMENTS).unwrap();
+        let authors: Vec<Option<Owner>> = comments.items_with_authors().into_iter()
+            .map(|(_, author)| author.map(|author| match author {
+                Author::Profile(user) => Owner::User(user.id as Id),
+                Author::Group(group) => Owner::Group(group.id),
+            }))
+            .collect();
+        assert_eq!(authors, [Some(Owner::User(5)), Some(Owner::Group(7)), None, None]);
+
+        match comments.author(Owner::User(5)) {
+            Some(Author::Profile(user)) => assert_eq!(user.screen_name.as_deref(), Some("durov")),
+            other => panic!("unexpected author {:?}", other),
+        }
+        assert_eq!(find_author(&comments.profiles, &comments.groups, Owner::Group(5)), None);
+        assert_eq!(find_author(&[], &[], Owner::User(0)), None);
+    }
+
+    #[test]
+    fn extended_request() {
+        let request = Extended::new(wall::Get::new().owner_id(Owner::Group(1)));
+        assert_eq!(Extended::<wall::Get>::method_name(), "wall.get");
+        assert!(request.to_query_string().ends_with("&extended=1"));
+        assert!(!request.request().to_query_string().contains("extended"));
+    }
+
+    #[test]
+    fn owner_id_bounds() {
+        assert_eq!(Owner::from(0), Owner::User(0));
+        assert_eq!(Owner::from(i64::MAX), Owner::User(i64::MAX as Id));
+        assert_eq!(Owner::from(i64::MIN), Owner::Group(1 << 63));
+        assert_eq!(OwnerId::try_from(Owner::User(i64::MAX as Id)), Ok(i64::MAX));
+        assert_eq!(OwnerId::try_from(Owner::Group(1 << 63)), Ok(i64::MIN));
+        assert_eq!(OwnerId::try_from(Owner::Group(1)), Ok(-1));
+
+        let overflow = [Owner::User(1 << 63), Owner::User(Id::MAX), Owner::Group((1 << 63) + 1), Owner::Group(Id::MAX)];
+        for &owner in &overflow {
+            assert_eq!(OwnerId::try_from(owner), Err(OwnerIdOverflow(owner)));
+            assert!(serde_json::to_string(&owner).is_err());
+        }
+        assert_eq!(Owner::Group(Id::MAX).to_string(), "-18446744073709551615");
+        assert_eq!(serde_json::to_string(&Owner::Group(1 << 63)).unwrap(), "-9223372036854775808");
+        assert_eq!("-9223372036854775808".parse(), Ok(Owner::Group(1 << 63)));
+    }
+
+    #[test]
+    fn parse_full_id() {
+        assert_eq!("-1_2".parse(), Ok(FullId(Owner::Group(1), 2)));
+        assert_eq!("1_2".parse(), Ok(FullId(Owner::User(1), 2)));
+        for malformed in &["", "1", "1_", "_2", "a_2", "1_-2", "1_99999999999999999999999"] {
+            assert_eq!(malformed.parse::<FullId>(), Err(ParseRefError), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn parse_attachment() {
+        assert_eq!("photo-1_2".parse(), Ok(Attachment {
+            kind: AttachmentKind::Photo,
+            owner_id: Owner::Group(1),
+            media_id: 2,
+            access_key: None,
+        }));
+        let doc: Attachment = "doc1_2_abc".parse().unwrap();
+        assert_eq!((doc.kind, doc.owner_id, doc.access_key.as_deref()), (AttachmentKind::Document, Owner::User(1), Some("abc")));
+        assert_eq!(doc.to_string(), "doc1_2_abc");
+        for malformed in &["", "photo", "photo-", "photo1_", "doc1_2_", "1_2", "story1_2", "photo1_99999999999999999999999"] {
+            assert_eq!(malformed.parse::<Attachment>(), Err(ParseRefError), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn parse_object_ref() {
+        assert_eq!("wall-1_2".parse(), Ok(ObjectRef::Wall(FullId(Owner::Group(1), 2))));
+        assert_eq!("album1_000".parse(), Ok(ObjectRef::Album(Owner::User(1), -15)));
+        assert_eq!("id1".parse(), Ok(ObjectRef::User(1)));
+        assert_eq!("public1".parse(), Ok(ObjectRef::Group(1)));
+        assert_eq!("durov".parse(), Ok(ObjectRef::ScreenName("durov".to_owned())));
+        assert_eq!("identity".parse(), Ok(ObjectRef::ScreenName("identity".to_owned())));
+        for malformed in &["", "photo-", "wall1_", "album1", "id99999999999999999999", "club-1", "feed", "im", "settings", "a/b"] {
+            assert_eq!(malformed.parse::<ObjectRef>(), Err(ParseRefError), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn object_ref_from_url() {
+        assert_eq!(ObjectRef::from_url("https://vk.com/wall-1_2"), Ok(ObjectRef::Wall(FullId(Owner::Group(1), 2))));
+        assert_eq!(ObjectRef::from_url("m.vk.com/photo1_2"), Ok(ObjectRef::Photo(FullId(Owner::User(1), 2))));
+        assert_eq!(ObjectRef::from_url("vk.com/wall1_2?reply=3"), Ok(ObjectRef::WallComment(FullId(Owner::User(1), 2), 3)));
+        assert_eq!(ObjectRef::from_url("vk.com/feed?w=wall-1_2"), Ok(ObjectRef::Wall(FullId(Owner::Group(1), 2))));
+        assert_eq!(ObjectRef::from_url("https://vk.com/durov/"), Ok(ObjectRef::ScreenName("durov".to_owned())));
+        for malformed in &["https://vk.com/feed", "vk.com/im?sel=1", "vk.com/settings", "vk.com/wall1_2?reply=x",
+                           "https://example.com/wall1_2", "vk.com.example.com/id1", "https://vk.com/"] {
+            assert_eq!(ObjectRef::from_url(malformed), Err(ParseRefError), "{}", malformed);
+        }
     }
 }
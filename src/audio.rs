@@ -1,49 +1,66 @@
 use std::borrow::Borrow;
 use std::convert::AsRef;
 use std::string::ToString;
-use serde::de;
-use api::{AlbumId, Bool, Collection, Duration, FullId, Group, Id, OwnerId, Sort, Timestamp};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use serde::{de, ser};
+use adapters::{self, EmptyLink, Flag, Time};
+use api::{self, AlbumId, Bool, Client, Collection, Duration, FullId, Group, Id, Owner, OwnerId, Sort};
+use auth::AccessToken;
+use upload::{Form, Progress, UploadError, UploadFile, UploadResult};
 use users::User;
 use std::fmt;
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Audio {
     pub id: Id,
-    pub owner_id: OwnerId,
+    pub owner_id: Owner,
     pub artist: String,
     pub title: String,
-    pub date: Timestamp,
-    pub url: String, // Url !!!
+    #[serde(default, with = "adapters::time")]
+    pub date: Time,
+    /// Empty for audios not available in current region
+    #[serde(default, with = "adapters::empty_link")]
+    pub url: EmptyLink,
     pub lyrics_id: Option<Id>,
     pub album_id: Option<Id>,
     pub genre_id: Option<Genre>,
     pub duration: Duration,
-    #[serde(default)]
-    pub no_search: Bool, // FIXME: bool
+    #[serde(default, with = "adapters::flag")]
+    pub no_search: Flag,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Lyrics {
     pub lyrics_id: Id,
     pub text: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Album {
     pub id: Id,
-    pub owner_id: OwnerId,
+    pub owner_id: Owner,
     pub title: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct UploadUrl {
     pub upload_url: String,
 }
 
+/// Upload server response for audio file uploaded to `UploadUrl::upload_url`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct UploadedAudio {
+    pub server: Id,
+    pub audio: String,
+    pub hash: String,
+}
+
 request! {
     #[derive(Eq)]
     struct Get for ["audio.get"](v => 5.37, need_user => 0) -> Collection<Audio> [Audio] {
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         album_id: Option<Id> = () => { |value| value.as_ref().map(ToString::to_string).as_ref().map(Borrow::borrow).unwrap_or("") },
         audio_ids: Vec<Id> = () => { Vec },
         offset: usize = (0) => {},
@@ -75,7 +92,7 @@ request! {
 
 request_ref! {
     #[derive(Eq, Copy)]
-    struct Save for ["audio.save"](v => 5.44) -> Vec<Audio> [Audio] {
+    struct Save for ["audio.save"](v => 5.44) -> Audio [Audio] {
         sized {
             server: Id = () => {},
         }
@@ -92,7 +109,7 @@ request! {
     #[derive(Eq, Copy)]
     struct Add for ["audio.add"](v => 5.44) -> Id [Audio] {
         audio_id: Id = () => {},
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         group_id: Option<Id> = () => {Option},
         album_id: Option<Id> = () => {Option},
     }
@@ -102,14 +119,14 @@ request! {
     #[derive(Eq, Copy)]
     struct Delete for ["audio.delete"](v => 5.44) -> Bool [Audio] {
         audio_id: Id = () => {},
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
     }
 }
 
 request_ref! {
     struct Edit for ["audio.edit"](v => 5.44) -> Id [Audio] {
         sized {
-            owner_id: OwnerId = () => {},
+            owner_id: Owner = () => {},
             audio_id: Id = () => {},
             genre_id: Option<Genre> = () => {Option},
             no_search: bool = () => {bool},
@@ -126,7 +143,7 @@ request_ref! {
 request! {
     struct Reorder for ["audio.reorder"](v => 5.44) -> Bool [Audio] {
         audio_id: Id = () => {},
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         before: Option<Id> = () => {Option},
         after: Option<Id> = () => {Option},
     }
@@ -135,7 +152,7 @@ request! {
 request! {
     struct Restore for ["audio.restore"](v => 5.44) -> Audio [Audio] {
         audio_id: Id = () => {},
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
     }
 }
 
@@ -156,14 +173,14 @@ request! {
 request! {
     #[derive(Copy, Eq)]
     struct GetCount for ["audio.getCount"](v => 5.44) -> u64 [Audio] {
-        owner_id: OwnerId = () => {}
+        owner_id: Owner = () => {}
     }
 }
 
 request! {
     #[derive(Copy, Eq)]
     struct GetAlbums for ["audio.getAlbums"](v => 5.44) -> Collection<Album> [Audio] {
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         offset: usize = (0) => {},
         count: usize = (30) => {},
     }
@@ -385,8 +402,375 @@ impl Into<u32> for Genre {
     }
 }
 
+impl ser::Serialize for Genre {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u32((*self).into())
+    }
+}
+
 enum_str! { Filter {
     All = "all",
     Friends = "friends",
     Groups = "groups",
 }}
+
+/// Artist and title, as found in MP3 file ID3 tags
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Tags {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+}
+
+impl Tags {
+    /// Read ID3v2 tags, falling back to ID3v1, from the file starting at reader's current position,
+    /// and rewind the reader back to that position
+    pub fn read<R: Read + Seek>(reader: &mut R) -> io::Result<Tags> {
+        let start = reader.stream_position()?;
+        let mut tags = read_id3v2(reader, start)?;
+        if tags.artist.is_none() || tags.title.is_none() {
+            let fallback = read_id3v1(reader, start)?;
+            tags.artist = tags.artist.or(fallback.artist);
+            tags.title = tags.title.or(fallback.title);
+        }
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(tags)
+    }
+}
+
+/// Artist and title frames longer than this are skipped as bogus
+const MAX_ID3_TEXT_FRAME_SIZE: u64 = 64 * 1024;
+
+/// Read ID3v2 tag at the start of the file, which begins at `start` offset of the stream
+fn read_id3v2<R: Read + Seek>(reader: &mut R, start: u64) -> io::Result<Tags> {
+    let mut tags = Tags::default();
+
+    let mut header = [0u8; 10];
+    match reader.read_exact(&mut header) {
+        Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(tags),
+        result => result?,
+    }
+    if &header[..3] != b"ID3" {
+        return Ok(tags);
+    }
+
+    // Declared tag size can be up to 256 MB, don't trust it beyond the end of stream
+    let len = reader.seek(SeekFrom::End(0))?.saturating_sub(start);
+    reader.seek(SeekFrom::Start(start + 10))?;
+
+    let version = header[3];
+    let end = (10 + syncsafe(&header[6..10])).min(len);
+    let mut pos = 10;
+
+    // Extended header
+    if header[5] & 0x40 != 0 && version > 2 {
+        let mut size = [0u8; 4];
+        reader.read_exact(&mut size)?;
+        pos += if version == 3 { 4 + big_endian(&size) } else { syncsafe(&size) };
+        reader.seek(SeekFrom::Start(start + pos))?;
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut frame = [0u8; 10];
+    while pos + header_len <= end && (tags.artist.is_none() || tags.title.is_none()) {
+        reader.read_exact(&mut frame[..header_len as usize])?;
+        if frame[0] == 0 {
+            break;
+        }
+
+        let size = match version {
+            2 => big_endian(&frame[3..6]),
+            3 => big_endian(&frame[4..8]),
+            _ => syncsafe(&frame[4..8]),
+        };
+        pos += header_len + size;
+        if pos > end {
+            break;
+        }
+
+        let slot = match &frame[..id_len] {
+            b"TPE1" | b"TP1" if size <= MAX_ID3_TEXT_FRAME_SIZE => &mut tags.artist,
+            b"TIT2" | b"TT2" if size <= MAX_ID3_TEXT_FRAME_SIZE => &mut tags.title,
+            _ => {
+                reader.seek(SeekFrom::Start(start + pos))?;
+                continue;
+            }
+        };
+
+        let mut data = vec![0; size as usize];
+        reader.read_exact(&mut data)?;
+        *slot = decode_id3_text(&data);
+    }
+
+    Ok(tags)
+}
+
+/// Read ID3v1 tag at the end of the file, which begins at `start` offset of the stream
+fn read_id3v1<R: Read + Seek>(reader: &mut R, start: u64) -> io::Result<Tags> {
+    let mut tag = [0u8; 128];
+    if reader.seek(SeekFrom::End(0))?.saturating_sub(start) < tag.len() as u64 {
+        return Ok(Tags::default());
+    }
+
+    reader.seek(SeekFrom::End(-(tag.len() as i64)))?;
+    reader.read_exact(&mut tag)?;
+    if &tag[..3] != b"TAG" {
+        return Ok(Tags::default());
+    }
+
+    Ok(Tags {
+        title: non_empty(tag[3..33].iter().map(|&c| c as char).collect()),
+        artist: non_empty(tag[33..63].iter().map(|&c| c as char).collect()),
+    })
+}
+
+fn decode_id3_text(data: &[u8]) -> Option<String> {
+    let (encoding, text) = data.split_first()?;
+    let text = match *encoding {
+        0 => text.iter().map(|&c| c as char).collect(),
+        1 | 2 => {
+            let (is_be, text) = match text {
+                [0xfe, 0xff, rest @ ..] => (true, rest),
+                [0xff, 0xfe, rest @ ..] => (false, rest),
+                _ => (*encoding == 2, text),
+            };
+            let units = text.chunks(2)
+                .filter(|pair| pair.len() == 2)
+                .map(|pair| if is_be { u16::from_be_bytes([pair[0], pair[1]]) } else { u16::from_le_bytes([pair[0], pair[1]]) })
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).into_owned(),
+    };
+    non_empty(text)
+}
+
+fn non_empty(text: String) -> Option<String> {
+    let text = text.split('\0').next().unwrap_or("").trim();
+    if text.is_empty() { None } else { Some(text.to_owned()) }
+}
+
+fn big_endian(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, &b| acc << 8 | u64::from(b))
+}
+
+fn syncsafe(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, &b| acc << 7 | u64::from(b & 0x7f))
+}
+
+/// Audio upload pipeline: `audio.getUploadServer`, upload, `audio.save`
+/// and optional `audio.moveToAlbum`.
+///
+/// Artist and title are prefilled from file ID3 tags.
+#[derive(Debug)]
+pub struct Upload {
+    file: Option<UploadFile>,
+    tags: Tags,
+    group_id: Option<Id>,
+    album_id: Option<Id>,
+    saved: Option<Audio>,
+    progress: Progress,
+}
+
+impl Upload {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Upload> {
+        let tags = Tags::read(&mut File::open(path.as_ref())?)?;
+        Ok(Upload::with_tags(UploadFile::open(path)?, tags))
+    }
+
+    pub fn from_reader<N: Into<String>, R: Read + Seek + Send + 'static>(name: N, mut reader: R) -> io::Result<Upload> {
+        let tags = Tags::read(&mut reader)?;
+        Ok(Upload::with_tags(UploadFile::from_reader(name, reader)?, tags))
+    }
+
+    fn with_tags(file: UploadFile, tags: Tags) -> Upload {
+        Upload {
+            file: Some(file),
+            tags,
+            group_id: None,
+            album_id: None,
+            saved: None,
+            progress: Progress::new(),
+        }
+    }
+
+    pub fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    pub fn artist<T: Into<String>>(&mut self, value: T) -> &mut Self {
+        self.tags.artist = Some(value.into());
+        self
+    }
+
+    pub fn title<T: Into<String>>(&mut self, value: T) -> &mut Self {
+        self.tags.title = Some(value.into());
+        self
+    }
+
+    pub fn group_id(&mut self, value: Option<Id>) -> &mut Self {
+        self.group_id = value;
+        self
+    }
+
+    /// Move saved audio to this album
+    pub fn album_id(&mut self, value: Option<Id>) -> &mut Self {
+        self.album_id = value;
+        self
+    }
+
+    pub fn progress(&mut self, value: Progress) -> &mut Self {
+        self.progress = value;
+        self
+    }
+
+    /// Audio saved by previous `upload()` call which failed to move it to album
+    pub fn saved(&self) -> Option<&Audio> {
+        self.saved.as_ref()
+    }
+
+    /// Run the whole pipeline, the file is kept for another call until it is sent.
+    ///
+    /// If audio is saved, but can't be moved to album, it is kept,
+    /// and calling `upload()` again only retries the move.
+    pub fn upload(&mut self, api: &Client, token: Option<&AccessToken>) -> UploadResult<Audio> {
+        self.progress.begin();
+        if self.saved.is_none() {
+            if self.file.is_none() {
+                return Err(UploadError::AlreadySent);
+            }
+            let server = api.call(token, &GetUploadServer).map_err(UploadError::GetServer)?;
+
+            let file = self.file.take().ok_or(UploadError::AlreadySent)?;
+            let uploaded: UploadedAudio = self.progress.send(api, &server.upload_url, Form::new().part("file", self.progress.part(file)))?;
+
+            let audio = api.call(token,
+                      Save::new()
+                          .server(uploaded.server)
+                          .audio(&uploaded.audio)
+                          .hash(&uploaded.hash)
+                          .artist(self.tags.artist.as_deref().unwrap_or(""))
+                          .title(self.tags.title.as_deref().unwrap_or("")))
+                .map_err(UploadError::Save)?;
+            self.saved = Some(audio);
+        }
+
+        let group_id = self.group_id;
+        self.move_saved(|album_id, audio| {
+            api.call(token, MoveToAlbum::new().group_id(group_id).album_id(album_id).audio_ids(&[audio.id])).map(|_| ())
+        })
+    }
+
+    /// Move saved audio to album, if set, taking it only when the move succeeds
+    fn move_saved<F: FnOnce(Id, &Audio) -> api::Result<()>>(&mut self, move_to_album: F) -> UploadResult<Audio> {
+        if let (Some(album_id), Some(audio)) = (self.album_id, self.saved.as_ref()) {
+            move_to_album(album_id, audio).map_err(UploadError::Move)?;
+        }
+        self.saved.take().ok_or(UploadError::AlreadySent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use serde_json;
+    use url::ParseError as UrlError;
+    use super::*;
+
+    fn id3v2(tag_size: u32, frames: &[(&[u8; 4], u32, &[u8])]) -> Cursor<Vec<u8>> {
+        let syncsafe = |size: u32| [(size >> 21) as u8 & 0x7f, (size >> 14) as u8 & 0x7f, (size >> 7) as u8 & 0x7f, size as u8 & 0x7f];
+        let mut data = b"ID3\x03\x00\x00".to_vec();
+        data.extend_from_slice(&syncsafe(tag_size));
+        for &(id, size, body) in frames {
+            data.extend_from_slice(id);
+            data.extend_from_slice(&size.to_be_bytes());
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(body);
+        }
+        Cursor::new(data)
+    }
+
+    #[test]
+    fn id3v2_text_frames() {
+        let mut file = id3v2(100, &[(b"TPE1", 7, b"\x00Artist"), (b"TIT2", 6, b"\x00Title")]);
+        let tags = Tags::read(&mut file).unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.title.as_deref(), Some("Title"));
+    }
+
+    #[test]
+    fn oversized_id3v2_frames_are_skipped() {
+        let huge = 0x0fff_ffff;
+        let mut file = id3v2(huge, &[(b"TIT2", huge - 10, b"\x00Title")]);
+        assert_eq!(Tags::read(&mut file).unwrap().title, None);
+
+        let body = vec![0u8; MAX_ID3_TEXT_FRAME_SIZE as usize + 1];
+        let mut file = id3v2(huge, &[(b"TIT2", body.len() as u32, &body), (b"TPE1", 7, b"\x00Artist")]);
+        let tags = Tags::read(&mut file).unwrap();
+        assert_eq!((tags.artist.as_deref(), tags.title), (Some("Artist"), None));
+    }
+
+    #[test]
+    fn tags_are_read_from_current_position() {
+        let mut data = b"ID3 of another file".to_vec();
+        data.extend(id3v2(100, &[(b"TPE1", 7, b"\x00Artist"), (b"TIT2", 6, b"\x00Title")]).into_inner());
+        let mut file = Cursor::new(data);
+        file.set_position(19);
+
+        let tags = Tags::read(&mut file).unwrap();
+        assert_eq!((tags.artist.as_deref(), tags.title.as_deref()), (Some("Artist"), Some("Title")));
+        assert_eq!(file.position(), 19);
+    }
+
+    #[test]
+    fn saved_audio_is_kept_when_move_fails() {
+        let mut upload = Upload::from_reader("track.mp3", Cursor::new(b"not an mp3".to_vec())).unwrap();
+        upload.album_id(Some(7));
+        upload.file = None;
+        upload.saved = Some(serde_json::from_str(AUDIO).unwrap());
+
+        match upload.move_saved(|_, _| Err(api::Error::Url(UrlError::EmptyHost))) {
+            Err(UploadError::Move(api::Error::Url(UrlError::EmptyHost))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(upload.saved().map(|audio| audio.id), Some(456239017));
+
+        let moved = upload.move_saved(|album_id, audio| {
+            assert_eq!((album_id, audio.id), (7, 456239017));
+            Ok(())
+        });
+        assert_eq!(moved.unwrap().id, 456239017);
+        assert_eq!(upload.saved(), None);
+        match upload.move_saved(|_, _| Ok(())) {
+            Err(UploadError::AlreadySent) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    const AUDIO: &str = r#"{
+        "id": 456239017,
+        "owner_id": -1,
+        "artist": "Artist",
+        "title": "Title",
+        "duration": 215,
+        "date": 1500000000,
+        "url": "",
+        "album_id": 7,
+        "genre_id": 18,
+        "no_search": 1
+    }"#;
+
+    #[test]
+    fn audio_round_trip() {
+        let audio: Audio = serde_json::from_str(AUDIO).unwrap();
+        assert_eq!(audio.owner_id, Owner::Group(1));
+        assert_eq!(adapters::empty_link::as_str(&audio.url), "");
+
+        let value = serde_json::to_value(&audio).unwrap();
+        assert_eq!(value["owner_id"], -1);
+        assert_eq!(value["url"], "");
+        assert_eq!(value["date"], 1500000000);
+        assert_eq!(value["no_search"], 1);
+        assert_eq!(value["genre_id"], 18);
+        assert_eq!(serde_json::from_value::<Audio>(value).unwrap(), audio);
+    }
+}
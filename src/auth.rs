@@ -270,6 +270,12 @@ impl<'de> de::Deserialize<'de> for Permissions {
     }
 }
 
+impl ser::Serialize for Permissions {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_i32(self.0)
+    }
+}
+
 impl FromStr for Permissions {
     type Err = ();
     fn from_str(s: &str) -> Result<Permissions, ()> {
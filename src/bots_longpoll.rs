@@ -0,0 +1,429 @@
+//! Bots Long Poll API client, see <https://vk.com/dev/bots_longpoll>
+
+use std::thread;
+use std::time::Duration;
+use serde_json::{self, Value};
+use url::Url;
+use adapters::{self, Flag, Time};
+use api::{Client, Error, Id, Owner, Result};
+use audio::Audio;
+use auth::AccessToken;
+use longpoll::{PollServer, Poller};
+use messages::Message;
+use photos::Photo;
+use video::Video;
+use wall::{Attachment, WallPost};
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct LongPollServer {
+    pub key: String,
+    pub server: String, // URL
+    pub ts: String,
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetLongPollServer for ["groups.getLongPollServer"](v => 5.92) -> LongPollServer {
+        group_id: Id = () => {},
+    }
+}
+
+/// New incoming message, with client features if bot is subscribed to them
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct MessageNew {
+    pub message: Message,
+    pub client_info: Option<ClientInfo>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ClientInfo {
+    #[serde(default)]
+    pub button_actions: Vec<String>,
+    #[serde(default)]
+    pub keyboard: bool,
+    #[serde(default)]
+    pub inline_keyboard: bool,
+    #[serde(default)]
+    pub carousel: bool,
+    #[serde(default)]
+    pub lang_id: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct MessageAllow {
+    pub user_id: Id,
+    #[serde(default)]
+    pub key: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct MessageDeny {
+    pub user_id: Id,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct MessageTypingState {
+    pub state: String,
+    pub from_id: Owner,
+    pub to_id: Owner,
+}
+
+/// New, edited or restored comment to photo, video or wall post
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct CommentEvent {
+    pub id: Id,
+    pub from_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    pub text: String,
+    pub reply_to_user: Option<Owner>,
+    pub reply_to_comment: Option<Id>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    pub photo_id: Option<Id>,
+    pub photo_owner_id: Option<Owner>,
+    pub video_id: Option<Id>,
+    pub video_owner_id: Option<Owner>,
+    pub post_id: Option<Id>,
+    pub post_owner_id: Option<Owner>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct CommentDelete {
+    pub owner_id: Owner,
+    pub id: Id,
+    pub deleter_id: Id,
+    pub user_id: Option<Owner>,
+    pub photo_id: Option<Id>,
+    pub video_id: Option<Id>,
+    pub post_id: Option<Id>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct GroupJoin {
+    pub user_id: Id,
+    pub join_type: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct GroupLeave {
+    pub user_id: Id,
+    /// Whether user left by themselves or was removed by admin
+    #[serde(rename="self", default, with = "adapters::flag")]
+    pub self_: Flag,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct UserBlock {
+    pub admin_id: Id,
+    pub user_id: Id,
+    /// Zero unix time if user is blocked forever
+    #[serde(default, with = "adapters::time")]
+    pub unblock_date: Time,
+    #[serde(default)]
+    pub reason: u32,
+    #[serde(default)]
+    pub comment: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct UserUnblock {
+    pub admin_id: Id,
+    pub user_id: Id,
+    /// Whether block expired rather than was lifted by admin
+    #[serde(default, with = "adapters::flag")]
+    pub by_end_date: Flag,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct PollVote {
+    pub owner_id: Owner,
+    pub poll_id: Id,
+    pub option_id: Id,
+    pub user_id: Id,
+}
+
+/// Community event, as received from Bots Long Poll API or Callback API
+#[derive(Debug, PartialEq, Clone)]
+pub enum GroupEvent {
+    MessageNew(MessageNew),
+    MessageReply(Message),
+    MessageEdit(Message),
+    MessageAllow(MessageAllow),
+    MessageDeny(MessageDeny),
+    MessageTypingState(MessageTypingState),
+    PhotoNew(Photo),
+    PhotoCommentNew(CommentEvent),
+    PhotoCommentEdit(CommentEvent),
+    PhotoCommentRestore(CommentEvent),
+    PhotoCommentDelete(CommentDelete),
+    AudioNew(Audio),
+    VideoNew(Box<Video>),
+    VideoCommentNew(CommentEvent),
+    VideoCommentEdit(CommentEvent),
+    VideoCommentRestore(CommentEvent),
+    VideoCommentDelete(CommentDelete),
+    WallPostNew(WallPost),
+    WallRepost(WallPost),
+    WallReplyNew(CommentEvent),
+    WallReplyEdit(CommentEvent),
+    WallReplyRestore(CommentEvent),
+    WallReplyDelete(CommentDelete),
+    GroupJoin(GroupJoin),
+    GroupLeave(GroupLeave),
+    UserBlock(UserBlock),
+    UserUnblock(UserUnblock),
+    PollVoteNew(PollVote),
+    /// Any other event, or event which failed to parse, in its raw form
+    Unknown { kind: String, object: Value },
+}
+
+impl GroupEvent {
+    /// Build event from its `type` and `object` fields
+    pub fn from_object(kind: &str, object: Value) -> serde_json::Result<GroupEvent> {
+        GroupEvent::parse(kind, &object).map(|event| event.unwrap_or_else(|| GroupEvent::Unknown { kind: kind.to_owned(), object }))
+    }
+
+    /// Parse event of known `type` from its `object` without taking it, `None` for unknown types
+    fn parse(kind: &str, object: &Value) -> serde_json::Result<Option<GroupEvent>> {
+        use self::GroupEvent::*;
+        use serde::Deserialize;
+        fn from<'de, T: Deserialize<'de>>(object: &'de Value) -> serde_json::Result<T> {
+            T::deserialize(object)
+        }
+
+        Ok(Some(match kind {
+            // Before API version 5.103 incoming message object was sent without `client_info` wrapper
+            "message_new" if object.get("message").is_none() => MessageNew(self::MessageNew { message: from(object)?, client_info: None }),
+            "message_new" => MessageNew(from(object)?),
+            "message_reply" => MessageReply(from(object)?),
+            "message_edit" => MessageEdit(from(object)?),
+            "message_allow" => MessageAllow(from(object)?),
+            "message_deny" => MessageDeny(from(object)?),
+            "message_typing_state" => MessageTypingState(from(object)?),
+            "photo_new" => PhotoNew(from(object)?),
+            "photo_comment_new" => PhotoCommentNew(from(object)?),
+            "photo_comment_edit" => PhotoCommentEdit(from(object)?),
+            "photo_comment_restore" => PhotoCommentRestore(from(object)?),
+            "photo_comment_delete" => PhotoCommentDelete(from(object)?),
+            "audio_new" => AudioNew(from(object)?),
+            "video_new" => VideoNew(Box::new(from(object)?)),
+            "video_comment_new" => VideoCommentNew(from(object)?),
+            "video_comment_edit" => VideoCommentEdit(from(object)?),
+            "video_comment_restore" => VideoCommentRestore(from(object)?),
+            "video_comment_delete" => VideoCommentDelete(from(object)?),
+            "wall_post_new" => WallPostNew(from(object)?),
+            "wall_repost" => WallRepost(from(object)?),
+            "wall_reply_new" => WallReplyNew(from(object)?),
+            "wall_reply_edit" => WallReplyEdit(from(object)?),
+            "wall_reply_restore" => WallReplyRestore(from(object)?),
+            "wall_reply_delete" => WallReplyDelete(from(object)?),
+            "group_join" => GroupJoin(from(object)?),
+            "group_leave" => GroupLeave(from(object)?),
+            "user_block" => UserBlock(from(object)?),
+            "user_unblock" => UserUnblock(from(object)?),
+            "poll_vote_new" => PollVoteNew(from(object)?),
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// Raw update, as sent by VK, with event payload not parsed yet
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Update {
+    #[serde(rename="type")]
+    pub kind: String,
+    #[serde(default)]
+    pub object: Value,
+    pub group_id: Id,
+    pub event_id: Option<String>,
+    /// Secret key, sent by Callback API only
+    pub secret: Option<String>,
+}
+
+impl Update {
+    pub fn event(&self) -> Result<GroupEvent> {
+        GroupEvent::parse(&self.kind, &self.object)
+            .map(|event| event.unwrap_or_else(|| GroupEvent::Unknown { kind: self.kind.clone(), object: self.object.clone() }))
+            .map_err(Error::Json)
+    }
+
+    pub fn into_event(self) -> Result<GroupEvent> {
+        GroupEvent::from_object(&self.kind, self.object).map_err(Error::Json)
+    }
+
+    /// Parsed event, or `GroupEvent::Unknown` if it doesn't match its known type,
+    /// so that one malformed event can't stop processing of others
+    pub fn into_event_or_unknown(self) -> GroupEvent {
+        match GroupEvent::parse(&self.kind, &self.object) {
+            Ok(Some(event)) => event,
+            _ => GroupEvent::Unknown { kind: self.kind, object: self.object },
+        }
+    }
+}
+
+impl PollServer for LongPollServer {
+    type Ts = String;
+
+    fn ts(&self) -> &String {
+        &self.ts
+    }
+
+    fn ts_mut(&mut self) -> &mut String {
+        &mut self.ts
+    }
+
+    fn into_ts(self) -> String {
+        self.ts
+    }
+}
+
+/// Bots Long Poll client.
+///
+/// Keeps server key and `ts` up to date, and can be used as an endless iterator of events.
+pub struct LongPoll<'a> {
+    poller: Poller<'a, GetLongPollServer, Update>,
+}
+
+impl<'a> LongPoll<'a> {
+    pub fn new(api: &'a Client, token: Option<&'a AccessToken>, group_id: Id) -> LongPoll<'a> {
+        LongPoll {
+            poller: Poller::new(api, token, *GetLongPollServer::new().group_id(group_id)),
+        }
+    }
+
+    /// Seconds to wait for events in one request, capped at `MAX_WAIT`
+    pub fn wait(&mut self, value: u32) -> &mut Self {
+        self.poller.set_wait(value);
+        self
+    }
+
+    /// Last seen events position, can be used to resume listening with `set_ts()`
+    pub fn ts(&self) -> Option<&str> {
+        self.poller.ts().map(|ts| &**ts)
+    }
+
+    /// Continue from given events position, fetching server key if needed
+    pub fn set_ts<T: Into<String>>(&mut self, ts: T) -> Result<()> {
+        self.poller.set_ts(ts.into())
+    }
+
+    /// Make one long poll request, returning received updates, possibly none
+    pub fn poll(&mut self) -> Result<Vec<Update>> {
+        self.poller.poll(poll_url)
+    }
+
+    /// Poll events forever, passing them to `handler` until it returns `false`.
+    ///
+    /// Network errors are retried with growing delay, keeping events position,
+    /// other errors stop the loop. Events which fail to parse are passed as `GroupEvent::Unknown`.
+    pub fn run<F: FnMut(GroupEvent) -> bool>(&mut self, mut handler: F) -> Result<()> {
+        let mut delay = 0;
+        loop {
+            let updates = match self.poll() {
+                Ok(updates) => updates,
+                Err(Error::Http(_)) => {
+                    delay = if delay == 0 { 1 } else { (delay * 2).min(60) };
+                    thread::sleep(Duration::from_secs(delay));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            delay = 0;
+
+            for update in updates {
+                if !handler(update.into_event_or_unknown()) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn poll_url(server: &LongPollServer, wait: u32) -> Result<Url> {
+    let mut url = Url::parse(&server.server)?;
+    url.query_pairs_mut()
+        .append_pair("act", "a_check")
+        .append_pair("key", &server.key)
+        .append_pair("ts", &server.ts)
+        .append_pair("wait", &wait.to_string());
+    Ok(url)
+}
+
+/// Endless stream of events, request errors are yielded as is, so caller can decide to stop or retry.
+///
+/// Events that fail to parse are yielded as `GroupEvent::Unknown`, same as in `run()`.
+impl<'a> Iterator for LongPoll<'a> {
+    type Item = Result<GroupEvent>;
+
+    fn next(&mut self) -> Option<Result<GroupEvent>> {
+        self.poller.next(poll_url).map(|update| update.map(Update::into_event_or_unknown))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use super::*;
+
+    #[test]
+    fn malformed_event_is_unknown() {
+        let update: Update = serde_json::from_str(r#"{"type": "group_join", "object": {"user_id": "x"}, "group_id": 1}"#).unwrap();
+        assert!(update.event().is_err());
+        match update.into_event_or_unknown() {
+            GroupEvent::Unknown { ref kind, ref object } => {
+                assert_eq!(kind, "group_join");
+                assert_eq!(object["user_id"], "x");
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        let update: Update =
+            serde_json::from_str(r#"{"type": "group_join", "object": {"user_id": 2, "join_type": "join"}, "group_id": 1}"#).unwrap();
+        assert_eq!(update.into_event_or_unknown(),
+                   GroupEvent::GroupJoin(GroupJoin { user_id: 2, join_type: "join".to_owned() }));
+    }
+
+    #[test]
+    fn typed_event_fields() {
+        let comment = r#"{"id": 5, "from_id": -3, "date": 1500000000, "text": "hi", "reply_to_user": 4, "post_id": 6, "post_owner_id": -3}"#;
+        match GroupEvent::from_object("wall_reply_new", serde_json::from_str(comment).unwrap()).unwrap() {
+            GroupEvent::WallReplyNew(comment) => {
+                assert_eq!(comment.from_id, Owner::Group(3));
+                assert_eq!(adapters::time::to_param(&comment.date), "1500000000");
+                assert_eq!((comment.reply_to_user, comment.post_owner_id), (Some(Owner::User(4)), Some(Owner::Group(3))));
+                assert_eq!(serde_json::to_value(&comment).unwrap()["from_id"], -3);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        match GroupEvent::from_object("group_leave", serde_json::from_str(r#"{"user_id": 2, "self": 1}"#).unwrap()).unwrap() {
+            GroupEvent::GroupLeave(leave) => assert_eq!(leave.self_, adapters::flag::from_bool(true)),
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        match GroupEvent::from_object("user_block", serde_json::from_str(r#"{"admin_id": 1, "user_id": 2}"#).unwrap()).unwrap() {
+            GroupEvent::UserBlock(block) => assert_eq!(adapters::time::to_param(&block.unblock_date), "0"),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn iterator_passes_malformed_events() {
+        let api = Client::new();
+        let mut long_poll = LongPoll::new(&api, None, 1);
+        for object in &[r#"{"user_id": "x"}"#, r#"{"user_id": 2, "join_type": "join"}"#] {
+            let update = format!(r#"{{"type": "group_join", "object": {}, "group_id": 1}}"#, object);
+            long_poll.poller.updates.push_back(serde_json::from_str(&update).unwrap());
+        }
+
+        match long_poll.next() {
+            Some(Ok(GroupEvent::Unknown { ref kind, .. })) => assert_eq!(kind, "group_join"),
+            other => panic!("unexpected event {:?}", other),
+        }
+        match long_poll.next() {
+            Some(Ok(GroupEvent::GroupJoin(ref join))) => assert_eq!(join.user_id, 2),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Callback API receiver, see <https://vk.com/dev/callback_api>
+//!
+//! `Callback` does not depend on any web framework: pass it request body,
+//! and send back `reply()` for parsed request as plain text response.
+//! Small built-in HTTP listener is available with `Callback::serve()`.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::result::Result as StdResult;
+use std::time::Duration;
+use serde_json::{self, Error as JsonError};
+use api::Id;
+use bots_longpoll::{GroupEvent, Update};
+
+/// Reply VK expects for every received event
+pub const OK: &str = "ok";
+
+/// Max request body size accepted by built-in listener
+pub const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Max size of request line or one header line accepted by built-in listener
+pub const MAX_HEADER_LINE_SIZE: usize = 8 * 1024;
+
+/// Max total size of request line and headers accepted by built-in listener
+pub const MAX_HEADERS_SIZE: usize = 64 * 1024;
+
+/// Read and write timeout for connections accepted by built-in listener, in seconds
+pub const IO_TIMEOUT: u64 = 5;
+
+/// Parsed callback request
+#[derive(Debug, PartialEq, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum Incoming {
+    /// Server address confirmation, must be answered with confirmation code
+    Confirmation { group_id: Id },
+    Event { group_id: Id, event_id: Option<String>, event: GroupEvent },
+}
+
+#[derive(Debug)]
+pub enum CallbackError {
+    /// Body is not a valid callback request
+    Json(JsonError),
+    /// Secret key is missing or doesn't match configured one
+    WrongSecret,
+    /// Event is sent for other community
+    WrongGroup(Id),
+}
+
+impl fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CallbackError::Json(ref err) => write!(f, "invalid callback request: {}", err),
+            CallbackError::WrongSecret => f.write_str("wrong callback secret key"),
+            CallbackError::WrongGroup(group_id) => write!(f, "unexpected callback group id {}", group_id),
+        }
+    }
+}
+
+impl StdError for CallbackError {}
+
+impl From<JsonError> for CallbackError {
+    fn from(err: JsonError) -> CallbackError {
+        CallbackError::Json(err)
+    }
+}
+
+pub type CallbackResult<T> = StdResult<T, CallbackError>;
+
+/// Callback API server settings
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Callback {
+    confirmation: String,
+    secret: Option<String>,
+    group_id: Option<Id>,
+}
+
+impl Callback {
+    /// Create callback receiver replying with `confirmation` code to confirmation requests,
+    /// as returned by `groups.getCallbackConfirmationCode`
+    pub fn new<T: Into<String>>(confirmation: T) -> Callback {
+        Callback {
+            confirmation: confirmation.into(),
+            secret: None,
+            group_id: None,
+        }
+    }
+
+    /// Require every request to have this secret key
+    pub fn secret<T: Into<String>>(&mut self, value: T) -> &mut Self {
+        self.secret = Some(value.into());
+        self
+    }
+
+    /// Accept requests for this community only
+    pub fn group_id(&mut self, value: Id) -> &mut Self {
+        self.group_id = Some(value);
+        self
+    }
+
+    /// Parse and check request body.
+    ///
+    /// Event which doesn't match its known type is returned as `GroupEvent::Unknown`,
+    /// as replying with an error makes VK resend it and eventually disable the server.
+    pub fn parse(&self, body: &[u8]) -> CallbackResult<Incoming> {
+        let update: Update = serde_json::from_slice(body)?;
+
+        if let Some(ref secret) = self.secret {
+            if update.secret.as_ref() != Some(secret) {
+                return Err(CallbackError::WrongSecret);
+            }
+        }
+
+        if let Some(group_id) = self.group_id {
+            if update.group_id != group_id {
+                return Err(CallbackError::WrongGroup(update.group_id));
+            }
+        }
+
+        if update.kind == "confirmation" {
+            return Ok(Incoming::Confirmation { group_id: update.group_id });
+        }
+
+        Ok(Incoming::Event {
+            group_id: update.group_id,
+            event_id: update.event_id.clone(),
+            event: update.into_event_or_unknown(),
+        })
+    }
+
+    /// Response body to send back to VK
+    pub fn reply(&self, incoming: &Incoming) -> &str {
+        match *incoming {
+            Incoming::Confirmation { .. } => &self.confirmation,
+            Incoming::Event { .. } => OK,
+        }
+    }
+
+    /// Parse request body, returning response body along with received event, if any
+    pub fn handle(&self, body: &[u8]) -> CallbackResult<(&str, Option<GroupEvent>)> {
+        let incoming = self.parse(body)?;
+        let reply = self.reply(&incoming);
+        match incoming {
+            Incoming::Confirmation { .. } => Ok((reply, None)),
+            Incoming::Event { event, .. } => Ok((reply, Some(event))),
+        }
+    }
+
+    /// Serve one HTTP request from `stream`, passing received event to `handler`
+    pub fn handle_http<S, F>(&self, stream: S, handler: F) -> io::Result<()>
+        where S: Read + Write,
+              F: FnOnce(GroupEvent)
+    {
+        let mut stream = BufReader::new(stream);
+        let body = read_http_body(&mut stream)?;
+        let stream = stream.get_mut();
+
+        match body {
+            Some(body) => match self.handle(&body) {
+                Ok((reply, event)) => {
+                    write_http_response(stream, "200 OK", reply)?;
+                    if let Some(event) = event {
+                        handler(event);
+                    }
+                    Ok(())
+                }
+                Err(CallbackError::Json(err)) => write_http_response(stream, "400 Bad Request", &err.to_string()),
+                Err(err) => write_http_response(stream, "403 Forbidden", &err.to_string()),
+            },
+            None => write_http_response(stream, "405 Method Not Allowed", ""),
+        }
+    }
+
+    /// Listen for VK requests on `addr` forever, passing received events to `handler`.
+    ///
+    /// Requests are served one by one, so `handler` should not block for too long,
+    /// as VK expects reply within a few seconds. Connections which stall for more than
+    /// `IO_TIMEOUT` seconds are dropped.
+    pub fn serve<A, F>(&self, addr: A, mut handler: F) -> io::Result<()>
+        where A: ToSocketAddrs,
+              F: FnMut(GroupEvent)
+    {
+        let listener = TcpListener::bind(addr)?;
+        for stream in listener.incoming() {
+            // Broken or stalled connection must not stop the server
+            let _ = stream.and_then(|stream| {
+                stream.set_read_timeout(Some(Duration::from_secs(IO_TIMEOUT)))?;
+                stream.set_write_timeout(Some(Duration::from_secs(IO_TIMEOUT)))?;
+                self.handle_http(stream, &mut handler)
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Read HTTP request, returning its body for POST requests
+fn read_http_body<R: BufRead>(stream: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut line = String::new();
+    let mut headers_left = MAX_HEADERS_SIZE;
+    read_header_line(stream, &mut line, &mut headers_left)?;
+    let is_post = line.starts_with("POST ");
+
+    let mut len = 0;
+    loop {
+        line.clear();
+        if read_header_line(stream, &mut line, &mut headers_left)? == 0 || line.trim().is_empty() {
+            break;
+        }
+
+        let mut header = line.splitn(2, ':');
+        if let (Some(name), Some(value)) = (header.next(), header.next()) {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                len = value.trim().parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid content length"))?;
+            }
+        }
+    }
+
+    if !is_post {
+        return Ok(None);
+    }
+    if len > MAX_BODY_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request body is too large"));
+    }
+
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// Read one line of request head, failing if it is longer than `MAX_HEADER_LINE_SIZE`
+/// or the head so far exceeds `MAX_HEADERS_SIZE`
+fn read_header_line<R: BufRead>(stream: &mut R, line: &mut String, headers_left: &mut usize) -> io::Result<usize> {
+    let limit = MAX_HEADER_LINE_SIZE.min(*headers_left);
+    let read = stream.take(limit as u64).read_line(line)?;
+    if read == limit && !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request headers are too large"));
+    }
+    *headers_left -= read;
+    Ok(read)
+}
+
+fn write_http_response<W: Write>(stream: &mut W, status: &str, body: &str) -> io::Result<()> {
+    write!(stream,
+           "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status,
+           body.len(),
+           body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    /// Connection with VK sending `request` and reading reply into `response`
+    struct FakeSender {
+        request: Cursor<Vec<u8>>,
+        response: Vec<u8>,
+    }
+
+    impl Read for FakeSender {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.request.read(buf)
+        }
+    }
+
+    impl Write for FakeSender {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.response.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn send(callback: &Callback, method: &str, body: &str) -> (String, Vec<GroupEvent>) {
+        let mut sender = FakeSender {
+            request: Cursor::new(format!("{} /callback HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+                                         method, body.len(), body).into_bytes()),
+            response: Vec::new(),
+        };
+        let mut events = Vec::new();
+        callback.handle_http(&mut sender, |event| events.push(event)).unwrap();
+        (String::from_utf8(sender.response).unwrap(), events)
+    }
+
+    fn callback() -> Callback {
+        let mut callback = Callback::new("c0de");
+        callback.secret("s3cret").group_id(1);
+        callback
+    }
+
+    #[test]
+    fn confirmation_is_answered_with_code() {
+        let (response, events) = send(&callback(), "POST", r#"{"type": "confirmation", "group_id": 1, "secret": "s3cret"}"#);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nc0de"));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn event_is_passed_to_handler() {
+        let (response, events) = send(&callback(), "POST", r#"{
+            "type": "group_join", "object": {"user_id": 2, "join_type": "join"},
+            "group_id": 1, "event_id": "abc", "secret": "s3cret"
+        }"#);
+        assert!(response.ends_with("\r\n\r\nok"));
+        assert_eq!(events, [GroupEvent::GroupJoin(::bots_longpoll::GroupJoin { user_id: 2, join_type: "join".to_owned() })]);
+    }
+
+    #[test]
+    fn malformed_event_is_acknowledged() {
+        let (response, events) = send(&callback(), "POST", r#"{
+            "type": "group_join", "object": {"user_id": "x"}, "group_id": 1, "secret": "s3cret"
+        }"#);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nok"));
+        match events[..] {
+            [GroupEvent::Unknown { ref kind, .. }] => assert_eq!(kind, "group_join"),
+            ref other => panic!("unexpected events {:?}", other),
+        }
+    }
+
+    #[test]
+    fn foreign_requests_are_rejected() {
+        let (response, events) = send(&callback(), "POST", r#"{"type": "group_join", "object": {}, "group_id": 1, "secret": "guess"}"#);
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(events.is_empty());
+
+        let (response, _) = send(&callback(), "POST", r#"{"type": "group_join", "object": {}, "group_id": 2, "secret": "s3cret"}"#);
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+
+        let (response, _) = send(&callback(), "POST", "not json");
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+        let (response, _) = send(&callback(), "GET", "");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected() {
+        let read = |head: String| read_http_body(&mut Cursor::new(head.into_bytes()));
+
+        let long_line = format!("POST / HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "a".repeat(MAX_HEADER_LINE_SIZE));
+        assert_eq!(read(long_line).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let many_lines = format!("POST / HTTP/1.1\r\n{}\r\n", "X-Padding: a\r\n".repeat(MAX_HEADERS_SIZE / 10));
+        assert_eq!(read(many_lines).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let fits = format!("POST / HTTP/1.1\r\n{}Content-Length: 2\r\n\r\n{{}}", "X-Padding: a\r\n".repeat(100));
+        assert_eq!(read(fits).unwrap(), Some(b"{}".to_vec()));
+    }
+}
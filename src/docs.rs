@@ -0,0 +1,140 @@
+use std::borrow::Borrow;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+use api::{Attachment, AttachmentKind, Client, Id};
+use auth::AccessToken;
+use upload::{Form, Progress, UploadError, UploadFile, UploadResult};
+pub use wall::Doc;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct UploadUrl {
+    pub upload_url: String,
+}
+
+/// Upload server response for document uploaded to `UploadUrl::upload_url`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct UploadedDoc {
+    pub file: String,
+}
+
+impl<'a> From<&'a Doc> for Attachment {
+    fn from(doc: &'a Doc) -> Attachment {
+        Attachment {
+            kind: AttachmentKind::Document,
+            owner_id: doc.owner_id,
+            media_id: doc.id,
+            access_key: doc.access_key.clone(),
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetUploadServer for ["docs.getUploadServer"](v => 5.45) -> UploadUrl [Docs] {
+        group_id: Option<Id> = () => {Option},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetWallUploadServer for ["docs.getWallUploadServer"](v => 5.45) -> UploadUrl [Docs] {
+        group_id: Option<Id> = () => {Option},
+    }
+}
+
+request_ref! {
+    #[derive(Eq, Copy)]
+    struct Save for ["docs.save"](v => 5.45) -> Vec<Doc> [Docs] {
+        sized {}
+        unsized {
+            file: str = ("") => {=},
+            title: str = ("") => {=},
+            tags: str = ("") => {=},
+        }
+    }
+}
+
+/// Document upload pipeline: `docs.getUploadServer` or `docs.getWallUploadServer`,
+/// upload and `docs.save`.
+#[derive(Debug)]
+pub struct Upload {
+    file: Option<UploadFile>,
+    group_id: Option<Id>,
+    wall: bool,
+    title: String,
+    tags: Vec<String>,
+    progress: Progress,
+}
+
+impl Upload {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Upload> {
+        UploadFile::open(path).map(Upload::with_file)
+    }
+
+    pub fn from_reader<N: Into<String>, R: Read + Seek + Send + 'static>(name: N, reader: R) -> io::Result<Upload> {
+        UploadFile::from_reader(name, reader).map(Upload::with_file)
+    }
+
+    fn with_file(file: UploadFile) -> Upload {
+        Upload {
+            file: Some(file),
+            group_id: None,
+            wall: false,
+            title: String::new(),
+            tags: Vec::new(),
+            progress: Progress::new(),
+        }
+    }
+
+    pub fn group_id(&mut self, value: Option<Id>) -> &mut Self {
+        self.group_id = value;
+        self
+    }
+
+    /// Upload document to be attached to a wall post rather than to user or community documents
+    pub fn wall(&mut self, value: bool) -> &mut Self {
+        self.wall = value;
+        self
+    }
+
+    /// Document title, file name if empty
+    pub fn title<T: Into<String>>(&mut self, value: T) -> &mut Self {
+        self.title = value.into();
+        self
+    }
+
+    pub fn tag<T: Into<String>>(&mut self, value: T) -> &mut Self {
+        self.tags.push(value.into());
+        self
+    }
+
+    pub fn progress(&mut self, value: Progress) -> &mut Self {
+        self.progress = value;
+        self
+    }
+
+    /// Run the whole pipeline, the file is kept for another call until it is sent
+    pub fn upload(&mut self, api: &Client, token: Option<&AccessToken>) -> UploadResult<Doc> {
+        self.progress.begin();
+        if self.file.is_none() {
+            return Err(UploadError::AlreadySent);
+        }
+        let server = if self.wall {
+            api.call(token, GetWallUploadServer::new().group_id(self.group_id))
+        } else {
+            api.call(token, GetUploadServer::new().group_id(self.group_id))
+        }.map_err(UploadError::GetServer)?;
+
+        let file = self.file.take().ok_or(UploadError::AlreadySent)?;
+        let uploaded: UploadedDoc = self.progress.send(api, &server.upload_url, Form::new().part("file", self.progress.part(file)))?;
+
+        let docs = api.call(token,
+                  Save::new()
+                      .file(&uploaded.file)
+                      .title(&self.title)
+                      .tags(&self.tags.join(",")))
+            .map_err(UploadError::Save)?;
+
+        docs.into_iter().next().ok_or(UploadError::NotSaved)
+    }
+}
@@ -0,0 +1,357 @@
+use std::borrow::Borrow;
+use serde::{de, ser};
+use adapters::{self, Flag};
+use api::{Bool, Client, Collection, Id, Result};
+use auth::AccessToken;
+use users::{NameCase, User, UserOptionField};
+
+/// Max number of `target_uids` in one `friends.getMutual` request
+pub const MAX_MUTUAL_TARGETS: usize = 100;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct MutualFriends {
+    pub id: Id,
+    pub common_friends: Vec<Id>,
+    pub common_count: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct FriendRequest {
+    pub user_id: Id,
+    pub message: Option<String>,
+    pub mutual: Option<MutualCount>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct MutualCount {
+    pub count: u32,
+    #[serde(default)]
+    pub users: Vec<Id>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct DeleteResult {
+    #[serde(with = "adapters::flag")]
+    pub success: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub friend_deleted: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub out_request_deleted: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub in_request_deleted: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub suggestion_deleted: Flag,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct FriendList {
+    pub id: Id,
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct ListId {
+    pub list_id: Id,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Friendship {
+    pub user_id: Id,
+    pub friend_status: FriendStatus,
+    pub sign: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FriendStatus {
+    NotFriend, // 0
+    RequestSent, // 1
+    RequestReceived, // 2
+    Friend, // 3
+}
+
+impl<'de> de::Deserialize<'de> for FriendStatus {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> ::std::result::Result<FriendStatus, D::Error> {
+        use self::FriendStatus::*;
+        de::Deserialize::deserialize(d).and_then(|v: u32| {
+            match v {
+                0 => Ok(NotFriend),
+                1 => Ok(RequestSent),
+                2 => Ok(RequestReceived),
+                3 => Ok(Friend),
+                _ => Err(de::Error::invalid_value(de::Unexpected::Unsigned(v as u64), &"friend status from 0 to 3")),
+            }
+        })
+    }
+}
+
+impl ser::Serialize for FriendStatus {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error> {
+        use self::FriendStatus::*;
+        s.serialize_u32(match *self {
+            NotFriend => 0,
+            RequestSent => 1,
+            RequestReceived => 2,
+            Friend => 3,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddStatus {
+    RequestSent, // 1
+    RequestApproved, // 2
+    RequestResent, // 4
+}
+
+impl<'de> de::Deserialize<'de> for AddStatus {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> ::std::result::Result<AddStatus, D::Error> {
+        use self::AddStatus::*;
+        de::Deserialize::deserialize(d).and_then(|v: u32| {
+            match v {
+                1 => Ok(RequestSent),
+                2 => Ok(RequestApproved),
+                4 => Ok(RequestResent),
+                _ => Err(de::Error::invalid_value(de::Unexpected::Unsigned(v as u64), &"friend add status 1, 2 or 4")),
+            }
+        })
+    }
+}
+
+impl ser::Serialize for AddStatus {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error> {
+        use self::AddStatus::*;
+        s.serialize_u32(match *self {
+            RequestSent => 1,
+            RequestApproved => 2,
+            RequestResent => 4,
+        })
+    }
+}
+
+request_ref! {
+    /// Friends are returned as user objects only if `fields` is not empty, so it defaults to `domain`,
+    /// use `GetIds` to get bare ids.
+    struct Get for ["friends.get"](v => 5.44) -> Collection<User> [Friends] {
+        sized {
+            user_id: Option<Id> = () => {Option},
+            order: Option<Order> = () => {AsRef<Option>},
+            list_id: Option<Id> = () => {Option},
+            offset: usize = (0) => {},
+            count: Option<usize> = () => {Option},
+            name_case: NameCase = (NameCase::Nominative) => {AsRef},
+        }
+        unsized {
+            fields: [UserOptionField] = (&[UserOptionField::Domain][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetIds for ["friends.get"](v => 5.44) -> Collection<Id> [Friends] {
+        user_id: Option<Id> = () => {Option},
+        order: Option<Order> = () => {AsRef<Option>},
+        list_id: Option<Id> = () => {Option},
+        offset: usize = (0) => {},
+        count: Option<usize> = () => {Option},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetOnline for ["friends.getOnline"](v => 5.44) -> Vec<Id> [Friends] {
+        user_id: Option<Id> = () => {Option},
+        list_id: Option<Id> = () => {Option},
+        order: Option<Order> = () => {AsRef<Option>},
+        offset: usize = (0) => {},
+        count: Option<usize> = () => {Option},
+    }
+}
+
+request_ref! {
+    /// Up to `MAX_MUTUAL_TARGETS` users at once, see `get_mutual()` for any number of them
+    struct GetMutual for ["friends.getMutual"](v => 5.44) -> Vec<MutualFriends> [Friends] {
+        sized {
+            source_uid: Option<Id> = () => {Option},
+            order: Option<Order> = () => {AsRef<Option>},
+            offset: usize = (0) => {},
+            count: Option<usize> = () => {Option},
+        }
+        unsized {
+            target_uids: [Id] = (&[][..]) => {Vec},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetRequests for ["friends.getRequests"](v => 5.44, extended => 1) -> Collection<FriendRequest> [Friends] {
+        offset: usize = (0) => {},
+        count: usize = (100) => {},
+        need_mutual: bool = (false) => {bool},
+        out: bool = (false) => {bool},
+        sort: RequestSort = (RequestSort::Date) => {AsRef},
+        suggested: bool = (false) => {bool},
+    }
+}
+
+request_ref! {
+    struct Add for ["friends.add"](v => 5.44) -> AddStatus [Friends] {
+        sized {
+            user_id: Id = () => {},
+            follow: bool = (false) => {bool},
+        }
+        unsized {
+            text: str = ("") => {=},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct Delete for ["friends.delete"](v => 5.44) -> DeleteResult [Friends] {
+        user_id: Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetLists for ["friends.getLists"](v => 5.44) -> Collection<FriendList> [Friends] {
+        user_id: Option<Id> = () => {Option},
+        return_system: bool = (false) => {bool},
+    }
+}
+
+request_ref! {
+    struct AddList for ["friends.addList"](v => 5.44) -> ListId [Friends] {
+        sized {}
+        unsized {
+            name: str = ("") => {=},
+            user_ids: [Id] = (&[][..]) => {Vec},
+        }
+    }
+}
+
+request_ref! {
+    struct EditList for ["friends.editList"](v => 5.44) -> Bool [Friends] {
+        sized {
+            list_id: Id = () => {},
+        }
+        unsized {
+            name: str = ("") => {=},
+            user_ids: [Id] = (&[][..]) => {Vec},
+            add_user_ids: [Id] = (&[][..]) => {Vec},
+            delete_user_ids: [Id] = (&[][..]) => {Vec},
+        }
+    }
+}
+
+request_ref! {
+    struct AreFriends for ["friends.areFriends"](v => 5.44) -> Vec<Friendship> [Friends] {
+        sized {
+            need_sign: bool = (false) => {bool},
+        }
+        unsized {
+            user_ids: [Id] = (&[][..]) => {Vec},
+        }
+    }
+}
+
+request_ref! {
+    struct GetSuggestions for ["friends.getSuggestions"](v => 5.44) -> Collection<User> [Friends] {
+        sized {
+            offset: usize = (0) => {},
+            count: usize = (100) => {},
+            name_case: NameCase = (NameCase::Nominative) => {AsRef},
+        }
+        unsized {
+            filter: [SuggestionFilter] = (&[][..]) => {AsRef<Vec>},
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+fields_request_impl!(UserOptionField; Get<'a> => Collection, GetSuggestions<'a> => Collection);
+
+enum_str! { Order {
+    Name = "name",
+    Hints = "hints",
+    Random = "random",
+    Mobile = "mobile",
+}}
+
+enum_str! { RequestSort {
+    Date = "0",
+    Mutual = "1",
+    Rotate = "2",
+}}
+
+enum_str! { SuggestionFilter {
+    Mutual = "mutual",
+    Contacts = "contacts",
+    MutualContacts = "mutual_contacts",
+}}
+
+/// Get mutual friends with any number of users, splitting them into
+/// batches of `MAX_MUTUAL_TARGETS` per request
+pub fn get_mutual(api: &Client, token: Option<&AccessToken>, source_uid: Option<Id>, target_uids: &[Id]) -> Result<Vec<MutualFriends>> {
+    let mut mutual = Vec::with_capacity(target_uids.len());
+    for targets in target_uids.chunks(MAX_MUTUAL_TARGETS) {
+        mutual.extend(api.call(token, GetMutual::new().source_uid(source_uid).target_uids(targets))?);
+    }
+    Ok(mutual)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use super::*;
+
+    #[test]
+    fn friendship_round_trip() {
+        let json = r#"[{"user_id": 1, "friend_status": 3, "sign": "f00"}, {"user_id": 2, "friend_status": 1}]"#;
+        let friendships: Vec<Friendship> = serde_json::from_str(json).unwrap();
+        assert_eq!(friendships[1].sign, None);
+
+        let value = serde_json::to_value(&friendships).unwrap();
+        assert_eq!(value[0]["friend_status"], 3);
+        assert_eq!(value[1]["friend_status"], 1);
+        assert_eq!(serde_json::from_value::<Vec<Friendship>>(value).unwrap(), friendships);
+    }
+
+    #[test]
+    fn delete_result_round_trip() {
+        let result: DeleteResult = serde_json::from_str(r#"{"success": 1, "friend_deleted": 1}"#).unwrap();
+        assert_eq!((result.success, result.friend_deleted, result.in_request_deleted),
+                   (adapters::flag::from_bool(true), adapters::flag::from_bool(true), adapters::flag::from_bool(false)));
+
+        let value = serde_json::to_value(result).unwrap();
+        assert_eq!(value["success"], 1);
+        assert_eq!(value["out_request_deleted"], 0);
+        assert_eq!(serde_json::from_value::<DeleteResult>(value).unwrap(), result);
+    }
+
+    #[test]
+    fn status_codes() {
+        let statuses: Vec<FriendStatus> = serde_json::from_str("[0, 1, 2, 3]").unwrap();
+        assert_eq!(statuses, [FriendStatus::NotFriend, FriendStatus::RequestSent, FriendStatus::RequestReceived, FriendStatus::Friend]);
+        assert!(serde_json::from_str::<FriendStatus>("4").is_err());
+
+        let statuses: Vec<AddStatus> = serde_json::from_str("[1, 2, 4]").unwrap();
+        assert_eq!(statuses, [AddStatus::RequestSent, AddStatus::RequestApproved, AddStatus::RequestResent]);
+        assert!(serde_json::from_str::<AddStatus>("3").is_err());
+        assert_eq!(serde_json::to_string(&statuses).unwrap(), "[1,2,4]");
+    }
+
+    #[test]
+    fn requests_with_mutual_friends() {
+        let json = r#"{"count": 2, "items": [
+            {"user_id": 7, "message": "Hi", "mutual": {"count": 2, "users": [1, 5]}},
+            {"user_id": 8}
+        ]}"#;
+        let requests: Collection<FriendRequest> = serde_json::from_str(json).unwrap();
+        assert_eq!(requests.items[0].mutual, Some(MutualCount { count: 2, users: vec![1, 5] }));
+        assert_eq!((requests.items[1].user_id, &requests.items[1].message), (8, &None));
+    }
+}
+
@@ -1,8 +1,8 @@
 use std::borrow::Borrow;
 use api::Collection;
-use serde::de;
+use serde::{de, ser};
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct GiftItem {
     pub id: u64,
     pub from_id: i64,
@@ -12,7 +12,7 @@ pub struct GiftItem {
     pub privacy: GiftPrivacy,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Gift {
     pub id: u64,
     pub thumb_256: String, // Url
@@ -41,6 +41,17 @@ impl<'de> de::Deserialize<'de> for GiftPrivacy {
     }
 }
 
+impl ser::Serialize for GiftPrivacy {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use self::GiftPrivacy::*;
+        s.serialize_u32(match *self {
+            Pubclic => 0,
+            SenderNameOnly => 1,
+            Private => 2,
+        })
+    }
+}
+
 request! {
     #[derive(Eq, Copy)]
     struct Get for ["gifts.get"](v => 5.44) -> Collection<GiftItem> {
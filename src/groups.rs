@@ -0,0 +1,856 @@
+use std::borrow::Borrow;
+use serde::{de, ser};
+use adapters::{self, Flag, Time};
+use api::{Bool, Collection, Id, Owner, OwnerId};
+use audio::Audio;
+use users::{User, UserOptionField};
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Group {
+    pub id: Id,
+    pub name: String,
+    pub screen_name: String,
+    pub is_closed: GroupAccess,
+    pub deactivated: Option<String>,
+    #[serde(default, with = "adapters::flag")]
+    pub is_admin: Flag,
+    pub admin_level: Option<u8>, // 1 - moderator, 2 - editor, 3 - administrator
+    #[serde(default, with = "adapters::flag")]
+    pub is_member: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub is_advertiser: Flag,
+    pub invited_by: Option<Id>,
+    #[serde(rename="type")]
+    pub kind: GroupKind,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_50: Option<adapters::Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_100: Option<adapters::Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_200: Option<adapters::Link>,
+    pub status_audio: Option<Audio>,
+
+    // Optional fields, returned only if requested in `fields`
+    pub activity: Option<String>,
+    pub age_limits: Option<u8>, // 1 - none, 2 - 16+, 3 - 18+
+    pub ban_info: Option<BanInfo>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub can_create_topic: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub can_message: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub can_post: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub can_see_all_posts: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub can_upload_doc: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub can_upload_video: Option<Flag>,
+    pub city: Option<Place>,
+    pub country: Option<Place>,
+    #[serde(default)]
+    pub contacts: Vec<Contact>,
+    pub counters: Option<Counters>,
+    pub cover: Option<Cover>,
+    pub description: Option<String>,
+    pub fixed_post: Option<Id>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub has_photo: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub is_favorite: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub is_hidden_from_feed: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub is_messages_blocked: Option<Flag>,
+    #[serde(default)]
+    pub links: Vec<Link>,
+    pub main_album_id: Option<Id>,
+    pub main_section: Option<u8>,
+    pub member_status: Option<MemberStatus>,
+    pub members_count: Option<u32>,
+    pub public_date_label: Option<String>,
+    #[serde(default, with = "adapters::option_link")]
+    pub site: Option<adapters::Link>,
+    pub status: Option<String>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub trending: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub verified: Option<Flag>,
+    pub wall: Option<u8>, // 0 - disabled, 1 - open, 2 - limited, 3 - closed
+    pub wiki_page: Option<String>,
+}
+
+enum_str! { GroupKind {
+    Group = "group",
+    Page = "page",
+    Event = "event",
+}}
+
+/// Ban details for current user, or for banned user in `groups.getBanned`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct BanInfo {
+    pub admin_id: Option<OwnerId>,
+    #[serde(default, with = "adapters::option_time")]
+    pub date: Option<Time>,
+    #[serde(default)]
+    pub reason: u8,
+    #[serde(default)]
+    pub comment: String,
+    #[serde(default, with = "adapters::flag")]
+    pub comment_visible: Flag,
+    /// Unix epoch (`0` in VK response) if banned forever
+    #[serde(default, with = "adapters::time")]
+    pub end_date: Time,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Place {
+    pub id: Id,
+    pub title: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Contact {
+    pub user_id: Option<Id>,
+    pub desc: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Counters {
+    #[serde(default)]
+    pub photos: u32,
+    #[serde(default)]
+    pub albums: u32,
+    #[serde(default)]
+    pub audios: u32,
+    #[serde(default)]
+    pub videos: u32,
+    #[serde(default)]
+    pub topics: u32,
+    #[serde(default)]
+    pub docs: u32,
+    #[serde(default)]
+    pub market: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Cover {
+    #[serde(with = "adapters::flag")]
+    pub enabled: Flag,
+    #[serde(default)]
+    pub images: Vec<CoverImage>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct CoverImage {
+    #[serde(with = "adapters::link")]
+    pub url: adapters::Link,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Link {
+    pub id: Id,
+    #[serde(with = "adapters::link")]
+    pub url: adapters::Link,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub desc: String,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_50: Option<adapters::Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_100: Option<adapters::Link>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberStatus {
+    NotMember, // 0
+    Member, // 1
+    NotSure, // 2
+    Declined, // 3
+    RequestSent, // 4
+    Invited, // 5
+}
+
+impl<'de> de::Deserialize<'de> for MemberStatus {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<MemberStatus, D::Error> {
+        use self::MemberStatus::*;
+        de::Deserialize::deserialize(d).and_then(|v: u32| {
+            match v {
+                0 => Ok(NotMember),
+                1 => Ok(Member),
+                2 => Ok(NotSure),
+                3 => Ok(Declined),
+                4 => Ok(RequestSent),
+                5 => Ok(Invited),
+                _ => Err(de::Error::invalid_value(de::Unexpected::Unsigned(v as u64), &"member status from 0 to 5")),
+            }
+        })
+    }
+}
+
+impl ser::Serialize for MemberStatus {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use self::MemberStatus::*;
+        s.serialize_u32(match *self {
+            NotMember => 0,
+            Member => 1,
+            NotSure => 2,
+            Declined => 3,
+            RequestSent => 4,
+            Invited => 5,
+        })
+    }
+}
+
+/// Who can see community content, sent as `is_closed`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupAccess {
+    Open, // 0
+    Closed, // 1
+    Private, // 2
+}
+
+impl<'de> de::Deserialize<'de> for GroupAccess {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<GroupAccess, D::Error> {
+        use self::GroupAccess::*;
+        de::Deserialize::deserialize(d).and_then(|v: u32| {
+            match v {
+                0 => Ok(Open),
+                1 => Ok(Closed),
+                2 => Ok(Private),
+                _ => Err(de::Error::invalid_value(de::Unexpected::Unsigned(v as u64), &"access from 0 to 2")),
+            }
+        })
+    }
+}
+
+impl ser::Serialize for GroupAccess {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use self::GroupAccess::*;
+        s.serialize_u32(match *self {
+            Open => 0,
+            Closed => 1,
+            Private => 2,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct Membership {
+    pub user_id: Id,
+    #[serde(with = "adapters::flag")]
+    pub member: Flag,
+}
+
+/// Banned user or community
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct BannedItem {
+    #[serde(rename="type")]
+    pub kind: String, // "profile" or "group"
+    pub profile: Option<User>,
+    pub group: Option<Group>,
+    pub ban_info: BanInfo,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct GroupSettings {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub address: String,
+    pub access: Option<u8>,
+    pub subject: Option<u32>,
+    #[serde(default, with = "adapters::option_link")]
+    pub website: Option<adapters::Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub rss: Option<adapters::Link>,
+    pub public_category: Option<u32>,
+    pub public_subcategory: Option<u32>,
+    pub public_date: Option<String>,
+    pub wall: Option<u8>,
+    pub topics: Option<u8>,
+    pub photos: Option<u8>,
+    pub video: Option<u8>,
+    pub audio: Option<u8>,
+    pub docs: Option<u8>,
+    pub wiki: Option<u8>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub messages: Option<Flag>,
+    pub articles: Option<u8>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub addresses: Option<Flag>,
+    pub age_limits: Option<u8>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub obscene_filter: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub obscene_stopwords: Option<Flag>,
+    #[serde(default)]
+    pub obscene_words: Vec<String>,
+}
+
+request_ref! {
+    struct Get for ["groups.get"](v => 5.92, extended => 1) -> Collection<Group> [Groups] {
+        sized {
+            user_id: Option<Id> = () => {Option},
+            offset: usize = (0) => {},
+            count: usize = (1000) => {},
+        }
+        unsized {
+            filter: [GroupFilter] = (&[][..]) => {AsRef<Vec>},
+            fields: [GroupField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request_ref! {
+    struct GetIds for ["groups.get"](v => 5.92, extended => 0) -> Collection<Id> [Groups] {
+        sized {
+            user_id: Option<Id> = () => {Option},
+            offset: usize = (0) => {},
+            count: usize = (1000) => {},
+        }
+        unsized {
+            filter: [GroupFilter] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request_ref! {
+    struct GetById for ["groups.getById"](v => 5.92) -> Vec<Group> {
+        sized {}
+        unsized {
+            group_ids: [Id] = (&[][..]) => {Vec},
+            // Screen name or id of a single community
+            group_id: str = ("") => {=},
+            fields: [GroupField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request_ref! {
+    /// Members are returned as user objects only if `fields` is not empty, so it defaults to `domain`,
+    /// use `GetMemberIds` to get bare ids.
+    struct GetMembers for ["groups.getMembers"](v => 5.92) -> Collection<User> {
+        sized {
+            group_id: Id = () => {},
+            sort: MemberSort = (MemberSort::IdAsc) => {AsRef},
+            offset: usize = (0) => {},
+            count: usize = (1000) => {},
+            filter: Option<MemberFilter> = () => {AsRef<Option>},
+        }
+        unsized {
+            fields: [UserOptionField] = (&[UserOptionField::Domain][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+fields_request_impl!(GroupField; Get<'a> => Collection, GetById<'a> => Vec);
+fields_request_impl!(UserOptionField; GetMembers<'a> => Collection);
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetMemberIds for ["groups.getMembers"](v => 5.92) -> Collection<Id> {
+        group_id: Id = () => {},
+        sort: MemberSort = (MemberSort::IdAsc) => {AsRef},
+        offset: usize = (0) => {},
+        count: usize = (1000) => {},
+        filter: Option<MemberFilter> = () => {AsRef<Option>},
+    }
+}
+
+request_ref! {
+    struct Search for ["groups.search"](v => 5.92) -> Collection<Group> {
+        sized {
+            kind as ("type"): Option<GroupKind> = () => {AsRef<Option>},
+            country_id as ("country_id"): Option<Id> = () => {Option},
+            city_id as ("city_id"): Option<Id> = () => {Option},
+            future as ("future"): bool = (false) => {bool},
+            market as ("market"): bool = (false) => {bool},
+            sort as ("sort"): SearchSort = (SearchSort::Default) => {AsRef},
+            offset as ("offset"): usize = (0) => {},
+            count as ("count"): usize = (20) => {},
+        }
+        unsized {
+            q as ("q"): str = ("") => {=},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct Join for ["groups.join"](v => 5.92) -> Bool [Groups] {
+        group_id: Id = () => {},
+        not_sure: bool = (false) => {bool},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct Leave for ["groups.leave"](v => 5.92) -> Bool [Groups] {
+        group_id: Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct IsMember for ["groups.isMember"](v => 5.92) -> Bool {
+        group_id: Id = () => {},
+        user_id: Option<Id> = () => {Option},
+    }
+}
+
+request_ref! {
+    struct AreMembers for ["groups.isMember"](v => 5.92) -> Vec<Membership> {
+        sized {
+            group_id: Id = () => {},
+        }
+        unsized {
+            user_ids: [Id] = (&[][..]) => {Vec},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct Invite for ["groups.invite"](v => 5.92) -> Bool [Groups] {
+        group_id: Id = () => {},
+        user_id: Id = () => {},
+    }
+}
+
+request_ref! {
+    struct GetBanned for ["groups.getBanned"](v => 5.92) -> Collection<BannedItem> [Groups] {
+        sized {
+            group_id: Id = () => {},
+            owner_id: Option<Owner> = {} => {Option},
+            offset: usize = (0) => {},
+            count: usize = (20) => {},
+        }
+        unsized {
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request_ref! {
+    struct Ban for ["groups.ban"](v => 5.92) -> Bool [Groups] {
+        sized {
+            group_id: Id = () => {},
+            owner_id: Owner = () => {},
+            end_date: Option<Time> = () => {Option<Time>},
+            reason: BanReason = (BanReason::Other) => {AsRef},
+            comment_visible: bool = (false) => {bool},
+        }
+        unsized {
+            comment: str = ("") => {=},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct Unban for ["groups.unban"](v => 5.92) -> Bool [Groups] {
+        group_id: Id = () => {},
+        owner_id: Owner = () => {},
+    }
+}
+
+request_ref! {
+    struct Edit for ["groups.edit"](v => 5.92) -> Bool [Groups] {
+        sized {
+            group_id: Id = () => {},
+            access: Option<u8> = () => {Option},
+            subject: Option<u32> = () => {Option},
+            public_category: Option<u32> = () => {Option},
+            public_subcategory: Option<u32> = () => {Option},
+            event_start_date: Option<Time> = () => {Option<Time>},
+            event_finish_date: Option<Time> = () => {Option<Time>},
+            event_group_id: Option<Id> = () => {Option},
+            wall: Option<u8> = () => {Option},
+            topics: Option<u8> = () => {Option},
+            photos: Option<u8> = () => {Option},
+            video: Option<u8> = () => {Option},
+            audio: Option<u8> = () => {Option},
+            docs: Option<u8> = () => {Option},
+            wiki: Option<u8> = () => {Option},
+            messages: Option<bool> = () => {Option<bool>},
+            articles: Option<u8> = () => {Option},
+            addresses: Option<bool> = () => {Option<bool>},
+            age_limits: Option<u8> = () => {Option},
+            market: Option<bool> = () => {Option<bool>},
+            obscene_filter: Option<bool> = () => {Option<bool>},
+            obscene_stopwords: Option<bool> = () => {Option<bool>},
+        }
+        unsized {
+            title: str = ("") => {=},
+            description: str = ("") => {=},
+            screen_name: str = ("") => {=},
+            website: str = ("") => {=},
+            email: str = ("") => {=},
+            phone: str = ("") => {=},
+            rss: str = ("") => {=},
+            // Public page foundation date in `DD.MM.YYYY` format
+            public_date: str = ("") => {=},
+            obscene_words: [String] = (&[][..]) => {Vec},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetSettings for ["groups.getSettings"](v => 5.92) -> GroupSettings [Groups] {
+        group_id: Id = () => {},
+    }
+}
+
+enum_str! { GroupField {
+    Activity = "activity",
+    AgeLimits = "age_limits",
+    BanInfo = "ban_info",
+    CanCreateTopic = "can_create_topic",
+    CanMessage = "can_message",
+    CanPost = "can_post",
+    CanSeeAllPosts = "can_see_all_posts",
+    CanUploadDoc = "can_upload_doc",
+    CanUploadVideo = "can_upload_video",
+    City = "city",
+    Contacts = "contacts",
+    Counters = "counters",
+    Country = "country",
+    Cover = "cover",
+    Description = "description",
+    FixedPost = "fixed_post",
+    HasPhoto = "has_photo",
+    IsFavorite = "is_favorite",
+    IsHiddenFromFeed = "is_hidden_from_feed",
+    IsMessagesBlocked = "is_messages_blocked",
+    Links = "links",
+    MainAlbumId = "main_album_id",
+    MainSection = "main_section",
+    MemberStatus = "member_status",
+    MembersCount = "members_count",
+    PublicDateLabel = "public_date_label",
+    Site = "site",
+    Status = "status",
+    Trending = "trending",
+    Verified = "verified",
+    Wall = "wall",
+    WikiPage = "wiki_page",
+}}
+
+enum_str! { GroupFilter {
+    Admin = "admin",
+    Editor = "editor",
+    Moder = "moder",
+    Advertiser = "advertiser",
+    Groups = "groups",
+    Publics = "publics",
+    Events = "events",
+    HasAddress = "hasAddress",
+}}
+
+enum_str! { MemberSort {
+    IdAsc = "id_asc",
+    IdDesc = "id_desc",
+    TimeAsc = "time_asc",
+    TimeDesc = "time_desc",
+}}
+
+enum_str! { MemberFilter {
+    Friends = "friends",
+    Unsure = "unsure",
+    Managers = "managers",
+}}
+
+enum_str! { SearchSort {
+    Default = "0",
+    Growth = "1",
+    Attendance = "2",
+    Likes = "3",
+    Comments = "4",
+    Posts = "5",
+}}
+
+enum_str! { BanReason {
+    Other = "0",
+    Spam = "1",
+    VerbalAbuse = "2",
+    StrongLanguage = "3",
+    Flood = "4",
+}}
+
+macro_rules! event_settings {
+    ($($name:ident),+ $(,)*) => {
+        /// Community events subscription, one flag per event type
+        #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default)]
+        pub struct EventSettings {
+            $(
+                #[serde(default, with = "adapters::flag")]
+                pub $name: Flag,
+            )+
+        }
+
+        impl EventSettings {
+            /// Subscription to all events
+            pub fn all() -> EventSettings {
+                EventSettings {
+                    $($name: adapters::flag::from_bool(true),)+
+                }
+            }
+
+            /// Request parameters for all flags, so that events not set are unsubscribed
+            pub fn to_params(&self) -> Vec<(&'static str, String)> {
+                vec![$((stringify!($name), adapters::flag::to_param(&self.$name))),+]
+            }
+        }
+    }
+}
+
+event_settings! {
+    message_new,
+    message_reply,
+    message_allow,
+    message_deny,
+    message_edit,
+    message_typing_state,
+    photo_new,
+    audio_new,
+    video_new,
+    wall_reply_new,
+    wall_reply_edit,
+    wall_reply_delete,
+    wall_reply_restore,
+    wall_post_new,
+    wall_repost,
+    board_post_new,
+    board_post_edit,
+    board_post_restore,
+    board_post_delete,
+    photo_comment_new,
+    photo_comment_edit,
+    photo_comment_delete,
+    photo_comment_restore,
+    video_comment_new,
+    video_comment_edit,
+    video_comment_delete,
+    video_comment_restore,
+    market_comment_new,
+    market_comment_edit,
+    market_comment_delete,
+    market_comment_restore,
+    poll_vote_new,
+    group_join,
+    group_leave,
+    group_change_settings,
+    group_change_photo,
+    group_officers_edit,
+    user_block,
+    user_unblock,
+    lead_forms_new,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct ServerId {
+    pub server_id: Id,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ConfirmationCode {
+    pub code: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct CallbackSettings {
+    pub api_version: Option<String>,
+    pub events: EventSettings,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct LongPollSettings {
+    #[serde(with = "adapters::flag")]
+    pub is_enabled: Flag,
+    pub api_version: Option<String>,
+    pub events: EventSettings,
+}
+
+request_ref! {
+    struct AddCallbackServer for ["groups.addCallbackServer"](v => 5.92) -> ServerId [Groups] {
+        sized {
+            group_id: Id = () => {},
+        }
+        unsized {
+            url: str = ("") => {=},
+            title: str = ("") => {=},
+            secret_key: str = ("") => {=},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetCallbackConfirmationCode for ["groups.getCallbackConfirmationCode"](v => 5.92) -> ConfirmationCode [Groups] {
+        group_id: Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetCallbackSettings for ["groups.getCallbackSettings"](v => 5.92) -> CallbackSettings [Groups] {
+        group_id: Id = () => {},
+        server_id: Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetLongPollSettings for ["groups.getLongPollSettings"](v => 5.92) -> LongPollSettings [Groups] {
+        group_id: Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq)]
+    struct SetCallbackSettings for ["groups.setCallbackSettings"](v => 5.92) -> Bool [Groups] {
+        group_id: Id = () => {},
+        server_id: Id = () => {},
+        // API version of event objects sent to the server
+        api_version: String = {} => {|value| value.as_str()},
+        events: EventSettings = () => {Params},
+    }
+}
+
+request! {
+    #[derive(Eq)]
+    struct SetLongPollSettings for ["groups.setLongPollSettings"](v => 5.92) -> Bool [Groups] {
+        group_id: Id = () => {},
+        enabled: bool = () => {bool},
+        // API version of event objects returned by long poll server
+        api_version: String = {} => {|value| value.as_str()},
+        events: EventSettings = () => {Params},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+    use api::Request;
+    use super::*;
+
+    const GROUP: &str = r#"{
+        "id": 1,
+        "name": "VK API",
+        "screen_name": "apiclub",
+        "is_closed": 0,
+        "type": "page",
+        "is_admin": 1,
+        "admin_level": 3,
+        "is_member": 1,
+        "is_advertiser": 0,
+        "photo_50": "https://vk.com/images/community_50.png",
+        "photo_100": "https://vk.com/images/community_100.png",
+        "photo_200": "https://vk.com/images/community_200.png",
+        "activity": "Software",
+        "ban_info": {"date": 1500000000, "reason": 1, "comment": "Spam", "comment_visible": 1, "end_date": 0},
+        "can_post": 0,
+        "can_message": 1,
+        "city": {"id": 2, "title": "Saint Petersburg"},
+        "counters": {"photos": 10, "videos": 2},
+        "cover": {"enabled": 1, "images": [{"url": "https://vk.com/cover.jpg", "width": 795, "height": 200}]},
+        "links": [{"id": 3, "url": "https://vk.com/dev", "name": "Docs", "desc": "", "photo_50": null, "photo_100": null}],
+        "member_status": 1,
+        "members_count": 1000000,
+        "site": "https://vk.com/dev",
+        "verified": 1,
+        "wall": 3
+    }"#;
+
+    #[test]
+    fn group_round_trip() {
+        let group: Group = serde_json::from_str(GROUP).unwrap();
+        assert_eq!(group.kind, GroupKind::Page);
+        assert_eq!(group.member_status, Some(MemberStatus::Member));
+        assert_eq!(group.is_closed, GroupAccess::Open);
+
+        let value = serde_json::to_value(&group).unwrap();
+        assert_eq!(value["is_admin"], 1);
+        assert_eq!(value["is_advertiser"], 0);
+        assert_eq!(value["can_post"], 0);
+        assert_eq!(value["verified"], 1);
+        assert_eq!(value["can_upload_doc"], Value::Null);
+        assert_eq!(value["type"], "page");
+        assert_eq!(value["is_closed"], 0);
+        assert_eq!(value["member_status"], 1);
+        assert_eq!(value["photo_200"], "https://vk.com/images/community_200.png");
+        assert_eq!(value["site"], "https://vk.com/dev");
+        assert_eq!(value["ban_info"]["date"], 1500000000);
+        assert_eq!(value["ban_info"]["end_date"], 0);
+        assert_eq!(value["ban_info"]["comment_visible"], 1);
+        assert_eq!(value["cover"]["enabled"], 1);
+        assert_eq!(value["cover"]["images"][0]["url"], "https://vk.com/cover.jpg");
+        assert_eq!(value["links"][0]["url"], "https://vk.com/dev");
+        assert_eq!(value["links"][0]["photo_50"], Value::Null);
+        assert_eq!(serde_json::from_value::<Group>(value).unwrap(), group);
+    }
+
+    #[test]
+    fn settings_links() {
+        let settings: GroupSettings =
+            serde_json::from_str(r#"{"title": "VK API", "website": "", "rss": "https://vk.com/rss", "messages": 1, "obscene_filter": 0}"#)
+                .unwrap();
+        assert_eq!(settings.website, None);
+        assert_eq!(settings.messages, Some(adapters::flag::from_bool(true)));
+        assert_eq!((settings.obscene_filter, settings.addresses), (Some(adapters::flag::from_bool(false)), None));
+        assert_eq!(settings.rss.as_ref().map(|rss| rss.as_str()), Some("https://vk.com/rss"));
+
+        let value = serde_json::to_value(&settings).unwrap();
+        assert_eq!(value["website"], Value::Null);
+        assert_eq!(value["rss"], "https://vk.com/rss");
+        assert_eq!(value["messages"], 1);
+        assert_eq!(serde_json::from_value::<GroupSettings>(value).unwrap(), settings);
+    }
+
+    #[test]
+    fn event_settings_round_trip() {
+        let settings: LongPollSettings =
+            serde_json::from_str(r#"{"is_enabled": true, "api_version": "5.92", "events": {"message_new": 1, "group_join": 0}}"#)
+                .unwrap();
+        assert_eq!(settings.is_enabled, adapters::flag::from_bool(true));
+        assert_eq!(settings.events.message_new, adapters::flag::from_bool(true));
+
+        let value = serde_json::to_value(&settings).unwrap();
+        assert_eq!(value["is_enabled"], 1);
+        assert_eq!(value["events"]["message_new"], 1);
+        assert_eq!(value["events"]["wall_post_new"], 0);
+        assert_eq!(serde_json::from_value::<LongPollSettings>(value).unwrap(), settings);
+
+        let params = settings.events.to_params();
+        assert!(params.contains(&("message_new", "1".to_owned())));
+        assert!(params.contains(&("group_join", "0".to_owned())));
+    }
+
+    #[test]
+    fn edit_flags() {
+        let query = Edit::new().group_id(1).messages(Some(true)).obscene_filter(Some(false)).to_query_string();
+        assert!(query.contains("messages=1"));
+        assert!(query.contains("obscene_filter=0"));
+        assert!(!query.contains("addresses"));
+    }
+
+    #[test]
+    fn set_settings_query() {
+        let mut events = EventSettings::default();
+        events.message_new = adapters::flag::from_bool(true);
+        let query = SetLongPollSettings::new().group_id(1).enabled(true).api_version("5.92").events(events).to_query_string();
+        assert!(query.starts_with("group_id=1&enabled=1&api_version=5.92&message_new=1&message_reply=0&"));
+        assert!(query.ends_with("&v=5.92"));
+
+        let query = SetCallbackSettings::new().group_id(1).server_id(2).events(events).to_query_string();
+        assert!(query.starts_with("group_id=1&server_id=2&message_new=1&"));
+    }
+}
@@ -105,17 +105,28 @@ extern crate chrono;
 
 mod macros;
 pub mod api;
+pub mod adapters;
 pub mod auth;
 pub mod audio;
 pub mod gifts;
 pub mod photos;
+pub mod docs;
 pub mod video;
 pub mod users;
+pub mod friends;
+pub mod groups;
 pub mod stats;
 pub mod status;
 pub mod wall;
+pub mod newsfeed;
+pub mod likes;
 pub mod utils;
 pub mod execute;
 pub mod storage;
 pub mod account;
 pub mod notifications;
+pub mod upload;
+pub mod messages;
+pub mod longpoll;
+pub mod bots_longpoll;
+pub mod callback;
@@ -0,0 +1,191 @@
+use std::borrow::Borrow;
+use adapters::{self, Flag};
+use api::{Collection, ExtendedRequest, Id, LikesCount, Owner, OwnerId};
+
+/// Result of `likes.add`, can be converted into new object's `LikesCount`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct LikeAdded {
+    pub likes: u32,
+}
+
+impl From<LikeAdded> for LikesCount {
+    fn from(added: LikeAdded) -> LikesCount {
+        LikesCount {
+            user_likes: 1,
+            count: added.likes,
+        }
+    }
+}
+
+/// Result of `likes.delete`, can be converted into new object's `LikesCount`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct LikeDeleted {
+    pub likes: u32,
+}
+
+impl From<LikeDeleted> for LikesCount {
+    fn from(deleted: LikeDeleted) -> LikesCount {
+        LikesCount {
+            user_likes: 0,
+            count: deleted.likes,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct IsLikedResult {
+    #[serde(with = "adapters::flag")]
+    pub liked: Flag,
+    #[serde(with = "adapters::flag")]
+    pub copied: Flag,
+}
+
+/// User or community who liked or reposted an object
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Liker {
+    #[serde(rename="type")]
+    pub kind: LikerKind,
+    pub id: Id,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub name: Option<String>,
+}
+
+impl Liker {
+    pub fn owner(&self) -> Owner {
+        match self.kind {
+            LikerKind::Profile => Owner::User(self.id),
+            LikerKind::Group | LikerKind::Page => Owner::Group(self.id),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub enum LikerKind {
+    #[serde(rename="profile")]
+    Profile,
+    #[serde(rename="group")]
+    Group,
+    #[serde(rename="page")]
+    Page,
+}
+
+request_ref! {
+    struct Add for ["likes.add"](v => 5.44) -> LikeAdded [Wall] {
+        sized {
+            kind as ("type"): LikeableKind = (LikeableKind::Post) => {AsRef},
+            owner_id as ("owner_id"): Option<Owner> = {} => {Option},
+            item_id as ("item_id"): Id = () => {},
+        }
+        unsized {
+            access_key as ("access_key"): str = ("") => {=},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct Delete for ["likes.delete"](v => 5.44) -> LikeDeleted [Wall] {
+        kind as ("type"): LikeableKind = (LikeableKind::Post) => {AsRef},
+        owner_id as ("owner_id"): Option<Owner> = {} => {Option},
+        item_id as ("item_id"): Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct IsLiked for ["likes.isLiked"](v => 5.44) -> IsLikedResult {
+        user_id as ("user_id"): Option<Id> = () => {Option},
+        kind as ("type"): LikeableKind = (LikeableKind::Post) => {AsRef},
+        owner_id as ("owner_id"): Option<Owner> = {} => {Option},
+        item_id as ("item_id"): Id = () => {},
+    }
+}
+
+request_ref! {
+    struct GetList for ["likes.getList"](v => 5.44) -> Collection<OwnerId> {
+        sized {
+            kind as ("type"): LikeableKind = (LikeableKind::Post) => {AsRef},
+            owner_id as ("owner_id"): Option<Owner> = {} => {Option},
+            item_id as ("item_id"): Option<Id> = () => {Option},
+            filter as ("filter"): LikesFilter = (LikesFilter::Likes) => {AsRef},
+            friends_only as ("friends_only"): bool = (false) => {bool},
+            skip_own as ("skip_own"): bool = (false) => {bool},
+            offset as ("offset"): usize = (0) => {},
+            count as ("count"): usize = (100) => {},
+        }
+        unsized {
+            page_url as ("page_url"): str = ("") => {=},
+        }
+    }
+}
+
+impl<'a> ExtendedRequest for GetList<'a> {
+    type Extended = Collection<Liker>;
+}
+
+enum_str! { LikeableKind {
+    Post = "post",
+    Comment = "comment",
+    Photo = "photo",
+    Audio = "audio",
+    Video = "video",
+    Note = "note",
+    Market = "market",
+    PhotoComment = "photo_comment",
+    VideoComment = "video_comment",
+    TopicComment = "topic_comment",
+    MarketComment = "market_comment",
+    SitePage = "sitepage",
+}}
+
+enum_str! { LikesFilter {
+    Likes = "likes",
+    Copies = "copies",
+}}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use api::{Extended, Request};
+    use super::*;
+
+    #[test]
+    fn like_counts() {
+        let added: LikeAdded = serde_json::from_str(r#"{"likes": 16}"#).unwrap();
+        assert_eq!(LikesCount::from(added), LikesCount { user_likes: 1, count: 16 });
+
+        let deleted: LikeDeleted = serde_json::from_str(r#"{"likes": 15}"#).unwrap();
+        assert_eq!(LikesCount::from(deleted), LikesCount { user_likes: 0, count: 15 });
+
+        let liked: IsLikedResult = serde_json::from_str(r#"{"liked": 1, "copied": 0}"#).unwrap();
+        assert_eq!((liked.liked, liked.copied), (adapters::flag::from_bool(true), adapters::flag::from_bool(false)));
+    }
+
+    #[test]
+    fn likers() {
+        let json = r#"{"count": 2, "items": [
+            {"type": "profile", "id": 5, "first_name": "Pavel", "last_name": "Durov"},
+            {"type": "page", "id": 1, "name": "VK API"}
+        ]}"#;
+        let likers: Collection<Liker> = serde_json::from_str(json).unwrap();
+        assert_eq!((likers.items[0].kind, likers.items[0].first_name.as_deref()), (LikerKind::Profile, Some("Pavel")));
+        assert_eq!((likers.items[1].kind, likers.items[1].name.as_deref()), (LikerKind::Page, Some("VK API")));
+        assert_eq!((likers.items[0].owner(), likers.items[1].owner()), (Owner::User(5), Owner::Group(1)));
+    }
+
+    #[test]
+    fn extended_list() {
+        let request = GetList::new().kind(LikeableKind::Photo).item_id(Some(10)).to_query_string();
+        assert!(!request.contains("extended"));
+        let request = Extended::new(GetList::new().kind(LikeableKind::Photo).item_id(Some(10)));
+        assert_eq!(Extended::<GetList>::method_name(), "likes.getList");
+        assert!(request.to_query_string().contains("type=photo") && request.to_query_string().ends_with("&extended=1"));
+    }
+
+    #[test]
+    fn typed_kind() {
+        let query = Add::new().kind(LikeableKind::PhotoComment).owner_id(Owner::Group(1)).item_id(10).to_query_string();
+        assert!(query.contains("type=photo_comment") && query.contains("owner_id=-1") && query.contains("item_id=10"));
+    }
+}
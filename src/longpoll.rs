@@ -0,0 +1,664 @@
+//! User Long Poll client, see <https://vk.com/dev/using_longpoll>
+
+use std::collections::VecDeque;
+use std::time::Duration;
+use serde::{de, ser};
+use serde_json::{Map, Value};
+use url::Url;
+use adapters::{self, Time};
+use api::{Client, Error, HttpClient, Id, Owner, OwnerId, Request, Result};
+use auth::AccessToken;
+use messages::{GetLongPollServer, LongPollServer, CHAT_PEER_ID_OFFSET};
+
+/// Long poll protocol version, defines events format
+pub const LONG_POLL_VERSION: u32 = 3;
+
+/// Maximum seconds long poll server waits for events in one request
+pub const MAX_WAIT: u32 = 90;
+/// Seconds to wait for long poll response in addition to `wait`, before the request times out
+pub const WAIT_TIMEOUT_MARGIN: u32 = 10;
+
+/// Return message attachments
+pub const MODE_ATTACHMENTS: u32 = 2;
+/// Return extended set of events
+pub const MODE_EXTENDED: u32 = 8;
+/// Return `pts` along with `ts`
+pub const MODE_PTS: u32 = 32;
+/// Return platform user is online from in `Online` event
+pub const MODE_ONLINE_PLATFORM: u32 = 64;
+/// Return `random_id` of new messages
+pub const MODE_RANDOM_ID: u32 = 128;
+
+/// Message flags, as used in flags change and new message events
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct MessageFlags(pub u32);
+
+impl MessageFlags {
+    pub const UNREAD: u32 = 1;
+    pub const OUTBOX: u32 = 2;
+    pub const REPLIED: u32 = 4;
+    pub const IMPORTANT: u32 = 8;
+    pub const CHAT: u32 = 16;
+    pub const FRIENDS: u32 = 32;
+    pub const SPAM: u32 = 64;
+    pub const DELETED: u32 = 128;
+    pub const FIXED: u32 = 256;
+    pub const MEDIA: u32 = 512;
+    pub const HIDDEN: u32 = 65536;
+    pub const DELETED_FOR_ALL: u32 = 131072;
+
+    pub fn contains(&self, flags: u32) -> bool {
+        self.0 & flags == flags
+    }
+}
+
+/// New or edited message, as sent by long poll server
+#[derive(Debug, PartialEq, Clone)]
+pub struct LongPollMessage {
+    pub message_id: Id,
+    pub flags: MessageFlags,
+    /// Chat, user or community id, see `Message::peer_id`
+    pub peer_id: OwnerId,
+    pub timestamp: Time,
+    pub text: String,
+    /// Chat title, sender id and other additional fields
+    pub extra: Map<String, Value>,
+    /// Attachment descriptions, if `MODE_ATTACHMENTS` is set
+    pub attachments: Map<String, Value>,
+    pub random_id: i32,
+    pub conversation_message_id: Id,
+    pub edit_time: Time,
+}
+
+impl LongPollMessage {
+    /// Message author: chat member for chat messages, peer otherwise
+    pub fn from_id(&self) -> Owner {
+        if self.peer_id > CHAT_PEER_ID_OFFSET {
+            self.extra.get("from").and_then(|from| from.as_str()).and_then(|from| from.parse().ok()).unwrap_or_default()
+        } else {
+            Owner::from(self.peer_id)
+        }
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.extra.get("title").and_then(Value::as_str)
+    }
+
+    pub fn is_outbox(&self) -> bool {
+        self.flags.contains(MessageFlags::OUTBOX)
+    }
+
+    fn from_event(event: &[Value]) -> LongPollMessage {
+        LongPollMessage {
+            message_id: int(event, 1) as Id,
+            flags: MessageFlags(int(event, 2) as u32),
+            peer_id: int(event, 3),
+            timestamp: time(event, 4),
+            text: event.get(5).and_then(Value::as_str).unwrap_or("").to_owned(),
+            extra: object(event, 6),
+            attachments: object(event, 7),
+            random_id: int(event, 8) as i32,
+            conversation_message_id: int(event, 9) as Id,
+            edit_time: time(event, 10),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Event {
+    ReplaceFlags { message_id: Id, flags: MessageFlags },
+    SetFlags { message_id: Id, flags: MessageFlags, peer_id: OwnerId },
+    ResetFlags { message_id: Id, flags: MessageFlags, peer_id: OwnerId },
+    NewMessage(LongPollMessage),
+    EditMessage(LongPollMessage),
+    /// Incoming messages up to `local_id` were read by current user
+    ReadIncoming { peer_id: OwnerId, local_id: Id },
+    /// Outgoing messages up to `local_id` were read by peer
+    ReadOutgoing { peer_id: OwnerId, local_id: Id },
+    Online { user_id: Id, platform: u32, timestamp: Time },
+    /// User went offline, either explicitly or by timeout
+    Offline { user_id: Id, timeout: bool, timestamp: Time },
+    /// User is typing in private dialog (`chat_id` is `None`) or in chat
+    Typing { user_id: Id, chat_id: Option<Id> },
+    /// Users are typing in conversation with `peer_id`
+    TypingInPeer { peer_id: OwnerId, user_ids: Vec<Id> },
+    UnreadCount { count: u32 },
+    /// Any other event in its raw form
+    Unknown(Vec<Value>),
+}
+
+fn int(event: &[Value], index: usize) -> i64 {
+    event.get(index).and_then(|value| value.as_i64().or_else(|| value.as_str().and_then(|value| value.parse().ok()))).unwrap_or(0)
+}
+
+/// Unix time, zero if it is missing or out of range
+fn time(event: &[Value], index: usize) -> Time {
+    adapters::time::from_secs(int(event, index)).unwrap_or_default()
+}
+
+fn object(event: &[Value], index: usize) -> Map<String, Value> {
+    match event.get(index) {
+        Some(Value::Object(map)) => map.clone(),
+        _ => Map::new(),
+    }
+}
+
+impl From<Vec<Value>> for Event {
+    fn from(event: Vec<Value>) -> Event {
+        match int(&event, 0) {
+            1 => Event::ReplaceFlags { message_id: int(&event, 1) as Id, flags: MessageFlags(int(&event, 2) as u32) },
+            2 => Event::SetFlags {
+                message_id: int(&event, 1) as Id,
+                flags: MessageFlags(int(&event, 2) as u32),
+                peer_id: int(&event, 3),
+            },
+            3 => Event::ResetFlags {
+                message_id: int(&event, 1) as Id,
+                flags: MessageFlags(int(&event, 2) as u32),
+                peer_id: int(&event, 3),
+            },
+            4 => Event::NewMessage(LongPollMessage::from_event(&event)),
+            5 => Event::EditMessage(LongPollMessage::from_event(&event)),
+            6 => Event::ReadIncoming { peer_id: int(&event, 1), local_id: int(&event, 2) as Id },
+            7 => Event::ReadOutgoing { peer_id: int(&event, 1), local_id: int(&event, 2) as Id },
+            8 => Event::Online {
+                user_id: int(&event, 1).unsigned_abs(),
+                platform: (int(&event, 2) & 0xff) as u32,
+                timestamp: time(&event, 3),
+            },
+            9 => Event::Offline {
+                user_id: int(&event, 1).unsigned_abs(),
+                timeout: int(&event, 2) == 1,
+                timestamp: time(&event, 3),
+            },
+            61 => Event::Typing { user_id: int(&event, 1) as Id, chat_id: None },
+            62 => Event::Typing { user_id: int(&event, 1) as Id, chat_id: Some(int(&event, 2) as Id) },
+            63 => Event::TypingInPeer {
+                peer_id: int(&event, 1),
+                user_ids: event.get(2)
+                    .and_then(Value::as_array)
+                    .map(|ids| ids.iter().filter_map(Value::as_u64).collect())
+                    .unwrap_or_default(),
+            },
+            80 => Event::UnreadCount { count: int(&event, 1) as u32 },
+            _ => Event::Unknown(event),
+        }
+    }
+}
+
+impl<'a> From<&'a Event> for Vec<Value> {
+    fn from(event: &'a Event) -> Vec<Value> {
+        fn message(code: u32, message: &LongPollMessage) -> Vec<Value> {
+            vec![code.into(),
+                 message.message_id.into(),
+                 message.flags.0.into(),
+                 message.peer_id.into(),
+                 secs(&message.timestamp),
+                 message.text.clone().into(),
+                 Value::Object(message.extra.clone()),
+                 Value::Object(message.attachments.clone()),
+                 message.random_id.into(),
+                 message.conversation_message_id.into(),
+                 secs(&message.edit_time)]
+        }
+
+        fn secs(time: &Time) -> Value {
+            adapters::time::to_secs(time).into()
+        }
+
+        // Online and offline events have user id negated
+        fn negative(user_id: Id) -> Value {
+            0i64.checked_sub_unsigned(user_id).map_or(Value::Null, Value::from)
+        }
+
+        match *event {
+            Event::ReplaceFlags { message_id, flags } => vec![1.into(), message_id.into(), flags.0.into()],
+            Event::SetFlags { message_id, flags, peer_id } => vec![2.into(), message_id.into(), flags.0.into(), peer_id.into()],
+            Event::ResetFlags { message_id, flags, peer_id } => vec![3.into(), message_id.into(), flags.0.into(), peer_id.into()],
+            Event::NewMessage(ref msg) => message(4, msg),
+            Event::EditMessage(ref msg) => message(5, msg),
+            Event::ReadIncoming { peer_id, local_id } => vec![6.into(), peer_id.into(), local_id.into()],
+            Event::ReadOutgoing { peer_id, local_id } => vec![7.into(), peer_id.into(), local_id.into()],
+            Event::Online { user_id, platform, ref timestamp } => vec![8.into(), negative(user_id), platform.into(), secs(timestamp)],
+            Event::Offline { user_id, timeout, ref timestamp } => vec![9.into(), negative(user_id), (timeout as u8).into(), secs(timestamp)],
+            Event::Typing { user_id, chat_id: None } => vec![61.into(), user_id.into(), 1.into()],
+            Event::Typing { user_id, chat_id: Some(chat_id) } => vec![62.into(), user_id.into(), chat_id.into()],
+            Event::TypingInPeer { peer_id, ref user_ids } => vec![63.into(), peer_id.into(), user_ids.clone().into()],
+            Event::UnreadCount { count } => vec![80.into(), count.into()],
+            Event::Unknown(ref event) => event.clone(),
+        }
+    }
+}
+
+/// Serialized in the same array form as sent by long poll server
+impl ser::Serialize for Event {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error> {
+        Vec::<Value>::from(self).serialize(s)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Event {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> ::std::result::Result<Event, D::Error> {
+        de::Deserialize::deserialize(d).map(|event: Vec<Value>| Event::from(event))
+    }
+}
+
+/// Long poll response, with events position of type `T` and updates of type `U`
+#[derive(Debug, Deserialize)]
+pub struct PollResponse<T, U> {
+    pub ts: Option<T>,
+    #[serde(default = "Vec::new")]
+    pub updates: Vec<U>,
+    pub failed: Option<u32>,
+}
+
+/// Long poll server key along with events position
+pub trait PollServer {
+    type Ts: de::DeserializeOwned;
+    fn ts(&self) -> &Self::Ts;
+    fn ts_mut(&mut self) -> &mut Self::Ts;
+    fn into_ts(self) -> Self::Ts;
+}
+
+impl PollServer for LongPollServer {
+    type Ts = u64;
+
+    fn ts(&self) -> &u64 {
+        &self.ts
+    }
+
+    fn ts_mut(&mut self) -> &mut u64 {
+        &mut self.ts
+    }
+
+    fn into_ts(self) -> u64 {
+        self.ts
+    }
+}
+
+/// HTTP client for long poll requests, which take up to `wait` seconds by design,
+/// while default client times out after 30 seconds
+pub fn poll_client(wait: u32) -> Result<HttpClient> {
+    HttpClient::builder()
+        .timeout(Duration::from_secs(u64::from(wait.min(MAX_WAIT) + WAIT_TIMEOUT_MARGIN)))
+        .build()
+        .map_err(Error::Http)
+}
+
+/// Long poll connection, shared by User and Bots Long Poll clients.
+///
+/// Gets server key with request `R`, keeps it and `ts` up to date
+/// and buffers received updates of type `U`.
+pub struct Poller<'a, R: Request, U> where R::Response: PollServer {
+    api: &'a Client,
+    token: Option<&'a AccessToken>,
+    request: R,
+    server: Option<R::Response>,
+    /// Events position to continue from after server key is fetched again
+    resume_ts: Option<<R::Response as PollServer>::Ts>,
+    client: Option<HttpClient>,
+    wait: u32,
+    pub(crate) updates: VecDeque<U>,
+}
+
+impl<'a, R: Request, U: de::DeserializeOwned> Poller<'a, R, U> where R::Response: PollServer {
+    pub fn new(api: &'a Client, token: Option<&'a AccessToken>, request: R) -> Poller<'a, R, U> {
+        Poller {
+            api,
+            token,
+            request,
+            server: None,
+            resume_ts: None,
+            client: None,
+            wait: 25,
+            updates: VecDeque::new(),
+        }
+    }
+
+    /// Server key request to change, current key and events position are dropped
+    pub fn request_mut(&mut self) -> &mut R {
+        self.server = None;
+        self.resume_ts = None;
+        &mut self.request
+    }
+
+    /// Seconds to wait for events in one request, capped at `MAX_WAIT`
+    pub fn set_wait(&mut self, value: u32) {
+        self.wait = value.min(MAX_WAIT);
+        self.client = None;
+    }
+
+    /// Last seen events position
+    pub fn ts(&self) -> Option<&<R::Response as PollServer>::Ts> {
+        self.server.as_ref().map(PollServer::ts).or(self.resume_ts.as_ref())
+    }
+
+    /// Continue from given events position, fetching server key if needed
+    pub fn set_ts(&mut self, ts: <R::Response as PollServer>::Ts) -> Result<()> {
+        *self.server()?.ts_mut() = ts;
+        Ok(())
+    }
+
+    fn server(&mut self) -> Result<&mut R::Response> {
+        if self.server.is_none() {
+            let mut server = self.api.call(self.token, &self.request)?;
+            if let Some(ts) = self.resume_ts.take() {
+                *server.ts_mut() = ts;
+            }
+            self.server = Some(server);
+        }
+        Ok(self.server.as_mut().unwrap())
+    }
+
+    fn client(&mut self) -> Result<&HttpClient> {
+        if self.client.is_none() {
+            self.client = Some(poll_client(self.wait)?);
+        }
+        Ok(self.client.as_ref().unwrap())
+    }
+
+    /// Make one long poll request to URL built by `url` from server key and `wait`,
+    /// returning received updates, possibly none
+    pub fn poll<F: FnOnce(&R::Response, u32) -> Result<Url>>(&mut self, url: F) -> Result<Vec<U>> {
+        let wait = self.wait;
+        let url = url(self.server()?, wait)?;
+
+        let resp: PollResponse<<R::Response as PollServer>::Ts, U> = self.client()?.get(url).send()
+            .map_err(Error::Http)
+            .and_then(|resp| ::serde_json::from_reader(resp).map_err(Error::Json))?;
+
+        apply_response(&mut self.server, &mut self.resume_ts, resp)
+    }
+
+    /// Next buffered update, polling until some arrive; errors are returned as is
+    pub fn next<F: Fn(&R::Response, u32) -> Result<Url>>(&mut self, url: F) -> Option<Result<U>> {
+        while self.updates.is_empty() {
+            match self.poll(&url) {
+                Ok(updates) => self.updates.extend(updates),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        self.updates.pop_front().map(Ok)
+    }
+}
+
+/// Update server state from poll response, recovering from `failed` codes 1-3
+pub fn apply_response<S: PollServer, U>(server: &mut Option<S>, resume_ts: &mut Option<S::Ts>,
+                                        resp: PollResponse<S::Ts, U>) -> Result<Vec<U>> {
+    match resp.failed {
+        // Events history is lost partially, continue with new ts
+        Some(1) => {
+            if let (Some(ts), Some(server)) = (resp.ts, server.as_mut()) {
+                *server.ts_mut() = ts;
+            }
+            Ok(Vec::new())
+        }
+        // Key expired, get new key keeping ts
+        Some(2) => {
+            *resume_ts = server.take().map(PollServer::into_ts);
+            Ok(Vec::new())
+        }
+        // Events history is lost, start over with new key and ts
+        Some(3) => {
+            *server = None;
+            *resume_ts = None;
+            Ok(Vec::new())
+        }
+        Some(code) => Err(Error::LongPoll(code)),
+        None => {
+            if let (Some(ts), Some(server)) = (resp.ts, server.as_mut()) {
+                *server.ts_mut() = ts;
+            }
+            Ok(resp.updates)
+        }
+    }
+}
+
+/// User Long Poll client.
+///
+/// Keeps server key and `ts` up to date, and can be used as an endless iterator of events.
+pub struct LongPoll<'a> {
+    poller: Poller<'a, GetLongPollServer, Event>,
+    mode: u32,
+}
+
+impl<'a> LongPoll<'a> {
+    pub fn new(api: &'a Client, token: Option<&'a AccessToken>) -> LongPoll<'a> {
+        let mut request = GetLongPollServer::new();
+        request.lp_version(LONG_POLL_VERSION);
+        LongPoll {
+            poller: Poller::new(api, token, request),
+            mode: MODE_ATTACHMENTS | MODE_EXTENDED | MODE_RANDOM_ID,
+        }
+    }
+
+    /// Listen to community messages instead of user ones
+    pub fn group_id(&mut self, value: Option<Id>) -> &mut Self {
+        self.poller.request_mut().group_id(value);
+        self
+    }
+
+    /// Seconds to wait for events in one request, capped at `MAX_WAIT`
+    pub fn wait(&mut self, value: u32) -> &mut Self {
+        self.poller.set_wait(value);
+        self
+    }
+
+    /// Combination of `MODE_*` flags
+    pub fn mode(&mut self, value: u32) -> &mut Self {
+        self.mode = value;
+        self
+    }
+
+    /// Last seen events position, can be used to resume listening with `set_ts()`
+    pub fn ts(&self) -> Option<u64> {
+        self.poller.ts().cloned()
+    }
+
+    /// Continue from given events position, fetching server key if needed
+    pub fn set_ts(&mut self, ts: u64) -> Result<()> {
+        self.poller.set_ts(ts)
+    }
+
+    /// Make one long poll request, returning received events, possibly none
+    pub fn poll(&mut self) -> Result<Vec<Event>> {
+        let mode = self.mode;
+        self.poller.poll(|server, wait| poll_url(server, wait, mode))
+    }
+}
+
+fn poll_url(server: &LongPollServer, wait: u32, mode: u32) -> Result<Url> {
+    let mut url = Url::parse(&format!("https://{}", server.server))?;
+    url.query_pairs_mut()
+        .append_pair("act", "a_check")
+        .append_pair("key", &server.key)
+        .append_pair("ts", &server.ts.to_string())
+        .append_pair("wait", &wait.to_string())
+        .append_pair("mode", &mode.to_string())
+        .append_pair("version", &LONG_POLL_VERSION.to_string());
+    Ok(url)
+}
+
+/// Endless stream of events, errors are yielded as is, so caller can decide to stop or retry
+impl<'a> Iterator for LongPoll<'a> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Result<Event>> {
+        let mode = self.mode;
+        self.poller.next(|server, wait| poll_url(server, wait, mode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+    use super::*;
+
+    fn time_at(secs: i64) -> Time {
+        adapters::time::from_secs(secs).unwrap()
+    }
+
+    fn event(json: &str) -> Event {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn server(ts: u64) -> Option<LongPollServer> {
+        Some(LongPollServer { key: "key".into(), server: "im.vk.com/nim1".into(), ts, pts: None })
+    }
+
+    fn response(json: &str) -> PollResponse<u64, Event> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn flag_events() {
+        assert_eq!(event("[1,123,3]"), Event::ReplaceFlags { message_id: 123, flags: MessageFlags(3) });
+        assert_eq!(event("[2,123,128,2000000001]"),
+                   Event::SetFlags { message_id: 123, flags: MessageFlags(MessageFlags::DELETED), peer_id: 2000000001 });
+        assert_eq!(event("[3,123,1,-1]"), Event::ResetFlags { message_id: 123, flags: MessageFlags(MessageFlags::UNREAD), peer_id: -1 });
+    }
+
+    #[test]
+    fn message_events() {
+        let json = r#"[4,2021,51,2000000003,1543924400,"hello",
+            {"title":"Chat","from":"42"},{"attach1_type":"photo","attach1":"42_456"},-7,15,0]"#;
+        let message = match event(json) {
+            Event::NewMessage(message) => message,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(message.message_id, 2021);
+        assert_eq!(message.peer_id, 2000000003);
+        assert_eq!(message.timestamp, time_at(1543924400));
+        assert_eq!(message.text, "hello");
+        assert_eq!(message.title(), Some("Chat"));
+        assert_eq!(message.from_id(), Owner::User(42));
+        assert_eq!(message.attachments["attach1"], "42_456");
+        assert_eq!(message.random_id, -7);
+        assert_eq!(message.conversation_message_id, 15);
+
+        assert!(message.is_outbox());
+        assert!(message.flags.contains(MessageFlags::UNREAD | MessageFlags::CHAT));
+        assert!(!message.flags.contains(MessageFlags::REPLIED));
+        assert!(!message.flags.contains(MessageFlags::UNREAD | MessageFlags::DELETED));
+
+        match event(r#"[5,2021,0,42,1543924400,"edited",{},{},0,15,1543924500]"#) {
+            Event::EditMessage(message) => {
+                assert_eq!(message.from_id(), Owner::User(42));
+                assert_eq!(message.title(), None);
+                assert_eq!(message.edit_time, time_at(1543924500));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        match event(r#"[4,2022,3,-7,1543924400,"from community",{},{},0,16,0]"#) {
+            Event::NewMessage(message) => assert_eq!(message.from_id(), Owner::Group(7)),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Round trip through the array form
+        let message = event(json);
+        assert_eq!(event(&serde_json::to_string(&message).unwrap()), message);
+    }
+
+    #[test]
+    fn status_events() {
+        assert_eq!(event("[6,42,2021]"), Event::ReadIncoming { peer_id: 42, local_id: 2021 });
+        assert_eq!(event("[7,42,2022]"), Event::ReadOutgoing { peer_id: 42, local_id: 2022 });
+        assert_eq!(event("[8,-42,259,1543924400]"), Event::Online { user_id: 42, platform: 3, timestamp: time_at(1543924400) });
+        assert_eq!(event("[9,-42,1,1543924400]"), Event::Offline { user_id: 42, timeout: true, timestamp: time_at(1543924400) });
+        assert_eq!(event("[9,-42,0,1543924400]"), Event::Offline { user_id: 42, timeout: false, timestamp: time_at(1543924400) });
+        assert_eq!(event("[61,42,1]"), Event::Typing { user_id: 42, chat_id: None });
+        assert_eq!(event("[62,42,3]"), Event::Typing { user_id: 42, chat_id: Some(3) });
+        assert_eq!(event("[63,2000000003,[42,43],2,1543924400]"), Event::TypingInPeer { peer_id: 2000000003, user_ids: vec![42, 43] });
+        assert_eq!(event("[80,5,0]"), Event::UnreadCount { count: 5 });
+
+        let online = Event::Online { user_id: 42, platform: 3, timestamp: time_at(1543924400) };
+        assert_eq!(serde_json::to_value(&online).unwrap()[1], -42);
+        assert_eq!(event(&serde_json::to_string(&online).unwrap()), online);
+    }
+
+    #[test]
+    fn unknown_events() {
+        let raw: Vec<Value> = serde_json::from_str(r#"[114,{"peer_id":42}]"#).unwrap();
+        assert_eq!(Event::from(raw.clone()), Event::Unknown(raw));
+    }
+
+    #[test]
+    fn updates_advance_ts() {
+        let (mut server, mut resume_ts) = (server(10), None);
+        let events = apply_response(&mut server, &mut resume_ts, response(r#"{"ts":12,"updates":[[80,1,0],[6,42,2021]]}"#)).unwrap();
+        assert_eq!(events, vec![Event::UnreadCount { count: 1 }, Event::ReadIncoming { peer_id: 42, local_id: 2021 }]);
+        assert_eq!(server.unwrap().ts, 12);
+    }
+
+    #[test]
+    fn history_partially_lost() {
+        let (mut server, mut resume_ts) = (server(10), None);
+        let events = apply_response(&mut server, &mut resume_ts, response(r#"{"failed":1,"ts":30}"#)).unwrap();
+        assert!(events.is_empty());
+        assert_eq!(server.map(|server| server.ts), Some(30));
+        assert_eq!(resume_ts, None);
+    }
+
+    #[test]
+    fn key_expired() {
+        let (mut server, mut resume_ts) = (server(10), None);
+        let events = apply_response(&mut server, &mut resume_ts, response(r#"{"failed":2}"#)).unwrap();
+        assert!(events.is_empty());
+        // Key is fetched again on next poll, keeping ts
+        assert_eq!(server, None);
+        assert_eq!(resume_ts, Some(10));
+    }
+
+    #[test]
+    fn history_lost() {
+        let (mut server, mut resume_ts) = (server(10), Some(5));
+        let events = apply_response(&mut server, &mut resume_ts, response(r#"{"failed":3}"#)).unwrap();
+        assert!(events.is_empty());
+        // Both key and ts are fetched again on next poll
+        assert_eq!(server, None);
+        assert_eq!(resume_ts, None);
+    }
+
+    #[test]
+    fn unknown_failure() {
+        let (mut server, mut resume_ts) = (server(10), None);
+        match apply_response(&mut server, &mut resume_ts, response(r#"{"failed":4,"min_version":0,"max_version":3}"#)) {
+            Err(Error::LongPoll(4)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(server.unwrap().ts, 10);
+    }
+
+    #[test]
+    fn key_expired_keeps_ts_visible() {
+        let api = Client::new();
+        let mut long_poll = LongPoll::new(&api, None);
+        long_poll.poller.server = server(10);
+        let poller = &mut long_poll.poller;
+        apply_response(&mut poller.server, &mut poller.resume_ts, response(r#"{"failed":2}"#)).unwrap();
+        assert_eq!(long_poll.ts(), Some(10));
+    }
+
+    #[test]
+    fn wait_is_capped() {
+        let api = Client::new();
+        let mut long_poll = LongPoll::new(&api, None);
+        long_poll.wait(600);
+        assert_eq!(long_poll.poller.wait, MAX_WAIT);
+        assert!(long_poll.poller.client().is_ok());
+
+        long_poll.wait(30);
+        assert_eq!(long_poll.poller.wait, 30);
+        assert!(long_poll.poller.client.is_none());
+    }
+
+    #[test]
+    fn group_id_resets_server() {
+        let api = Client::new();
+        let mut long_poll = LongPoll::new(&api, None);
+        long_poll.poller.server = server(10);
+        long_poll.group_id(Some(1));
+        assert_eq!(long_poll.ts(), None);
+    }
+}
@@ -1,13 +1,5 @@
 #![macro_use]
 
-macro_rules! qs {
-    ($($name:expr => $value:expr),+ $(,)*) => {
-        ::url::form_urlencoded::Serializer::new(String::new()).extend_pairs([
-            $(($name, $value)),*
-        ].into_iter().filter(|&&(_, v)| !v.is_empty())).finish()
-    }
-}
-
 macro_rules! expand_value_expr {
     ($this:ident; $param_name:ident; =) => {
         expand_value_expr!($this; $param_name; |value| *value)
@@ -31,6 +23,10 @@ macro_rules! expand_value_expr {
         expand_value_expr!($this; $param_name; |value|
                            if *value {"1"} else {"0"})
     };
+    ($this:ident; $param_name:ident; Option<bool>) => {
+        expand_value_expr!($this; $param_name; |value|
+                           value.map(|value| if value {"1"} else {"0"}).unwrap_or(""))
+    };
     ($this:ident; $param_name:ident; AsRef<Vec>) => {
         expand_value_expr!($this; $param_name; |value|
                            &*value.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(","))
@@ -39,6 +35,14 @@ macro_rules! expand_value_expr {
         expand_value_expr!($this; $param_name; |value|
                            &*value.as_ref().map(AsRef::as_ref).unwrap_or(""))
     };
+    ($this:ident; $param_name:ident; Time) => {
+        expand_value_expr!($this; $param_name; |value|
+                           &*::adapters::time::to_param(value))
+    };
+    ($this:ident; $param_name:ident; Option<Time>) => {
+        expand_value_expr!($this; $param_name; |value|
+                           &*::adapters::option_time::to_param(value))
+    };
     ($this:ident; $param_name:ident; Vec) => {
         expand_value_expr!($this; $param_name; |value|
                            &*value.iter().map(ToString::to_string).collect::<Vec<_>>().join(","))
@@ -53,6 +57,23 @@ macro_rules! expand_value_expr {
     };
 }
 
+macro_rules! append_param {
+    // Value expanding to several parameters, returned by its `to_params()` method
+    ($qs:ident; $this:ident; $param_alias:expr => $param_name:ident {Params}) => {
+        $qs.extend_pairs($this.$param_name.to_params());
+    };
+    ($qs:ident; $this:ident; $param_alias:expr => $param_name:ident {$($value:tt)*}) => {
+        match expand_value_expr!($this; $param_name; $($value)*) {
+            value => {
+                let value: &str = value;
+                if !value.is_empty() {
+                    $qs.append_pair($param_alias, value);
+                }
+            }
+        }
+    };
+}
+
 macro_rules! expand_init_expr {
     (()) => { Default::default() };
     ({}) => { Default::default() };
@@ -112,10 +133,10 @@ macro_rules! request_trait_impl {
         fn method_name() -> &'static str { $method_name }
         fn permissions() -> ::auth::Permissions { ::auth::Permissions::new($(::auth::Permission::$permission as i32 |)* 0) }
         fn to_query_string(&self) -> String {
-            qs![
-                $($param_alias => expand_value_expr!(self; $param_name; $($value)*),)*
-                $(stringify!($const_param_name) => concat!($const_param_value),)*
-            ]
+            let mut qs = ::url::form_urlencoded::Serializer::new(String::new());
+            $(append_param!(qs; self; $param_alias => $param_name {$($value)*});)*
+            $(qs.append_pair(stringify!($const_param_name), concat!($const_param_value));)*
+            qs.finish()
         }
     };
 
@@ -548,6 +569,12 @@ macro_rules! enum_str {
             }
         }
 
+        impl ::serde::ser::Serialize for $name {
+            fn serialize<S: ::serde::ser::Serializer>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error> {
+                s.serialize_str(self.as_ref())
+            }
+        }
+
         impl<'de> ::serde::de::Deserialize<'de> for $name {
             fn deserialize<D: ::serde::de::Deserializer<'de>>(d: D) -> ::std::result::Result<$name, D::Error> {
                 struct TempVisitor;
@@ -573,3 +600,16 @@ macro_rules! enum_str {
         }
     };
 }
+
+macro_rules! fields_request_impl {
+    ($field:ty; $($name:ident<$lt:tt> => $container:ident),+) => {
+        $(impl<$lt, T: ::api::Fields<Field = $field>> ::api::FieldsRequest<T> for $name<$lt> {
+            type Field = $field;
+            type Typed = $container<T>;
+
+            fn set_fields(&mut self, fields: &'static [$field]) {
+                self.fields = fields;
+            }
+        })+
+    }
+}
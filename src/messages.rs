@@ -0,0 +1,526 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use adapters::{self, Flag, Link, Time};
+use api::{self, Author, Bool, Collection, ExtendedRequest, Group, Id, Owner, OwnerId, RichCollection};
+use users::{User, UserOptionField};
+use wall::{Attachment, GeoLocation};
+
+/// Peer id offset for group chats: chat with `chat_id` has `peer_id == CHAT_PEER_ID_OFFSET + chat_id`
+pub const CHAT_PEER_ID_OFFSET: OwnerId = 2_000_000_000;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Message {
+    pub id: Id,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    /// Numeric rather than `Owner`, as besides users and communities it can be
+    /// a chat with `CHAT_PEER_ID_OFFSET + chat_id` id
+    pub peer_id: OwnerId,
+    pub from_id: Owner,
+    pub text: String,
+    #[serde(default)]
+    pub random_id: i32,
+    #[serde(default)]
+    pub conversation_message_id: Id,
+    #[serde(default, with = "adapters::flag")]
+    pub out: Flag,
+    #[serde(default)]
+    pub important: bool,
+    #[serde(default)]
+    pub is_hidden: bool,
+    #[serde(default, with = "adapters::option_time")]
+    pub update_time: Option<Time>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub fwd_messages: Vec<Message>,
+    pub reply_message: Option<Box<Message>>,
+    pub action: Option<MessageAction>,
+    pub geo: Option<GeoLocation>,
+    pub payload: Option<String>,
+}
+
+/// Service action in a chat, like member invitation or title change
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct MessageAction {
+    #[serde(rename="type")]
+    pub kind: MessageActionKind,
+    pub member_id: Option<Owner>,
+    pub text: Option<String>,
+    pub email: Option<String>,
+    pub photo: Option<ChatPhoto>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub enum MessageActionKind {
+    #[serde(rename="chat_photo_update")]
+    ChatPhotoUpdate,
+    #[serde(rename="chat_photo_remove")]
+    ChatPhotoRemove,
+    #[serde(rename="chat_create")]
+    ChatCreate,
+    #[serde(rename="chat_title_update")]
+    ChatTitleUpdate,
+    #[serde(rename="chat_invite_user")]
+    ChatInviteUser,
+    #[serde(rename="chat_kick_user")]
+    ChatKickUser,
+    #[serde(rename="chat_pin_message")]
+    ChatPinMessage,
+    #[serde(rename="chat_unpin_message")]
+    ChatUnpinMessage,
+    #[serde(rename="chat_invite_user_by_link")]
+    ChatInviteUserByLink,
+    /// Action of type not known to this library
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ChatPhoto {
+    #[serde(with = "adapters::link")]
+    pub photo_50: Link,
+    #[serde(with = "adapters::link")]
+    pub photo_100: Link,
+    #[serde(with = "adapters::link")]
+    pub photo_200: Link,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Conversation {
+    pub peer: Peer,
+    #[serde(default)]
+    pub in_read: Id,
+    #[serde(default)]
+    pub out_read: Id,
+    #[serde(default)]
+    pub unread_count: u32,
+    #[serde(default)]
+    pub important: bool,
+    #[serde(default)]
+    pub unanswered: bool,
+    pub can_write: Option<CanWrite>,
+    pub chat_settings: Option<ChatSettings>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct Peer {
+    /// Chat, user or community id, see `Message::peer_id`
+    pub id: OwnerId,
+    #[serde(rename="type")]
+    pub kind: PeerKind,
+    pub local_id: OwnerId,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub enum PeerKind {
+    #[serde(rename="user")]
+    User,
+    #[serde(rename="chat")]
+    Chat,
+    #[serde(rename="group")]
+    Group,
+    #[serde(rename="email")]
+    Email,
+    /// Peer of type not known to this library
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct CanWrite {
+    pub allowed: bool,
+    /// Error code explaining why writing is not allowed
+    pub reason: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct ChatSettings {
+    pub title: String,
+    #[serde(default)]
+    pub members_count: u32,
+    pub state: Option<String>,
+    pub photo: Option<ChatPhoto>,
+    #[serde(default)]
+    pub active_ids: Vec<OwnerId>,
+    pub pinned_message: Option<Message>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct ConversationItem {
+    pub conversation: Conversation,
+    pub last_message: Option<Message>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Conversations {
+    pub count: u32,
+    pub items: Vec<ConversationItem>,
+    #[serde(default)]
+    pub unread_count: u32,
+    #[serde(default)]
+    pub profiles: Vec<User>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+}
+
+impl Conversations {
+    /// Profile or community of `owner`
+    pub fn author<'a>(&'a self, owner: Owner) -> Option<Author<'a>> {
+        api::find_author(&self.profiles, &self.groups, owner)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct ConversationMember {
+    pub member_id: Owner,
+    #[serde(default)]
+    pub invited_by: Owner,
+    #[serde(default, with = "adapters::time")]
+    pub join_date: Time,
+    #[serde(default)]
+    pub is_admin: bool,
+    #[serde(default)]
+    pub is_owner: bool,
+    #[serde(default)]
+    pub can_kick: bool,
+}
+
+/// Generate value for `random_id` parameter of `messages.send`.
+///
+/// VK drops messages with the same `random_id` sent by the same user,
+/// so each new `SendMessage` request gets its own value, and a cloned request
+/// retried after a network error is not delivered twice.
+///
+/// The value is mixed from current time, a call counter and process id, so it is unique
+/// on a best-effort basis only: two requests can still get the same value by chance.
+/// Pass your own `random_id` if you need a guarantee, e.g. from a database sequence.
+pub fn random_id() -> i32 {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    mix_random_id(nanos, COUNTER.fetch_add(1, Ordering::SeqCst) as u32, ::std::process::id())
+}
+
+fn mix_random_id(nanos: u32, count: u32, pid: u32) -> i32 {
+    (nanos ^ count.wrapping_mul(0x9E37_79B9) ^ pid) as i32 & i32::MAX
+}
+
+request_ref! {
+    struct SendMessage for ["messages.send"](v => 5.92) -> Id [Messages] {
+        sized {
+            user_id: Option<Id> = () => {Option},
+            random_id: i32 = (random_id()) => {},
+            peer_id: Option<OwnerId> = () => {Option},
+            chat_id: Option<Id> = () => {Option},
+            lat: Option<f32> = () => {Option},
+            long: Option<f32> = () => {Option},
+            reply_to: Option<Id> = () => {Option},
+            sticker_id: Option<Id> = () => {Option},
+            group_id: Option<Id> = () => {Option},
+            dont_parse_links: bool = (false) => {bool},
+            disable_mentions: bool = (false) => {bool},
+        }
+        unsized {
+            domain: str = ("") => {=},
+            message: str = ("") => {=},
+            attachment: [api::Attachment] = (&[][..]) => {Vec},
+            forward_messages: [Id] = (&[][..]) => {Vec},
+            payload: str = ("") => {=},
+            keyboard: str = ("") => {=},
+        }
+    }
+}
+
+request_ref! {
+    struct GetHistory for ["messages.getHistory"](v => 5.92) -> Collection<Message> [Messages] {
+        sized {
+            peer_id: OwnerId = () => {},
+            user_id: Option<Id> = () => {Option},
+            // May be negative to get messages after `start_message_id`
+            offset: i32 = (0) => {},
+            count: usize = (20) => {},
+            start_message_id: Option<Id> = () => {Option},
+            rev: bool = (false) => {bool},
+            group_id: Option<Id> = () => {Option},
+        }
+        unsized {
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+impl<'a> ExtendedRequest for GetHistory<'a> {
+    type Extended = RichCollection<Message>;
+}
+
+request_ref! {
+    struct GetConversations for ["messages.getConversations"](v => 5.92) -> Conversations [Messages] {
+        sized {
+            offset: usize = (0) => {},
+            count: usize = (20) => {},
+            filter: ConversationFilter = (ConversationFilter::All) => {AsRef},
+            start_message_id: Option<Id> = () => {Option},
+            group_id: Option<Id> = () => {Option},
+        }
+        unsized {
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+impl<'a> ExtendedRequest for GetConversations<'a> {
+    type Extended = Conversations;
+}
+
+request_ref! {
+    struct GetById for ["messages.getById"](v => 5.92) -> Collection<Message> [Messages] {
+        sized {
+            preview_length: usize = (0) => {},
+            group_id: Option<Id> = () => {Option},
+        }
+        unsized {
+            message_ids: [Id] = (&[][..]) => {Vec},
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+impl<'a> ExtendedRequest for GetById<'a> {
+    type Extended = RichCollection<Message>;
+}
+
+request_ref! {
+    /// Response maps each message id to deletion result
+    struct Delete for ["messages.delete"](v => 5.92) -> HashMap<String, Bool> [Messages] {
+        sized {
+            spam: bool = (false) => {bool},
+            delete_for_all: bool = (false) => {bool},
+            group_id: Option<Id> = () => {Option},
+        }
+        unsized {
+            message_ids: [Id] = (&[][..]) => {Vec},
+        }
+    }
+}
+
+request_ref! {
+    struct Edit for ["messages.edit"](v => 5.92) -> Bool [Messages] {
+        sized {
+            peer_id: OwnerId = () => {},
+            message_id: Id = () => {},
+            lat: Option<f32> = () => {Option},
+            long: Option<f32> = () => {Option},
+            keep_forward_messages: bool = (false) => {bool},
+            keep_snippets: bool = (false) => {bool},
+            dont_parse_links: bool = (false) => {bool},
+            group_id: Option<Id> = () => {Option},
+        }
+        unsized {
+            message: str = ("") => {=},
+            attachment: [api::Attachment] = (&[][..]) => {Vec},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct MarkAsRead for ["messages.markAsRead"](v => 5.92) -> Bool [Messages] {
+        peer_id: OwnerId = () => {},
+        start_message_id: Option<Id> = () => {Option},
+        mark_conversation_as_read: bool = (false) => {bool},
+        group_id: Option<Id> = () => {Option},
+    }
+}
+
+request_ref! {
+    struct Search for ["messages.search"](v => 5.92) -> Collection<Message> [Messages] {
+        sized {
+            peer_id: Option<OwnerId> = () => {Option},
+            preview_length: usize = (0) => {},
+            offset: usize = (0) => {},
+            count: usize = (20) => {},
+            group_id: Option<Id> = () => {Option},
+        }
+        unsized {
+            q: str = ("") => {=},
+            // Search messages sent before this date, in `DDMMYYYY` format
+            date: str = ("") => {=},
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+impl<'a> ExtendedRequest for Search<'a> {
+    type Extended = RichCollection<Message>;
+}
+
+request_ref! {
+    struct GetConversationMembers for ["messages.getConversationMembers"](v => 5.92) -> RichCollection<ConversationMember> [Messages] {
+        sized {
+            peer_id: OwnerId = () => {},
+            group_id: Option<Id> = () => {Option},
+        }
+        unsized {
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+enum_str! { ConversationFilter {
+    All = "all",
+    Unread = "unread",
+    Important = "important",
+    Unanswered = "unanswered",
+}}
+
+impl Message {
+    /// Whether message was sent to a group chat rather than to a user or community
+    pub fn is_chat(&self) -> bool {
+        self.peer_id > CHAT_PEER_ID_OFFSET
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct LongPollServer {
+    pub key: String,
+    pub server: String,
+    pub ts: u64,
+    pub pts: Option<u64>,
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetLongPollServer for ["messages.getLongPollServer"](v => 5.92) -> LongPollServer [Messages] {
+        need_pts: bool = (false) => {bool},
+        group_id: Option<Id> = () => {Option},
+        lp_version: u32 = (3) => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use super::*;
+
+    const MESSAGE: &str = r#"{
+        "id": 2021,
+        "date": 1543924400,
+        "peer_id": 2000000003,
+        "from_id": 42,
+        "text": "See above",
+        "random_id": 7,
+        "conversation_message_id": 15,
+        "out": 1,
+        "important": false,
+        "is_hidden": false,
+        "update_time": 1543924500,
+        "attachments": [],
+        "fwd_messages": [
+            {"id": 0, "date": 1543924300, "peer_id": 0, "from_id": 43, "text": "Forwarded", "attachments": [], "fwd_messages": []}
+        ],
+        "reply_message": {"id": 2020, "date": 1543924350, "peer_id": 2000000003, "from_id": 43, "text": "Question",
+                          "conversation_message_id": 14, "attachments": [], "fwd_messages": []},
+        "action": {"type": "chat_invite_user", "member_id": 44}
+    }"#;
+
+    const CONVERSATIONS: &str = r#"{
+        "count": 2,
+        "unread_count": 1,
+        "items": [
+            {"conversation": {"peer": {"id": 2000000003, "type": "chat", "local_id": 3},
+                              "in_read": 2021, "out_read": 2021, "unread_count": 1,
+                              "can_write": {"allowed": true},
+                              "chat_settings": {"title": "Chat", "members_count": 5, "state": "in", "active_ids": [42, 43]}},
+             "last_message": {"id": 2021, "date": 1543924400, "peer_id": 2000000003, "from_id": 42, "text": "See above",
+                              "out": 1, "attachments": [], "fwd_messages": []}},
+            {"conversation": {"peer": {"id": -1, "type": "group", "local_id": 1},
+                              "can_write": {"allowed": false, "reason": 203}}}
+        ],
+        "profiles": [{"id": 42, "first_name": "Pavel", "last_name": "Durov"}],
+        "groups": []
+    }"#;
+
+    #[test]
+    fn message_round_trip() {
+        let message: Message = serde_json::from_str(MESSAGE).unwrap();
+        assert_eq!(message.out, adapters::flag::from_bool(true));
+        assert_eq!(message.fwd_messages[0].update_time, None);
+
+        let value = serde_json::to_value(&message).unwrap();
+        assert_eq!(value["date"], 1543924400);
+        assert_eq!(value["out"], 1);
+        assert_eq!(value["update_time"], 1543924500);
+        assert_eq!(value["fwd_messages"][0]["out"], 0);
+        assert_eq!(value["reply_message"]["id"], 2020);
+        assert_eq!(value["action"]["type"], "chat_invite_user");
+        assert_eq!(serde_json::from_value::<Message>(value).unwrap(), message);
+    }
+
+    #[test]
+    fn conversations_round_trip() {
+        let conversations: Conversations = serde_json::from_str(CONVERSATIONS).unwrap();
+        let value = serde_json::to_value(&conversations).unwrap();
+        assert_eq!(value["items"][0]["conversation"]["peer"]["type"], "chat");
+        assert_eq!(value["items"][0]["last_message"]["out"], 1);
+        assert_eq!(value["items"][1]["conversation"]["can_write"]["reason"], 203);
+        assert_eq!(serde_json::from_value::<Conversations>(value).unwrap(), conversations);
+    }
+
+    #[test]
+    fn message_with_forwards_and_reply() {
+        let message: Message = serde_json::from_str(MESSAGE).unwrap();
+        assert!(message.is_chat());
+        assert_eq!(message.fwd_messages.len(), 1);
+        assert_eq!((message.fwd_messages[0].from_id, &*message.fwd_messages[0].text), (Owner::User(43), "Forwarded"));
+        let reply = message.reply_message.as_ref().unwrap();
+        assert_eq!((reply.id, reply.conversation_message_id, &*reply.text), (2020, 14, "Question"));
+        assert_eq!(reply.reply_message, None);
+        let action = message.action.as_ref().unwrap();
+        assert_eq!((action.kind, action.member_id), (MessageActionKind::ChatInviteUser, Some(Owner::User(44))));
+    }
+
+    #[test]
+    fn conversations() {
+        let conversations: Conversations = serde_json::from_str(CONVERSATIONS).unwrap();
+        assert_eq!((conversations.count, conversations.unread_count), (2, 1));
+
+        let chat = &conversations.items[0];
+        assert_eq!(chat.conversation.peer, Peer { id: 2000000003, kind: PeerKind::Chat, local_id: 3 });
+        let settings = chat.conversation.chat_settings.as_ref().unwrap();
+        assert_eq!((&*settings.title, settings.members_count, &*settings.active_ids), ("Chat", 5, &[42, 43][..]));
+        let last = chat.last_message.as_ref().unwrap();
+        match conversations.author(last.from_id) {
+            Some(Author::Profile(user)) => assert_eq!(user.first_name, "Pavel"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let group = &conversations.items[1];
+        assert_eq!(group.conversation.peer.kind, PeerKind::Group);
+        assert_eq!(group.conversation.can_write, Some(CanWrite { allowed: false, reason: Some(203) }));
+        assert_eq!(group.last_message, None);
+    }
+
+    #[test]
+    fn unknown_action_and_peer_kinds() {
+        let action: MessageAction = serde_json::from_str(r#"{"type": "chat_screenshot", "member_id": 42}"#).unwrap();
+        assert_eq!((action.kind, action.member_id), (MessageActionKind::Unknown, Some(Owner::User(42))));
+
+        let peer: Peer = serde_json::from_str(r#"{"id": 7, "type": "contact", "local_id": 7}"#).unwrap();
+        assert_eq!(peer.kind, PeerKind::Unknown);
+    }
+
+    #[test]
+    fn random_ids_differ_by_counter() {
+        assert!(random_id() >= 0);
+
+        // Calls in the same nanosecond are told apart by counter
+        let ids: Vec<i32> = (0..100).map(|count| mix_random_id(123_456_789, count, 4242)).collect();
+        assert!(ids.iter().all(|&id| id >= 0));
+        let mut unique = ids.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), ids.len());
+    }
+}
@@ -0,0 +1,556 @@
+use std::borrow::Borrow;
+use adapters::{self, Flag, Time};
+use api::{self, Author, Bool, Client, Collection, Group, Id, LikesCount, Owner, OwnerId, Request, Result};
+use audio::Audio;
+use auth::AccessToken;
+use photos::Photo;
+use users::{User, UserOptionField};
+use video::Video;
+use wall::{Attachment, CommentsCount, PostType, RepostInfo, RepostsCount, WallComment, WallPost};
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Newsfeed {
+    pub items: Vec<NewsfeedItem>,
+    #[serde(default)]
+    pub profiles: Vec<User>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    pub next_from: Option<String>,
+}
+
+impl Newsfeed {
+    /// Profile or community of `owner`
+    pub fn author<'a>(&'a self, owner: Owner) -> Option<Author<'a>> {
+        api::find_author(&self.profiles, &self.groups, owner)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[serde(tag="type")]
+pub enum NewsfeedItem {
+    #[serde(rename="post")]
+    Post(NewsfeedPost),
+    #[serde(rename="photo")]
+    Photo(NewsfeedPhotos),
+    #[serde(rename="photo_tag")]
+    PhotoTag(NewsfeedPhotoTags),
+    #[serde(rename="wall_photo")]
+    WallPhoto(NewsfeedPhotos),
+    #[serde(rename="friend")]
+    Friend(NewsfeedFriends),
+    #[serde(rename="note")]
+    Note(NewsfeedNotes),
+    #[serde(rename="audio")]
+    Audio(NewsfeedAudio),
+    #[serde(rename="video")]
+    Video(NewsfeedVideo),
+    /// Item of type not known to this library
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct NewsfeedPost {
+    pub source_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    pub post_id: Id,
+    pub post_type: Option<PostType>,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default, with = "adapters::flag")]
+    pub marked_as_ads: Flag,
+    pub copy_history: Option<Vec<RepostInfo>>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    pub comments: Option<CommentsCount>,
+    pub likes: Option<LikesCount>,
+    pub reposts: Option<RepostsCount>,
+    pub signer_id: Option<Id>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct NewsfeedPhotos {
+    pub source_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    pub photos: Collection<Photo>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct NewsfeedPhotoTags {
+    pub source_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    pub photo_tags: Collection<Photo>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct NewsfeedFriends {
+    pub source_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    pub friends: Collection<FriendId>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct FriendId {
+    pub user_id: Id,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct NewsfeedNotes {
+    pub source_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    pub notes: Collection<Note>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Note {
+    pub id: Id,
+    pub owner_id: Option<Owner>,
+    pub title: String,
+    #[serde(default)]
+    pub comments: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct NewsfeedAudio {
+    pub source_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    pub audio: Collection<Audio>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct NewsfeedVideo {
+    pub source_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    pub video: Collection<Video>,
+}
+
+/// Response of `newsfeed.getComments`: commented objects with their last comments
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct CommentsFeed {
+    pub items: Vec<CommentedItem>,
+    #[serde(default)]
+    pub profiles: Vec<User>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    pub next_from: Option<String>,
+}
+
+impl CommentsFeed {
+    /// Profile or community of `owner`
+    pub fn author<'a>(&'a self, owner: Owner) -> Option<Author<'a>> {
+        api::find_author(&self.profiles, &self.groups, owner)
+    }
+}
+
+/// Post, photo, video, topic or note in comments feed.
+///
+/// Unlike `NewsfeedItem`, it has no `photos` or `video` collections,
+/// just the commented object id and its last comments.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct CommentedItem {
+    #[serde(rename="type")]
+    pub kind: CommentedKind,
+    pub source_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    /// Commented object id
+    #[serde(default)]
+    pub post_id: Id,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    pub comments: Option<ItemComments>,
+    pub likes: Option<LikesCount>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub enum CommentedKind {
+    #[serde(rename="post")]
+    Post,
+    #[serde(rename="photo")]
+    Photo,
+    #[serde(rename="video")]
+    Video,
+    #[serde(rename="topic")]
+    Topic,
+    #[serde(rename="note")]
+    Note,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Comments count and last comments of `CommentedItem`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ItemComments {
+    pub count: u32,
+    #[serde(default, with = "adapters::flag")]
+    pub can_post: Flag,
+    #[serde(default)]
+    pub list: Vec<WallComment>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct SearchResult {
+    pub items: Vec<WallPost>,
+    #[serde(default)]
+    pub count: u32,
+    #[serde(default)]
+    pub total_count: u32,
+    #[serde(default)]
+    pub profiles: Vec<User>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    pub next_from: Option<String>,
+}
+
+impl SearchResult {
+    /// Profile or community of `owner`
+    pub fn author<'a>(&'a self, owner: Owner) -> Option<Author<'a>> {
+        api::find_author(&self.profiles, &self.groups, owner)
+    }
+}
+
+/// Wall post current user was mentioned in
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Mention {
+    pub id: Id,
+    pub from_id: Owner,
+    pub to_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    #[serde(default)]
+    pub text: String,
+    pub post_type: Option<PostType>,
+    pub comments: Option<CommentsCount>,
+    pub likes: Option<LikesCount>,
+    pub reposts: Option<RepostsCount>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct NewsfeedList {
+    pub id: Id,
+    pub title: String,
+    #[serde(default, with = "adapters::flag")]
+    pub no_reposts: Flag,
+    #[serde(default)]
+    pub source_ids: Vec<OwnerId>,
+}
+
+request_ref! {
+    struct Get for ["newsfeed.get"](v => 5.44) -> Newsfeed [Wall, Friends] {
+        sized {
+            return_banned: bool = (false) => {bool},
+            start_time: Option<Time> = () => {Option<Time>},
+            end_time: Option<Time> = () => {Option<Time>},
+            max_photos: Option<u8> = () => {Option},
+            start_from: String = {} => {|value| value.as_str()},
+            count: usize = (50) => {},
+        }
+        unsized {
+            filters: [NewsfeedFilter] = (&[][..]) => {AsRef<Vec>},
+            // Comma separated list of sources, like "u123,g456,list7,friends,groups,pages,following"
+            source_ids: str = ("") => {=},
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request_ref! {
+    struct GetRecommended for ["newsfeed.getRecommended"](v => 5.44) -> Newsfeed [Wall, Friends] {
+        sized {
+            start_time: Option<Time> = () => {Option<Time>},
+            end_time: Option<Time> = () => {Option<Time>},
+            max_photos: Option<u8> = () => {Option},
+            start_from: String = {} => {|value| value.as_str()},
+            count: usize = (50) => {},
+        }
+        unsized {
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request_ref! {
+    struct Search for ["newsfeed.search"](v => 5.44) -> SearchResult {
+        sized {
+            extended: bool = (false) => {bool},
+            count: usize = (30) => {},
+            latitude: Option<f32> = () => {Option},
+            longitude: Option<f32> = () => {Option},
+            start_time: Option<Time> = () => {Option<Time>},
+            end_time: Option<Time> = () => {Option<Time>},
+            start_from: String = {} => {|value| value.as_str()},
+        }
+        unsized {
+            q: str = ("") => {=},
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request_ref! {
+    struct GetComments for ["newsfeed.getComments"](v => 5.44) -> CommentsFeed [Wall, Friends] {
+        sized {
+            count: usize = (30) => {},
+            start_time: Option<Time> = () => {Option<Time>},
+            end_time: Option<Time> = () => {Option<Time>},
+            last_comments_count: Option<u8> = () => {Option},
+            start_from: String = {} => {|value| value.as_str()},
+        }
+        unsized {
+            filters: [CommentsFilter] = (&[][..]) => {AsRef<Vec>},
+            // Comma separated list of reposts, like "wall123_456,photo-123_456"
+            reposts: str = ("") => {=},
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetMentions for ["newsfeed.getMentions"](v => 5.44) -> Collection<Mention> [Wall] {
+        owner_id: Option<Owner> = {} => {Option},
+        start_time: Option<Time> = () => {Option<Time>},
+        end_time: Option<Time> = () => {Option<Time>},
+        offset: usize = (0) => {},
+        count: usize = (20) => {},
+    }
+}
+
+request_ref! {
+    struct GetLists for ["newsfeed.getLists"](v => 5.44) -> Collection<NewsfeedList> [Wall, Friends] {
+        sized {
+            extended: bool = (false) => {bool},
+        }
+        unsized {
+            list_ids: [Id] = (&[][..]) => {Vec},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct IgnoreItem for ["newsfeed.ignoreItem"](v => 5.44) -> Bool [Wall, Friends] {
+        kind as ("type"): IgnoreKind = (IgnoreKind::Wall) => {AsRef},
+        owner_id as ("owner_id"): Owner = () => {},
+        item_id as ("item_id"): Id = () => {},
+    }
+}
+
+enum_str! { NewsfeedFilter {
+    Post = "post",
+    Photo = "photo",
+    PhotoTag = "photo_tag",
+    WallPhoto = "wall_photo",
+    Friend = "friend",
+    Note = "note",
+    Audio = "audio",
+    Video = "video",
+}}
+
+enum_str! { CommentsFilter {
+    Post = "post",
+    Photo = "photo",
+    Video = "video",
+    Topic = "topic",
+    Note = "note",
+}}
+
+enum_str! { IgnoreKind {
+    Wall = "wall",
+    Tag = "tag",
+    ProfilePhoto = "profilephoto",
+    Video = "video",
+    Photo = "photo",
+    Audio = "audio",
+}}
+
+/// Request paginated with `start_from` cursor instead of offset
+pub trait CursorRequest: Request + Clone {
+    fn start_from(&self) -> &str;
+    fn set_start_from(&mut self, cursor: String);
+    fn next_from(response: &Self::Response) -> Option<&str>;
+}
+
+macro_rules! cursor_request_impl {
+    ($($name:ident<$lt:tt>),+) => {
+        $(impl<$lt> CursorRequest for $name<$lt> {
+            fn start_from(&self) -> &str {
+                &self.start_from
+            }
+
+            fn set_start_from(&mut self, cursor: String) {
+                self.start_from = cursor;
+            }
+
+            fn next_from(response: &Self::Response) -> Option<&str> {
+                response.next_from.as_ref().map(|next_from| &**next_from)
+            }
+        })+
+    }
+}
+
+cursor_request_impl!(Get<'a>, GetRecommended<'a>, Search<'a>, GetComments<'a>);
+
+/// Iterator over pages of cursor paginated request, see `pages()`
+pub struct Pages<'a, R> {
+    api: &'a Client,
+    token: Option<&'a AccessToken>,
+    request: R,
+    done: bool,
+}
+
+/// Iterate over all pages of `request` results, starting from its `start_from` cursor.
+///
+/// Iteration stops after the last page, the first error, or when VK returns the same cursor again.
+pub fn pages<'a, R: CursorRequest>(api: &'a Client, token: Option<&'a AccessToken>, request: &R) -> Pages<'a, R> {
+    Pages {
+        api,
+        token,
+        request: request.clone(),
+        done: false,
+    }
+}
+
+impl<'a, R: CursorRequest> Iterator for Pages<'a, R> {
+    type Item = Result<R::Response>;
+
+    fn next(&mut self) -> Option<Result<R::Response>> {
+        if self.done {
+            return None;
+        }
+
+        let page = self.api.call(self.token, &self.request);
+        self.done = !advance(&mut self.request, &page);
+        Some(page)
+    }
+}
+
+/// Move `request` cursor to the page after `page`, returning `false` if there is none:
+/// on error, on empty cursor, or on the same cursor as the current one
+fn advance<R: CursorRequest>(request: &mut R, page: &Result<R::Response>) -> bool {
+    match *page {
+        Ok(ref page) => match R::next_from(page) {
+            Some(next_from) if !next_from.is_empty() && next_from != request.start_from() => {
+                request.set_start_from(next_from.to_owned());
+                true
+            }
+            _ => false,
+        },
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use super::*;
+
+    const COMMENTS: &str = r#"{
+        "items": [
+            {"type": "post", "source_id": -1, "date": 1500000000, "post_id": 10, "text": "Release notes",
+             "comments": {"count": 1, "can_post": 1, "list": [
+                {"id": 11, "from_id": 5, "date": 1500000001, "text": "Thanks"}
+             ]},
+             "likes": {"user_likes": 0, "count": 15}},
+            {"type": "photo", "source_id": 5, "date": 1500000002, "post_id": 20,
+             "comments": {"count": 0}},
+            {"type": "video", "source_id": -1, "date": 1500000003, "post_id": 30},
+            {"type": "market", "source_id": -1, "date": 1500000004}
+        ],
+        "profiles": [{"id": 5, "first_name": "Pavel", "last_name": "Durov", "screen_name": "durov", "online": 0}],
+        "groups": [],
+        "next_from": "10/5_-1_30"
+    }"#;
+
+    #[test]
+    fn comments_feed_without_media_collections() {
+        let feed: CommentsFeed = serde_json::from_str(COMMENTS).unwrap();
+        let kinds: Vec<CommentedKind> = feed.items.iter().map(|item| item.kind).collect();
+        assert_eq!(kinds, [CommentedKind::Post, CommentedKind::Photo, CommentedKind::Video, CommentedKind::Unknown]);
+        assert_eq!(feed.items[0].comments.as_ref().unwrap().list[0].from_id, Owner::User(5));
+        assert_eq!(feed.items[1].post_id, 20);
+        assert_eq!(feed.next_from.as_deref(), Some("10/5_-1_30"));
+        match feed.author(feed.items[1].source_id) {
+            Some(Author::Profile(user)) => assert_eq!(user.screen_name.as_deref(), Some("durov")),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(feed.author(feed.items[0].source_id), None);
+    }
+
+    const NEWSFEED: &str = r#"{
+        "items": [
+            {"type": "post", "source_id": -1, "date": 1500000000, "post_id": 10, "post_type": "post",
+             "text": "Release notes", "marked_as_ads": 1,
+             "comments": {"count": 2, "can_post": 1},
+             "likes": {"user_likes": 1, "count": 15},
+             "reposts": {"count": 1, "user_reposted": 0}},
+            {"type": "friend", "source_id": 5, "date": 1500000001, "friends": {"count": 1, "items": [{"user_id": 6}]}},
+            {"type": "audio", "source_id": 5, "date": 1500000002, "audio": {"count": 1, "items": [
+                {"id": 3, "owner_id": 5, "artist": "Artist", "title": "Title", "duration": 215, "date": 1500000002}
+            ]}}
+        ],
+        "profiles": [{"id": 5, "first_name": "Pavel", "last_name": "Durov"}],
+        "groups": [],
+        "next_from": "5/5_-1_10"
+    }"#;
+
+    #[test]
+    fn newsfeed_round_trip() {
+        let feed: Newsfeed = serde_json::from_str(NEWSFEED).unwrap();
+        match feed.items[0] {
+            NewsfeedItem::Post(ref post) => {
+                assert_eq!((post.source_id, post.marked_as_ads), (Owner::Group(1), adapters::flag::from_bool(true)));
+                assert_eq!(adapters::time::to_param(&post.date), "1500000000");
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+
+        let value = serde_json::to_value(&feed).unwrap();
+        assert_eq!(value["items"][0]["type"], "post");
+        assert_eq!(value["items"][0]["source_id"], -1);
+        assert_eq!(value["items"][0]["date"], 1500000000);
+        assert_eq!(value["items"][0]["marked_as_ads"], 1);
+        assert_eq!(value["items"][0]["likes"]["user_likes"], 1);
+        assert_eq!(value["items"][1]["type"], "friend");
+        assert_eq!(value["items"][1]["friends"]["items"][0]["user_id"], 6);
+        assert_eq!(value["items"][2]["audio"]["items"][0]["date"], 1500000002);
+        assert_eq!(serde_json::from_value::<Newsfeed>(value).unwrap(), feed);
+    }
+
+    fn page(next_from: Option<&str>) -> Result<Newsfeed> {
+        Ok(Newsfeed { items: Vec::new(), profiles: Vec::new(), groups: Vec::new(), next_from: next_from.map(ToOwned::to_owned) })
+    }
+
+    #[test]
+    fn cursor_advances_until_last_page() {
+        let mut request = Get::new();
+        assert!(advance(&mut request, &page(Some("10/5_-1_30"))));
+        assert_eq!(request.start_from(), "10/5_-1_30");
+        assert!(advance(&mut request, &page(Some("20/5_-1_20"))));
+        assert_eq!(request.start_from(), "20/5_-1_20");
+
+        // Last page has no cursor or an empty one
+        assert!(!advance(&mut request.clone(), &page(None)));
+        assert!(!advance(&mut request, &page(Some(""))));
+        assert_eq!(request.start_from(), "20/5_-1_20");
+    }
+
+    #[test]
+    fn cursor_stops_on_repeat_and_error() {
+        let mut request = Get::new();
+        request.set_start_from("10/5_-1_30".to_owned());
+        assert!(!advance(&mut request, &page(Some("10/5_-1_30"))));
+        assert!(!advance(&mut request, &Err(api::Error::LongPoll(1))));
+        assert_eq!(request.start_from(), "10/5_-1_30");
+    }
+}
+
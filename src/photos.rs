@@ -1,6 +1,28 @@
 use std::borrow::Borrow;
-use api::{Attachment, Bool, Collection, Comment, FullId, Id, OwnerId, ReportReason, SortOrder, Timestamp};
+use serde::de;
+use adapters::{self, Flag, Link, Time};
+use api::{self, Attachment, AttachmentKind, Bool, Client, Collection, Comment, ExtendedRequest, FullId, Id, Owner, ReportReason, RichCollection,
+          SortOrder};
+use auth::AccessToken;
+use upload::{Form, Progress, UploadError, UploadFile, UploadResult};
+use std::cmp;
 use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem;
+use std::path::Path;
+
+/// Maximum number of photos VK accepts in a single `photos.save` batch
+pub const MAX_UPLOAD_BATCH: usize = 5;
+
+/// Minimum owner photo (avatar) width and height
+pub const OWNER_PHOTO_MIN_SIZE: (u32, u32) = (200, 200);
+/// Minimum market product photo width and height
+pub const MARKET_PHOTO_MIN_SIZE: (u32, u32) = (400, 400);
+/// Minimum market album photo width and height
+pub const MARKET_ALBUM_PHOTO_MIN_SIZE: (u32, u32) = (1280, 720);
+/// Maximum sum of market photo width and height
+pub const MARKET_PHOTO_MAX_SIZE_SUM: u32 = 14000;
 
 request_ref! {
     #[derive(Eq, Copy)]
@@ -26,7 +48,7 @@ request_ref! {
     struct EditAlbum for ["photos.editAlbum"](v => 5.45) -> Bool [Photos] {
         sized {
             album_id: Id = () => {},
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             upload_by_admins_only: bool = () => {bool},
             comments_disabled: bool = () => {bool},
         }
@@ -45,7 +67,7 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct GetAlbums for ["photos.getAlbums"](v => 5.45) -> Collection<Album> {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             need_system: bool = () => {bool},
             need_covers: bool = () => {bool},
             photo_sizes: bool = () => {bool},
@@ -62,7 +84,7 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct Get for ["photos.get"](v => 5.45, photo_sizes => 1) -> Collection<Photo> {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             album_id: Option<Id> = () => {Option}, // TODO: can be negative or string (wall, profile, saved)
             rev: bool = () => {bool},
             extended: bool = () => {bool},
@@ -108,7 +130,7 @@ request! {
 request! {
     #[derive(Eq, Copy)]
     struct GetOwnerPhotoUploadServer for ["photos.getOwnerPhotoUploadServer"](v => 5.45) -> UploadUrl {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
     }
 }
 
@@ -127,9 +149,9 @@ request! {
     struct GetMarketUploadServer for ["photos.getMarketUploadServer"](v => 5.45) -> UploadUrl [Market, Photos] {
         group_id: Id = () => {},
         main_photo: bool = () => {bool},
-        crop_x: u32 = () => {},
-        crop_y: u32 = () => {},
-        crop_width: u32 = () => {},
+        crop_x: Option<u32> = () => {Option},
+        crop_y: Option<u32> = () => {Option},
+        crop_width: Option<u32> = () => {Option},
     }
 }
 
@@ -148,12 +170,15 @@ request! {
 }
 
 request! {
-    struct GetMessagesUploadServer for ["photos.getMessagesUploadServer"](v => 5.45) -> UploadServer [Photos];
+    #[derive(Eq, Copy)]
+    struct GetMessagesUploadServer for ["photos.getMessagesUploadServer"](v => 5.45) -> UploadServer [Photos] {
+        peer_id: Option<Owner> = {} => {Option},
+    }
 }
 
 request_ref! {
     #[derive(Eq, Copy)]
-    struct SaveMarketPhoto for ["photos.saveMarketPhoto"](v => 5.45) -> SavedPhoto [Market, Photos] { // TODO ???
+    struct SaveMarketPhoto for ["photos.saveMarketPhoto"](v => 5.45) -> Vec<Photo> [Market, Photos] {
         sized {
             group_id: Option<Id> = () => {Option},
             server: Id = () => {},
@@ -169,7 +194,7 @@ request_ref! {
 
 request_ref! {
     #[derive(Eq, Copy)]
-    struct SaveMarketAlbumPhoto for ["photos.saveMarketAlbumPhoto"](v => 5.45) -> SavedPhoto [Market, Photos] { // TODO ???
+    struct SaveMarketAlbumPhoto for ["photos.saveMarketAlbumPhoto"](v => 5.45) -> Vec<Photo> [Market, Photos] {
         sized {
             group_id: Id = () => {},
             server: Id = () => {},
@@ -183,7 +208,7 @@ request_ref! {
 
 request_ref! {
     #[derive(Eq, Copy)]
-    struct SaveOwnerPhoto for ["photos.saveOwnerPhoto"](v => 5.45) -> SavedPhoto { // TODO ???
+    struct SaveOwnerPhoto for ["photos.saveOwnerPhoto"](v => 5.45) -> SavedPhoto {
         sized {
             server: Id = () => {}, // TODO string???
         }
@@ -195,31 +220,40 @@ request_ref! {
 }
 
 request_ref! {
-    #[derive(Eq, Copy)]
-    struct SaveWallPhoto for ["photos.saveWallPhoto"](v => 5.45) -> Vec<Photo> {
+    #[derive(Copy)]
+    struct SaveWallPhoto for ["photos.saveWallPhoto"](v => 5.45) -> Vec<Photo> [Photos] {
         sized {
-            user_id: Id = () => {},
-            group_id: Id = () => {},
-            server: Id = () => {}, // TODO string???
+            user_id: Option<Id> = () => {Option},
+            group_id: Option<Id> = () => {Option},
+            server: Id = () => {},
+            latitude: Option<f32> = () => {Option},
+            longitude: Option<f32> = () => {Option},
         }
         unsized {
             photo: str = ("") => {=},
             hash: str = ("") => {=},
+            caption: str = ("") => {=},
         }
     }
 }
 
 request_ref! {
     #[derive(Eq, Copy)]
-    struct SaveMessagesPhoto for ["photos.saveMessagesPhoto"](v => 5.45) -> SavedPhoto [Photos] {
-        photo: str = ("") => {=},
+    struct SaveMessagesPhoto for ["photos.saveMessagesPhoto"](v => 5.45) -> Vec<Photo> [Photos] {
+        sized {
+            server: Id = () => {},
+        }
+        unsized {
+            photo: str = ("") => {=},
+            hash: str = ("") => {=},
+        }
     }
 }
 
 request! {
     #[derive(Eq, Copy)]
     struct Report for ["photos.report"](v => 5.44) -> Bool [Photos] {
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         photo_id: Id = () => {},
         reason: ReportReason = () => {AsRef},
     }
@@ -228,7 +262,7 @@ request! {
 request! {
     #[derive(Eq, Copy)]
     struct ReportComment for ["photos.reportComment"](v => 5.44) -> Bool [Photos] {
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         comment_id: Id = () => {},
         reason: ReportReason = () => {AsRef},
     }
@@ -240,8 +274,8 @@ request_ref! {
         sized {
             lat: f32 = () => {},
             long: f32 = () => {},
-            start_time: Timestamp = () => {},
-            end_time: Timestamp = () => {},
+            start_time: Time = () => {Time},
+            end_time: Time = () => {Time},
             sort: Sort = () => {AsRef},
             offset: usize = (0) => {},
             count: usize = (30) => {},
@@ -260,8 +294,8 @@ request_ref! {
             server: Id = () => {},
             album_id: Option<Id> = () => {Option},
             group_id: Option<Id> = () => {Option},
-            latitude: f32 = () => {},
-            longitude: f32 = () => {},
+            latitude: Option<f32> = () => {Option},
+            longitude: Option<f32> = () => {Option},
         }
         unsized {
             photos_list: str = ("") => {=},
@@ -275,7 +309,7 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct Copy for ["photos.copy"](v => 5.44) -> Id [Photos] {
         sized {
-            owner_id: OwnerId = () => {},
+            owner_id: Owner = () => {},
             photo_id: Id = () => {},
         }
         unsized {
@@ -288,7 +322,7 @@ request_ref! {
     #[derive(Copy)]
     struct Edit for ["photos.edit"](v => 5.44) -> Bool [Photos] {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             photo_id: Id = () => {},
             latitude: f32 = () => {},
             longitude: f32 = () => {},
@@ -305,7 +339,7 @@ request_ref! {
 request! {
     #[derive(Eq, Copy)]
     struct Move for ["photos.move"](v => 5.44) -> Bool [Photos] {
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         photo_id: Id = () => {},
         target_album_id: Id = () => {},
     }
@@ -314,7 +348,7 @@ request! {
 request! {
     #[derive(Eq, Copy)]
     struct MakeCover for ["photos.makeCover"](v => 5.44) -> Bool [Photos] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         photo_id: Id = () => {},
         album_id: Id = () => {},
     }
@@ -322,7 +356,7 @@ request! {
 
 request! {
     struct ReorderAlbums for ["photos.reorderAlbums"](v => 5.44) -> Bool [Photos] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         album_id: Id = () => {},
         before: Option<Id> = () => {Option},
         after: Option<Id> = () => {Option},
@@ -332,7 +366,7 @@ request! {
 request! {
     #[derive(Eq, Copy)]
     struct ReorderPhotos for ["photos.reorderPhotos"](v => 5.44) -> Bool [Photos] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         photo_id: Id = () => {},
         before: Option<Id> = () => {Option},
         after: Option<Id> = () => {Option},
@@ -342,7 +376,7 @@ request! {
 request! {
     #[derive(Eq, Copy)]
     struct GetAll for ["photos.getAll"](v => 5.45, photo_sizes => 1) -> Collection<Photo> [Photos] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         extended: bool = () => {bool},
         offset: usize = (0) => {},
         count: usize = (20) => {},
@@ -375,14 +409,14 @@ request! {
     #[derive(Eq, Copy)]
     struct Delete for ["photos.delete"](v => 5.44) -> Bool [Photos] {
         photo_id: Id = () => {},
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
     }
 }
 
 request! {
     #[derive(Eq, Copy)]
     struct ConfirmTag for ["photos.confirmTag"](v => 5.44) -> Bool [Photos] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         photo_id: Id = () => {},
         tag_id: Id = () => {},
     }
@@ -390,9 +424,9 @@ request! {
 
 request_ref! {
     #[derive(Eq, Copy)]
-    struct GetComments for ["photos.getComments"](v => 5.44, extended => 0) -> Collection<Comment> [Photos] {
+    struct GetComments for ["photos.getComments"](v => 5.44) -> Collection<Comment> [Photos] {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             photo_id: Id = () => {},
             need_likes: bool = () => {bool},
             start_comment_id: Option<Id> = () => {Option},
@@ -407,10 +441,14 @@ request_ref! {
     }
 }
 
+impl<'a> ExtendedRequest for GetComments<'a> {
+    type Extended = RichCollection<Comment>;
+}
+
 request! {
     #[derive(Eq, Copy)]
     struct GetAllComments for ["photos.getAllComments"](v => 5.44) -> Collection<Comment> [Photos] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         album_id: Option<Id> = () => {Option},
         need_likes: bool = () => {bool},
         offset: usize = (0) => {},
@@ -422,7 +460,7 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct CreateComment for ["photos.createComment"](v => 5.44) -> Id [Photos] {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             photo_id: Id = () => {},
             from_group: bool = () => {bool},
             reply_to_comment: Option<Id> = () => {Option},
@@ -440,7 +478,7 @@ request_ref! {
 request! {
     #[derive(Eq, Copy)]
     struct DeleteComment for ["photos.deleteComment"](v => 5.44) -> Bool [Photos] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         comment_id: Id = () => {},
     }
 }
@@ -448,7 +486,7 @@ request! {
 request! {
     #[derive(Eq, Copy)]
     struct Restore for ["photos.restore"](v => 5.44) -> Bool [Photos] {
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         photo_id: Id = () => {},
     }
 }
@@ -456,7 +494,7 @@ request! {
 request! {
     #[derive(Eq, Copy)]
     struct RestoreComment for ["photos.restoreComment"](v => 5.44) -> Bool [Photos] {
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         comment_id: Id = () => {},
     }
 }
@@ -465,7 +503,7 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct EditComment for ["photos.editComment"](v => 5.44) -> Bool [Photos] {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             comment_id: Id = () => {},
         }
         unsized {
@@ -479,7 +517,7 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct GetTags for ["photos.getTags"](v => 5.44) -> Vec<Tag> [Photos] {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             photo_id: Id = () => {},
         }
         unsized {
@@ -491,7 +529,7 @@ request_ref! {
 request! {
     #[derive(Copy)]
     struct PutTag for ["video.putTag"](v => 5.44) -> Id [Photos] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         user_id: Id = () => {},
         photo_id: Id = () => {},
         x: f32 = () => {},
@@ -505,7 +543,7 @@ request! {
     #[derive(Eq, Copy)]
     struct RemoveTag for ["photos.removeTag"](v => 5.44) -> Bool [Photos] {
         tag_id: Id = () => {},
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         photo_id: Id = () => {},
     }
 }
@@ -518,59 +556,80 @@ request! {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Photo {
     pub id: Id,
-    pub album_id: Id,
-    pub owner_id: OwnerId,
+    pub album_id: i64, // negative for system albums (wall, saved, messages)
+    pub owner_id: Owner,
+    #[serde(default)]
     pub user_id: Id,
+    #[serde(default)]
     pub width: u16,
+    #[serde(default)]
     pub height: u16,
+    #[serde(default)]
     pub text: String,
-    pub date: Timestamp,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
     #[serde(default)]
     pub sizes: Vec<Thumb>,
     #[serde(default)]
     pub placer_id: Id,
     #[serde(default)]
     pub tag_id: Id,
-    #[serde(default)]
-    pub tag_created: Timestamp,
+    #[serde(default, with = "adapters::time")]
+    pub tag_created: Time,
+    pub access_key: Option<String>,
+}
+
+impl<'a> From<&'a Photo> for Attachment {
+    fn from(photo: &'a Photo) -> Attachment {
+        Attachment {
+            kind: AttachmentKind::Photo,
+            owner_id: photo.owner_id,
+            media_id: photo.id,
+            access_key: photo.access_key.clone(),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Album {
     pub id: Id,
     pub thumb_id: Id,
-    pub owner_id: OwnerId,
+    pub owner_id: Owner,
     pub title: String,
     pub description: String,
-    pub created: Timestamp,
-    pub updated: Timestamp,
+    #[serde(with = "adapters::time")]
+    pub created: Time,
+    #[serde(with = "adapters::time")]
+    pub updated: Time,
     pub privacy: u32, // TODO ???
     pub comment_privacy: u32, // TODO ???
     pub size: u32,
-    #[serde(default)]
-    pub thumb_is_last: Bool,
-    #[serde(default)]
-    pub can_upload: Bool,
+    #[serde(default, with = "adapters::flag")]
+    pub thumb_is_last: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub can_upload: Flag,
     #[serde(default)]
     pub sizes: Vec<Thumb>,
-    pub thumb_src: Option<String>,
+    #[serde(default, with = "adapters::option_link")]
+    pub thumb_src: Option<Link>,
     #[serde(rename="type")]
     pub kind: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Thumb {
-    pub src: String,
+    #[serde(with = "adapters::link")]
+    pub src: Link,
     pub width: u16,
     pub height: u16,
     #[serde(rename="type")]
     pub kind: ThumbKind,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[repr(u8)]
 pub enum ThumbKind {
     Prop75 = 's' as u8,
@@ -614,35 +673,70 @@ impl<'de> ::serde::de::Deserialize<'de> for ThumbKind {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+impl ::serde::ser::Serialize for ThumbKind {
+    fn serialize<S: ::serde::ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_char(*self as u8 as char)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UploadServer {
     pub upload_url: String,
-    pub album_id: Id,
+    pub album_id: i64,
     pub user_id: Id,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+/// Upload server response for photos uploaded to `UploadServer::upload_url`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadedPhotos {
+    pub server: Id,
+    pub photos_list: String,
+    pub aid: Id,
+    pub hash: String,
+}
+
+/// Upload server response for a single photo, as used by wall, messages, owner and market photos
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadedPhoto {
+    pub server: Id,
+    pub photo: String,
+    pub hash: String,
+    // Main market photo crop
+    #[serde(default)]
+    pub crop_data: String,
+    #[serde(default)]
+    pub crop_hash: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UploadUrl {
     pub upload_url: String,
 }
 
-// TODO ???
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SavedPhoto {
-    pub hash: String,
-    pub src: String,
-    pub src_big: Option<String>,
-    pub src_small: Option<String>,
+    pub photo_hash: String,
+    #[serde(with = "adapters::link")]
+    pub photo_src: Link,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_src_big: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_src_small: Option<Link>,
+    #[serde(default, with = "adapters::flag")]
+    pub saved: Flag,
+    pub post_id: Option<Id>,
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Tag {
     id: Id,
     user_id: Id,
     placer_id: Id,
     tagged_name: String,
-    date: Timestamp,
-    viewed: Bool,
+    #[serde(with = "adapters::time")]
+    date: Time,
+    #[serde(with = "adapters::flag")]
+    viewed: Flag,
     x: f32,
     y: f32,
     x1: f32,
@@ -671,3 +765,779 @@ impl AsRef<str> for Sort {
         }
     }
 }
+
+/// Photo album upload pipeline: `photos.getUploadServer`, upload and `photos.save`.
+///
+/// Files are sent in batches of up to `MAX_UPLOAD_BATCH` photos.
+#[derive(Debug)]
+pub struct Upload {
+    album_id: Id,
+    group_id: Option<Id>,
+    caption: String,
+    latitude: Option<f32>,
+    longitude: Option<f32>,
+    files: Vec<UploadFile>,
+    saved: Vec<Photo>,
+    progress: Progress,
+}
+
+impl Upload {
+    pub fn new(album_id: Id) -> Upload {
+        Upload {
+            album_id,
+            group_id: None,
+            caption: String::new(),
+            latitude: None,
+            longitude: None,
+            files: Vec::new(),
+            saved: Vec::new(),
+            progress: Progress::new(),
+        }
+    }
+
+    pub fn group_id(&mut self, value: Option<Id>) -> &mut Self {
+        self.group_id = value;
+        self
+    }
+
+    pub fn caption<T: Into<String>>(&mut self, value: T) -> &mut Self {
+        self.caption = value.into();
+        self
+    }
+
+    /// Latitude to geotag saved photos with, none by default
+    pub fn latitude(&mut self, value: Option<f32>) -> &mut Self {
+        self.latitude = value;
+        self
+    }
+
+    pub fn longitude(&mut self, value: Option<f32>) -> &mut Self {
+        self.longitude = value;
+        self
+    }
+
+    pub fn file(&mut self, file: UploadFile) -> &mut Self {
+        self.files.push(file);
+        self
+    }
+
+    pub fn progress(&mut self, value: Progress) -> &mut Self {
+        self.progress = value;
+        self
+    }
+
+    /// Photos saved by previous `upload()` call which failed before sending all files
+    pub fn saved(&self) -> &[Photo] {
+        &self.saved
+    }
+
+    /// Run the whole pipeline, consuming all added files.
+    ///
+    /// Files are taken one batch at a time, so if a batch fails, files not sent yet stay added
+    /// and photos saved so far are kept, and calling `upload()` again continues with the rest,
+    /// returning all saved photos.
+    pub fn upload(&mut self, api: &Client, token: Option<&AccessToken>) -> UploadResult<Vec<Photo>> {
+        let (album_id, group_id, latitude, longitude, caption) =
+            (self.album_id, self.group_id, self.latitude, self.longitude, &self.caption);
+        upload_batches(api, &self.progress, &mut self.files, &mut self.saved, MAX_UPLOAD_BATCH,
+                       || api.call(token, GetUploadServer::new().album_id(album_id).group_id(group_id)),
+                       |uploaded: UploadedPhotos| {
+                           api.call(token,
+                                    Save::new()
+                                        .server(uploaded.server)
+                                        .album_id(Some(album_id))
+                                        .group_id(group_id)
+                                        .latitude(latitude)
+                                        .longitude(longitude)
+                                        .photos_list(&uploaded.photos_list)
+                                        .hash(&uploaded.hash)
+                                        .caption(caption))
+                       })
+    }
+}
+
+/// Upload `files` to the server returned by `get_server` and save them with `save`, up to `batch` files at a time.
+///
+/// Single file is sent as `photo` form field, and files of a bigger batch as `file1`, `file2` and so on.
+/// Upload server is requested only if there are files to send. Sent files are removed from `files`,
+/// and items saved from them are added to `saved`, which is taken once all files are sent.
+fn upload_batches<T, U, G, S>(api: &Client, progress: &Progress, files: &mut Vec<UploadFile>, saved: &mut Vec<T>, batch: usize,
+                              get_server: G, mut save: S) -> UploadResult<Vec<T>>
+    where U: de::DeserializeOwned,
+          G: FnOnce() -> api::Result<UploadServer>,
+          S: FnMut(U) -> api::Result<Vec<T>>
+{
+    progress.begin();
+    if files.is_empty() {
+        return Ok(mem::take(saved));
+    }
+
+    let server = get_server().map_err(UploadError::GetServer)?;
+
+    progress.expect(files.iter().map(UploadFile::len).sum());
+    while !files.is_empty() {
+        let form = if batch == 1 {
+            Form::new().part("photo", progress.expected_part(files.remove(0)))
+        } else {
+            files.drain(..cmp::min(files.len(), batch))
+                .enumerate()
+                .fold(Form::new(), |form, (idx, file)| form.part(format!("file{}", idx + 1), progress.expected_part(file)))
+        };
+
+        let uploaded: U = progress.send(api, &server.upload_url, form)?;
+        saved.extend(save(uploaded).map_err(UploadError::Save)?);
+    }
+
+    Ok(mem::take(saved))
+}
+
+/// Wall photo upload pipeline: `photos.getWallUploadServer`, upload and `photos.saveWallPhoto`.
+///
+/// Every file is uploaded and saved separately, and the resulting attachments
+/// can be passed to `wall::Post` as is.
+#[derive(Debug)]
+pub struct WallUpload {
+    group_id: Option<Id>,
+    caption: String,
+    latitude: Option<f32>,
+    longitude: Option<f32>,
+    files: Vec<UploadFile>,
+    saved: Vec<Attachment>,
+    progress: Progress,
+}
+
+impl WallUpload {
+    pub fn new() -> WallUpload {
+        WallUpload {
+            group_id: None,
+            caption: String::new(),
+            latitude: None,
+            longitude: None,
+            files: Vec::new(),
+            saved: Vec::new(),
+            progress: Progress::new(),
+        }
+    }
+
+    pub fn group_id(&mut self, value: Option<Id>) -> &mut Self {
+        self.group_id = value;
+        self
+    }
+
+    pub fn caption<T: Into<String>>(&mut self, value: T) -> &mut Self {
+        self.caption = value.into();
+        self
+    }
+
+    /// Latitude to geotag saved photos with, none by default
+    pub fn latitude(&mut self, value: Option<f32>) -> &mut Self {
+        self.latitude = value;
+        self
+    }
+
+    pub fn longitude(&mut self, value: Option<f32>) -> &mut Self {
+        self.longitude = value;
+        self
+    }
+
+    pub fn file(&mut self, file: UploadFile) -> &mut Self {
+        self.files.push(file);
+        self
+    }
+
+    pub fn progress(&mut self, value: Progress) -> &mut Self {
+        self.progress = value;
+        self
+    }
+
+    /// Attachments saved by previous `upload()` call which failed before sending all files
+    pub fn saved(&self) -> &[Attachment] {
+        &self.saved
+    }
+
+    /// Run the whole pipeline like `Upload::upload()`, taking files one at a time
+    pub fn upload(&mut self, api: &Client, token: Option<&AccessToken>) -> UploadResult<Vec<Attachment>> {
+        let (group_id, latitude, longitude, caption) = (self.group_id, self.latitude, self.longitude, &self.caption);
+        upload_batches(api, &self.progress, &mut self.files, &mut self.saved, 1,
+                       || api.call(token, GetWallUploadServer::new().group_id(group_id)),
+                       |uploaded: UploadedPhoto| {
+                           api.call(token,
+                                    SaveWallPhoto::new()
+                                        .group_id(group_id)
+                                        .server(uploaded.server)
+                                        .latitude(latitude)
+                                        .longitude(longitude)
+                                        .photo(&uploaded.photo)
+                                        .hash(&uploaded.hash)
+                                        .caption(caption))
+                               .map(|photos| photos.iter().map(Attachment::from).collect())
+                       })
+    }
+}
+
+impl Default for WallUpload {
+    fn default() -> WallUpload {
+        WallUpload::new()
+    }
+}
+
+/// Message photo upload pipeline: `photos.getMessagesUploadServer`, upload and `photos.saveMessagesPhoto`.
+///
+/// Every file is uploaded and saved separately, and the resulting attachments
+/// can be sent with a message as is.
+#[derive(Debug, Default)]
+pub struct MessagesUpload {
+    peer_id: Option<Owner>,
+    files: Vec<UploadFile>,
+    saved: Vec<Attachment>,
+    progress: Progress,
+}
+
+impl MessagesUpload {
+    pub fn new() -> MessagesUpload {
+        MessagesUpload::default()
+    }
+
+    pub fn peer_id<T: Into<Option<Owner>>>(&mut self, value: T) -> &mut Self {
+        self.peer_id = value.into();
+        self
+    }
+
+    pub fn file(&mut self, file: UploadFile) -> &mut Self {
+        self.files.push(file);
+        self
+    }
+
+    pub fn progress(&mut self, value: Progress) -> &mut Self {
+        self.progress = value;
+        self
+    }
+
+    /// Attachments saved by previous `upload()` call which failed before sending all files
+    pub fn saved(&self) -> &[Attachment] {
+        &self.saved
+    }
+
+    /// Run the whole pipeline like `Upload::upload()`, taking files one at a time
+    pub fn upload(&mut self, api: &Client, token: Option<&AccessToken>) -> UploadResult<Vec<Attachment>> {
+        let peer_id = self.peer_id;
+        upload_batches(api, &self.progress, &mut self.files, &mut self.saved, 1,
+                       || api.call(token, GetMessagesUploadServer::new().peer_id(peer_id)),
+                       |uploaded: UploadedPhoto| {
+                           api.call(token, SaveMessagesPhoto::new().server(uploaded.server).photo(&uploaded.photo).hash(&uploaded.hash))
+                               .map(|photos| photos.iter().map(Attachment::from).collect())
+                       })
+    }
+}
+
+/// Read image width and height from PNG, GIF or JPEG header at the current position,
+/// and rewind the reader back to it, so that the same image is uploaded.
+///
+/// Returns `None` for other formats and for truncated headers.
+pub fn read_image_size<R: Read + Seek>(reader: &mut R) -> io::Result<Option<(u32, u32)>> {
+    let start = reader.stream_position()?;
+    let mut header = Vec::with_capacity(24);
+    reader.by_ref().take(24).read_to_end(&mut header)?;
+
+    let size = if header.len() == 24 && header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some((u32::from_be_bytes([header[16], header[17], header[18], header[19]]),
+              u32::from_be_bytes([header[20], header[21], header[22], header[23]])))
+    } else if header.len() >= 10 && (header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a")) {
+        Some((u32::from(u16::from_le_bytes([header[6], header[7]])), u32::from(u16::from_le_bytes([header[8], header[9]]))))
+    } else if header.starts_with(&[0xff, 0xd8]) {
+        reader.seek(SeekFrom::Start(start + 2))?;
+        match read_jpeg_size(reader) {
+            // File ended before frame header
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => None,
+            size => size?,
+        }
+    } else {
+        None
+    };
+
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(size)
+}
+
+fn read_jpeg_size<R: Read + Seek>(reader: &mut R) -> io::Result<Option<(u32, u32)>> {
+    let mut buf = [0u8; 2];
+    loop {
+        reader.read_exact(&mut buf[..1])?;
+        if buf[0] != 0xff {
+            return Ok(None);
+        }
+
+        let mut marker = 0xff;
+        while marker == 0xff {
+            reader.read_exact(&mut buf[..1])?;
+            marker = buf[0];
+        }
+
+        match marker {
+            // Standalone markers without payload
+            0x01 | 0xd0..=0xd8 => continue,
+            // End of image or start of scan before any frame header
+            0xd9 | 0xda => return Ok(None),
+            _ => (),
+        }
+
+        reader.read_exact(&mut buf)?;
+        let len = u16::from_be_bytes(buf);
+
+        // Start of frame markers, except DHT, JPG and DAC
+        if (0xc0..=0xcf).contains(&marker) && marker != 0xc4 && marker != 0xc8 && marker != 0xcc {
+            let mut frame = [0u8; 5];
+            reader.read_exact(&mut frame)?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]);
+            let width = u16::from_be_bytes([frame[3], frame[4]]);
+            return Ok(Some((u32::from(width), u32::from(height))));
+        }
+
+        reader.seek(SeekFrom::Current(i64::from(len) - 2))?;
+    }
+}
+
+/// Image file with known dimensions, checked locally before upload
+#[derive(Debug)]
+struct Image {
+    name: String,
+    /// Taken when the image is sent
+    file: Option<UploadFile>,
+    width: u32,
+    height: u32,
+}
+
+impl Image {
+    fn open(path: &Path) -> UploadResult<Image> {
+        let size = read_image_size(&mut File::open(path)?)?;
+        Image::new(UploadFile::open(path)?, size)
+    }
+
+    fn from_reader<N: Into<String>, R: Read + Seek + Send + 'static>(name: N, mut reader: R) -> UploadResult<Image> {
+        let size = read_image_size(&mut reader)?;
+        Image::new(UploadFile::from_reader(name, reader)?, size)
+    }
+
+    fn new(file: UploadFile, size: Option<(u32, u32)>) -> UploadResult<Image> {
+        let (width, height) = size.ok_or_else(|| UploadError::Rejected(format!("{}: unknown or truncated image", file.name())))?;
+        Ok(Image { name: file.name().to_owned(), file: Some(file), width, height })
+    }
+
+    fn check_not_sent(&self) -> UploadResult<()> {
+        if self.file.is_some() { Ok(()) } else { Err(UploadError::AlreadySent) }
+    }
+
+    fn take_file(&mut self) -> UploadResult<UploadFile> {
+        self.file.take().ok_or(UploadError::AlreadySent)
+    }
+
+    fn check_size(&self, min_size: (u32, u32), max_sum: Option<u32>) -> UploadResult<()> {
+        if self.width < min_size.0 || self.height < min_size.1 {
+            return Err(UploadError::Rejected(format!("{}: image is {}x{}, but at least {}x{} is required",
+                                                     self.name, self.width, self.height, min_size.0, min_size.1)));
+        }
+        match max_sum {
+            Some(max_sum) if self.width.saturating_add(self.height) > max_sum => {
+                Err(UploadError::Rejected(format!("{}: image is {}x{}, but width and height must sum up to at most {}",
+                                                  self.name, self.width, self.height, max_sum)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_crop(&self, x: u32, y: u32, width: u32, height: u32, min_size: (u32, u32)) -> UploadResult<()> {
+        let fits = |start: u32, len: u32, max: u32| start.checked_add(len).filter(|&end| end <= max).is_some();
+        if width < min_size.0 || height < min_size.1 || !fits(x, width, self.width) || !fits(y, height, self.height) {
+            return Err(UploadError::Rejected(format!("{}: crop {}x{} at ({}, {}) must be at least {}x{} and fit into {}x{} image",
+                                                     self.name, width, height, x, y, min_size.0, min_size.1,
+                                                     self.width, self.height)));
+        }
+        Ok(())
+    }
+}
+
+/// Owner photo (avatar) upload pipeline: `photos.getOwnerPhotoUploadServer`, upload and `photos.saveOwnerPhoto`.
+///
+/// The image and its crops are checked to be at least `OWNER_PHOTO_MIN_SIZE` before upload.
+#[derive(Debug)]
+pub struct OwnerPhotoUpload {
+    image: Image,
+    owner_id: Option<Owner>,
+    crop: Option<(u32, u32, u32, u32)>,
+    square_crop: Option<(u32, u32, u32)>,
+    progress: Progress,
+}
+
+impl OwnerPhotoUpload {
+    pub fn open<P: AsRef<Path>>(path: P) -> UploadResult<OwnerPhotoUpload> {
+        Image::open(path.as_ref()).map(OwnerPhotoUpload::with_image)
+    }
+
+    pub fn from_reader<N: Into<String>, R: Read + Seek + Send + 'static>(name: N, reader: R) -> UploadResult<OwnerPhotoUpload> {
+        Image::from_reader(name, reader).map(OwnerPhotoUpload::with_image)
+    }
+
+    fn with_image(image: Image) -> OwnerPhotoUpload {
+        OwnerPhotoUpload {
+            image,
+            owner_id: None,
+            crop: None,
+            square_crop: None,
+            progress: Progress::new(),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.image.width, self.image.height)
+    }
+
+    pub fn owner_id<T: Into<Option<Owner>>>(&mut self, value: T) -> &mut Self {
+        self.owner_id = value.into();
+        self
+    }
+
+    /// Set photo crop as top left corner coordinates, width and height
+    pub fn crop(&mut self, x: u32, y: u32, width: u32, height: u32) -> &mut Self {
+        self.crop = Some((x, y, width, height));
+        self
+    }
+
+    /// Set square thumbnail crop as top left corner coordinates and side width
+    pub fn square_crop(&mut self, x: u32, y: u32, width: u32) -> &mut Self {
+        self.square_crop = Some((x, y, width));
+        self
+    }
+
+    pub fn progress(&mut self, value: Progress) -> &mut Self {
+        self.progress = value;
+        self
+    }
+
+    /// Run the whole pipeline, the image is kept for another call until it is sent
+    pub fn upload(&mut self, api: &Client, token: Option<&AccessToken>) -> UploadResult<SavedPhoto> {
+        self.progress.begin();
+        self.image.check_not_sent()?;
+        self.image.check_size(OWNER_PHOTO_MIN_SIZE, None)?;
+
+        let mut form = Form::new();
+        if let Some((x, y, width, height)) = self.crop {
+            self.image.check_crop(x, y, width, height, OWNER_PHOTO_MIN_SIZE)?;
+            // Top left and bottom right corners
+            form = form.text("_full", format!("{},{},{},{}", x, y, x + width, y + height));
+        }
+        if let Some((x, y, width)) = self.square_crop {
+            self.image.check_crop(x, y, width, width, OWNER_PHOTO_MIN_SIZE)?;
+            form = form.text("_square_crop", format!("{},{},{}", x, y, width));
+        }
+
+        let server = api.call(token, GetOwnerPhotoUploadServer::new().owner_id(self.owner_id))
+            .map_err(UploadError::GetServer)?;
+
+        let form = form.part("photo", self.progress.part(self.image.take_file()?));
+        let uploaded: UploadedPhoto = self.progress.send(api, &server.upload_url, form)?;
+
+        api.call(token,
+                  SaveOwnerPhoto::new()
+                      .server(uploaded.server)
+                      .photo(&uploaded.photo)
+                      .hash(&uploaded.hash))
+            .map_err(UploadError::Save)
+    }
+}
+
+/// Market product photo upload pipeline: `photos.getMarketUploadServer`, upload and `photos.saveMarketPhoto`.
+///
+/// The image is checked to be at least `MARKET_PHOTO_MIN_SIZE` and
+/// at most `MARKET_PHOTO_MAX_SIZE_SUM` before upload.
+#[derive(Debug)]
+pub struct MarketUpload {
+    image: Image,
+    group_id: Id,
+    main_photo: bool,
+    crop: Option<(u32, u32, u32)>,
+    progress: Progress,
+}
+
+impl MarketUpload {
+    pub fn open<P: AsRef<Path>>(group_id: Id, path: P) -> UploadResult<MarketUpload> {
+        Image::open(path.as_ref()).map(|image| MarketUpload::with_image(group_id, image))
+    }
+
+    pub fn from_reader<N: Into<String>, R: Read + Seek + Send + 'static>(group_id: Id, name: N, reader: R) -> UploadResult<MarketUpload> {
+        Image::from_reader(name, reader).map(|image| MarketUpload::with_image(group_id, image))
+    }
+
+    fn with_image(group_id: Id, image: Image) -> MarketUpload {
+        MarketUpload {
+            image,
+            group_id,
+            main_photo: false,
+            crop: None,
+            progress: Progress::new(),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.image.width, self.image.height)
+    }
+
+    pub fn main_photo(&mut self, value: bool) -> &mut Self {
+        self.main_photo = value;
+        self
+    }
+
+    /// Set main photo square crop as top left corner coordinates and side width
+    pub fn crop(&mut self, x: u32, y: u32, width: u32) -> &mut Self {
+        self.crop = Some((x, y, width));
+        self
+    }
+
+    pub fn progress(&mut self, value: Progress) -> &mut Self {
+        self.progress = value;
+        self
+    }
+
+    /// Run the whole pipeline, the image is kept for another call until it is sent
+    pub fn upload(&mut self, api: &Client, token: Option<&AccessToken>) -> UploadResult<Vec<Photo>> {
+        self.progress.begin();
+        self.image.check_not_sent()?;
+        self.image.check_size(MARKET_PHOTO_MIN_SIZE, Some(MARKET_PHOTO_MAX_SIZE_SUM))?;
+        if let Some((x, y, width)) = self.crop {
+            self.image.check_crop(x, y, width, width, (MARKET_PHOTO_MIN_SIZE.0, MARKET_PHOTO_MIN_SIZE.0))?;
+        }
+
+        let server = api.call(token,
+                  GetMarketUploadServer::new()
+                      .group_id(self.group_id)
+                      .main_photo(self.main_photo)
+                      .crop_x(self.crop.map(|crop| crop.0))
+                      .crop_y(self.crop.map(|crop| crop.1))
+                      .crop_width(self.crop.map(|crop| crop.2)))
+            .map_err(UploadError::GetServer)?;
+
+        let form = Form::new().part("file", self.progress.part(self.image.take_file()?));
+        let uploaded: UploadedPhoto = self.progress.send(api, &server.upload_url, form)?;
+
+        api.call(token,
+                  SaveMarketPhoto::new()
+                      .group_id(Some(self.group_id))
+                      .server(uploaded.server)
+                      .photo(&uploaded.photo)
+                      .hash(&uploaded.hash)
+                      .crop_data(&uploaded.crop_data)
+                      .crop_hash(&uploaded.crop_hash))
+            .map_err(UploadError::Save)
+    }
+}
+
+/// Market album photo upload pipeline: `photos.getMarketAlbumUploadServer`, upload and `photos.saveMarketAlbumPhoto`.
+///
+/// The image is checked to be at least `MARKET_ALBUM_PHOTO_MIN_SIZE` and
+/// at most `MARKET_PHOTO_MAX_SIZE_SUM` before upload.
+#[derive(Debug)]
+pub struct MarketAlbumUpload {
+    image: Image,
+    group_id: Id,
+    progress: Progress,
+}
+
+impl MarketAlbumUpload {
+    pub fn open<P: AsRef<Path>>(group_id: Id, path: P) -> UploadResult<MarketAlbumUpload> {
+        Image::open(path.as_ref()).map(|image| MarketAlbumUpload::with_image(group_id, image))
+    }
+
+    pub fn from_reader<N, R>(group_id: Id, name: N, reader: R) -> UploadResult<MarketAlbumUpload>
+        where N: Into<String>,
+              R: Read + Seek + Send + 'static
+    {
+        Image::from_reader(name, reader).map(|image| MarketAlbumUpload::with_image(group_id, image))
+    }
+
+    fn with_image(group_id: Id, image: Image) -> MarketAlbumUpload {
+        MarketAlbumUpload {
+            image,
+            group_id,
+            progress: Progress::new(),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.image.width, self.image.height)
+    }
+
+    pub fn progress(&mut self, value: Progress) -> &mut Self {
+        self.progress = value;
+        self
+    }
+
+    /// Run the whole pipeline, the image is kept for another call until it is sent
+    pub fn upload(&mut self, api: &Client, token: Option<&AccessToken>) -> UploadResult<Vec<Photo>> {
+        self.progress.begin();
+        self.image.check_not_sent()?;
+        self.image.check_size(MARKET_ALBUM_PHOTO_MIN_SIZE, Some(MARKET_PHOTO_MAX_SIZE_SUM))?;
+
+        let server = api.call(token, GetMarketAlbumUploadServer::new().group_id(self.group_id))
+            .map_err(UploadError::GetServer)?;
+
+        let form = Form::new().part("file", self.progress.part(self.image.take_file()?));
+        let uploaded: UploadedPhoto = self.progress.send(api, &server.upload_url, form)?;
+
+        api.call(token,
+                  SaveMarketAlbumPhoto::new()
+                      .group_id(self.group_id)
+                      .server(uploaded.server)
+                      .photo(&uploaded.photo)
+                      .hash(&uploaded.hash))
+            .map_err(UploadError::Save)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use serde_json;
+    use api::Request;
+    use super::*;
+
+    fn image(width: u32, height: u32) -> Image {
+        Image::new(UploadFile::from_reader("test.png", Cursor::new(vec![0u8; 8])).unwrap(), Some((width, height))).unwrap()
+    }
+
+    #[test]
+    fn nothing_to_upload_returns_saved() {
+        let api = Client::new();
+        let mut saved = vec![1, 2];
+        let result = upload_batches(&api, &Progress::new(), &mut Vec::new(), &mut saved, 1,
+                                    || panic!("upload server requested without files"),
+                                    |_: UploadedPhoto| -> api::Result<Vec<i32>> { panic!("nothing to save") });
+        assert_eq!(result.unwrap(), [1, 2]);
+        assert!(saved.is_empty());
+    }
+
+    #[test]
+    fn sent_image_is_not_sent_again() {
+        let mut upload = MarketUpload::with_image(1, image(800, 600));
+        upload.image.take_file().unwrap();
+        match upload.upload(&Client::new(), None) {
+            Err(UploadError::AlreadySent) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn huge_image_is_rejected_without_overflow() {
+        assert!(image(u32::MAX, u32::MAX).check_size(MARKET_PHOTO_MIN_SIZE, Some(MARKET_PHOTO_MAX_SIZE_SUM)).is_err());
+        assert!(image(800, 600).check_size(MARKET_PHOTO_MIN_SIZE, Some(MARKET_PHOTO_MAX_SIZE_SUM)).is_ok());
+    }
+
+    #[test]
+    fn crop_must_fit_into_image() {
+        let image = image(800, 600);
+        assert!(image.check_crop(100, 100, 400, 300, OWNER_PHOTO_MIN_SIZE).is_ok());
+        assert!(image.check_crop(500, 100, 400, 300, OWNER_PHOTO_MIN_SIZE).is_err());
+        assert!(image.check_crop(100, 100, 100, 300, OWNER_PHOTO_MIN_SIZE).is_err());
+        assert!(image.check_crop(u32::MAX, 0, 200, 200, OWNER_PHOTO_MIN_SIZE).is_err());
+        assert!(image.check_crop(0, u32::MAX - 100, 200, 200, OWNER_PHOTO_MIN_SIZE).is_err());
+    }
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR\x00\x00\x02\x80\x00\x00\x01\xe0\x08\x06\x00\x00\x00";
+    const GIF: &[u8] = b"GIF89a\x80\x02\xe0\x01\xf7\x00\x00";
+    // SOI, APP0 with JFIF header, then SOF0 with 480x640 frame
+    const JPEG: &[u8] = b"\xff\xd8\xff\xe0\x00\x10JFIF\x00\x01\x01\x00\x00\x01\x00\x01\x00\x00\
+                          \xff\xc0\x00\x11\x08\x01\xe0\x02\x80\x03\x01\x22\x00\x02\x11\x01\x03\x11\x01";
+
+    fn image_size(bytes: &[u8]) -> Option<(u32, u32)> {
+        let mut reader = Cursor::new(bytes);
+        let size = read_image_size(&mut reader).unwrap();
+        assert_eq!(reader.position(), 0);
+        size
+    }
+
+    #[test]
+    fn image_sizes() {
+        assert_eq!(image_size(PNG), Some((640, 480)));
+        assert_eq!(image_size(GIF), Some((640, 480)));
+        assert_eq!(image_size(JPEG), Some((640, 480)));
+        assert_eq!(image_size(b"BM\x00\x00"), None);
+    }
+
+    #[test]
+    fn image_size_from_current_position() {
+        for image in &[PNG, GIF, JPEG] {
+            // Another 1x1 image before the current position
+            let mut data = b"GIF89a\x01\x00\x01\x00".to_vec();
+            data.resize(1000, 0);
+            data.extend_from_slice(image);
+            let mut reader = Cursor::new(data);
+            reader.set_position(1000);
+            assert_eq!(read_image_size(&mut reader).unwrap(), Some((640, 480)));
+            assert_eq!(reader.position(), 1000);
+        }
+
+        let mut data = b"GIF8".to_vec();
+        data.extend_from_slice(PNG);
+        let mut reader = Cursor::new(data);
+        reader.set_position(4);
+        let image = Image::from_reader("offset.png", reader).unwrap();
+        assert_eq!((image.width, image.height), (640, 480));
+        assert_eq!(image.file.as_ref().map(UploadFile::len), Some(PNG.len() as u64));
+    }
+
+    #[test]
+    fn truncated_images() {
+        assert_eq!(image_size(&PNG[..20]), None);
+        assert_eq!(image_size(&GIF[..8]), None);
+        // Inside APP0 segment and inside frame header
+        assert_eq!(image_size(&JPEG[..10]), None);
+        assert_eq!(image_size(&JPEG[..26]), None);
+        // No frame header before end of image
+        assert_eq!(image_size(b"\xff\xd8\xff\xe0\x00\x04\x00\x00\xff\xd9"), None);
+
+        match Image::from_reader("truncated.jpg", Cursor::new(JPEG[..26].to_vec())) {
+            Err(UploadError::Rejected(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unset_coordinates_are_not_sent() {
+        let query = Save::new().server(1).photos_list("[]").to_query_string();
+        assert!(!query.contains("latitude=") && !query.contains("longitude="));
+
+        let query = Save::new().server(1).latitude(Some(59.9)).longitude(Some(30.3)).to_query_string();
+        assert!(query.contains("latitude=59.9") && query.contains("longitude=30.3"));
+
+        let query = SaveWallPhoto::new().server(1).photo("[]").to_query_string();
+        assert!(!query.contains("latitude=") && !query.contains("longitude="));
+    }
+
+    const PHOTO: &str = r#"{
+        "id": 456239020,
+        "album_id": -7,
+        "owner_id": -1,
+        "user_id": 100,
+        "text": "",
+        "date": 1500000000,
+        "sizes": [
+            {"type": "s", "src": "https://pp.userapi.com/s.jpg", "width": 75, "height": 50},
+            {"type": "x", "src": "https://pp.userapi.com/x.jpg", "width": 604, "height": 403}
+        ],
+        "width": 1280,
+        "height": 853,
+        "access_key": "f00"
+    }"#;
+
+    #[test]
+    fn photo_round_trip() {
+        let photo: Photo = serde_json::from_str(PHOTO).unwrap();
+        assert_eq!(photo.sizes[1].kind, ThumbKind::Prop604);
+
+        let value = serde_json::to_value(&photo).unwrap();
+        assert_eq!(value["owner_id"], -1);
+        assert_eq!(value["date"], 1500000000);
+        assert_eq!(value["tag_created"], 0);
+        assert_eq!(value["sizes"][1]["type"], "x");
+        assert_eq!(value["sizes"][1]["src"], "https://pp.userapi.com/x.jpg");
+        assert_eq!(serde_json::from_value::<Photo>(value).unwrap(), photo);
+    }
+}
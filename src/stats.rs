@@ -1,12 +1,12 @@
 use std::borrow::Borrow;
-use api::{Bool, Collection, Id, OwnerId};
-use serde::de;
+use api::{Bool, Collection, Id, Owner};
+use serde::{de, ser};
 use std::fmt::Debug;
 use std::str::FromStr;
 use chrono::{Local, NaiveDate};
 use std::fmt;
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Period {
     pub day: NaiveDate,
     pub views: u32,
@@ -80,20 +80,63 @@ impl<'de> de::Deserialize<'de> for DemoSexAge {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy)]
+impl ser::Serialize for DemoSexAge {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&format!("{};{}", self.sex.as_ref(), self.age.as_ref()))
+    }
+}
+
+/// City id, or `"other"` for all other cities
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum DemoCity {
     CityId(Id),
     Other,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+impl<'de> de::Deserialize<'de> for DemoCity {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<DemoCity, D::Error> {
+        struct TempVisitor;
+
+        impl<'v> de::Visitor<'v> for TempVisitor {
+            type Value = DemoCity;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "city id or \"other\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<DemoCity, E> {
+                Ok(DemoCity::CityId(value))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<DemoCity, E> {
+                match value {
+                    "other" => Ok(DemoCity::Other),
+                    _ => value.parse().map(DemoCity::CityId).map_err(|_| de::Error::custom("unexpected value")),
+                }
+            }
+        }
+
+        d.deserialize_any(TempVisitor)
+    }
+}
+
+impl ser::Serialize for DemoCity {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            DemoCity::CityId(id) => s.serialize_u64(id),
+            DemoCity::Other => s.serialize_str("other"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Demography<T: Copy + Debug + Eq> {
     pub visitors: u32,
     pub value: T,
     pub name: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Country {
     pub visitors: u32,
     pub value: Id,
@@ -101,7 +144,7 @@ pub struct Country {
     pub name: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct PostReach {
     pub reach_subscribers: u32,
     pub reach_total: u32,
@@ -130,7 +173,46 @@ request! {
 request! {
     #[derive(Copy, Eq)]
     struct GetPostReach for ["stats.getPostReach"](v => 5.44) -> PostReach {
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         post_id: Id = () => {},
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+    use super::*;
+
+    const PERIOD: &str = r#"{
+        "day": "2018-12-01",
+        "views": 120,
+        "visitors": 40,
+        "reach": 300,
+        "reach_subscribers": 250,
+        "subscribed": 3,
+        "unsubscribed": 1,
+        "sex": [{"visitors": 25, "value": "f"}, {"visitors": 15, "value": "m"}],
+        "age": [{"visitors": 10, "value": "18-21"}],
+        "sex_age": [{"visitors": 7, "value": "f;18-21"}],
+        "cities": [{"visitors": 20, "value": 2, "name": "Saint Petersburg"}, {"visitors": 20, "value": "other"}],
+        "countries": [{"visitors": 40, "value": 1, "code": "RU", "name": "Russia"}]
+    }"#;
+
+    #[test]
+    fn period_round_trip() {
+        let period: Period = serde_json::from_str(PERIOD).unwrap();
+        assert_eq!(period.day, "2018-12-01".parse::<NaiveDate>().unwrap());
+        assert_eq!(period.sex_age[0].value, DemoSexAge { sex: DemoSex::Female, age: DemoAgeRange::_18_21 });
+        assert_eq!(period.cities[0].value, DemoCity::CityId(2));
+        assert_eq!(period.cities[1].value, DemoCity::Other);
+
+        let value = serde_json::to_value(&period).unwrap();
+        assert_eq!(value["day"], "2018-12-01");
+        assert_eq!(value["sex"][0]["value"], "f");
+        assert_eq!(value["sex_age"][0]["value"], "f;18-21");
+        assert_eq!(value["cities"][0]["value"], 2);
+        assert_eq!(value["cities"][1]["value"], "other");
+        assert_eq!(value["cities"][1]["name"], Value::Null);
+        assert_eq!(serde_json::from_value::<Period>(value).unwrap(), period);
+    }
+}
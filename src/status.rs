@@ -2,7 +2,7 @@ use std::borrow::Borrow;
 use audio::Audio;
 use api::Bool;
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Status {
     pub text: String,
     pub audio: Option<Audio>,
@@ -0,0 +1,374 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::result::Result as StdResult;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use serde::de;
+use serde_json::Value;
+use api::{Client, Error};
+
+pub use reqwest::multipart::{Form, Part};
+
+/// A file to be sent to VK upload server
+pub struct UploadFile {
+    name: String,
+    body: Box<dyn Read + Send>,
+    len: u64,
+}
+
+impl UploadFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<UploadFile> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(UploadFile {
+            name: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            body: Box::new(file),
+            len,
+        })
+    }
+
+    /// Create upload file from seekable stream, which is sent from its current position to the end
+    pub fn from_reader<N: Into<String>, R: Read + Seek + Send + 'static>(name: N, mut reader: R) -> io::Result<UploadFile> {
+        let start = reader.stream_position()?;
+        let len = reader.seek(SeekFrom::End(0))? - start;
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(UploadFile {
+            name: name.into(),
+            body: Box::new(reader),
+            len,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn into_part(self) -> Part {
+        Part::reader_with_length(self.body, self.len).file_name(self.name)
+    }
+}
+
+impl fmt::Debug for UploadFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UploadFile")
+            .field("name", &self.name)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+type ProgressCallback = Box<dyn FnMut(u64, u64) + Send>;
+
+struct ProgressState {
+    sent: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+    callback: Mutex<Option<ProgressCallback>>,
+}
+
+/// Upload progress tracker and cancellation switch.
+///
+/// It is cheap to clone, and all clones share the same state,
+/// so upload can be cancelled from another thread.
+#[derive(Clone)]
+pub struct Progress(Arc<ProgressState>);
+
+impl Progress {
+    pub fn new() -> Progress {
+        Progress(Arc::new(ProgressState {
+            sent: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            cancelled: AtomicBool::new(false),
+            callback: Mutex::new(None),
+        }))
+    }
+
+    /// Create progress tracker calling `callback` with bytes sent and total bytes as upload goes
+    pub fn with_callback<F: FnMut(u64, u64) + Send + 'static>(callback: F) -> Progress {
+        let progress = Progress::new();
+        *progress.0.callback.lock().unwrap() = Some(Box::new(callback));
+        progress
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.0.sent.load(Ordering::SeqCst)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.0.total.load(Ordering::SeqCst)
+    }
+
+    /// Stop upload, making it fail with `UploadError::Cancelled`
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Zero counters before a new upload, so that a reused tracker doesn't count previous uploads
+    pub fn begin(&self) {
+        self.0.sent.store(0, Ordering::SeqCst);
+        self.0.total.store(0, Ordering::SeqCst);
+    }
+
+    /// Reset counters, e.g. when resuming interrupted upload
+    pub fn reset(&self, sent: u64, total: u64) {
+        self.0.sent.store(sent, Ordering::SeqCst);
+        self.0.total.store(total, Ordering::SeqCst);
+        self.notify(sent, total);
+    }
+
+    /// Wrap reader to advance progress with every read
+    pub fn reader<R: Read>(&self, reader: R) -> ProgressReader<R> {
+        ProgressReader {
+            inner: reader,
+            progress: self.clone(),
+        }
+    }
+
+    /// Convert file into multipart form part tracked by this progress
+    pub fn part(&self, file: UploadFile) -> Part {
+        self.expect(file.len);
+        self.expected_part(file)
+    }
+
+    /// Add bytes to be sent later to total, so that it covers files sent in several requests from the start
+    pub fn expect(&self, len: u64) {
+        self.0.total.fetch_add(len, Ordering::SeqCst);
+    }
+
+    /// Convert file, already counted in total with `expect()`, into multipart form part tracked by this progress
+    pub fn expected_part(&self, file: UploadFile) -> Part {
+        let UploadFile { name, body, len } = file;
+        Part::reader_with_length(self.reader(body), len).file_name(name)
+    }
+
+    /// Post multipart form to upload server URL
+    pub fn send<T: de::DeserializeOwned>(&self, api: &Client, url: &str, form: Form) -> UploadResult<T> {
+        if self.is_cancelled() {
+            return Err(UploadError::Cancelled);
+        }
+        api.upload(url, form).map_err(|err| self.error(err))
+    }
+
+    /// Convert error happened during upload into `UploadError`, accounting for cancellation
+    pub fn error(&self, err: Error) -> UploadError {
+        if self.is_cancelled() { UploadError::Cancelled } else { UploadError::Upload(err) }
+    }
+
+    fn advance(&self, len: u64) {
+        let sent = self.0.sent.fetch_add(len, Ordering::SeqCst) + len;
+        self.notify(sent, self.total());
+    }
+
+    fn notify(&self, sent: u64, total: u64) {
+        if let Ok(mut callback) = self.0.callback.lock() {
+            if let Some(ref mut callback) = *callback {
+                callback(sent, total);
+            }
+        }
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Progress {
+        Progress::new()
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("sent", &self.sent())
+            .field("total", &self.total())
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Reader advancing upload progress, fails with `io::ErrorKind::Other` once upload is cancelled
+#[derive(Debug)]
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Progress,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    #[allow(clippy::io_other_error)]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.progress.is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Other, "upload cancelled"));
+        }
+        let len = self.inner.read(buf)?;
+        self.progress.advance(len as u64);
+        Ok(len)
+    }
+}
+
+/// Error returned by upload server instead of upload result, like
+/// `{"error": "ERR_UPLOAD_BAD_IMAGE_SIZE: market photo min size 400x400"}`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UploadServerError {
+    pub code: String,
+    pub message: String,
+}
+
+impl<'a> From<&'a Value> for UploadServerError {
+    fn from(value: &'a Value) -> UploadServerError {
+        match value.as_str() {
+            Some(error) => {
+                match error.find(": ") {
+                    Some(pos) => {
+                        UploadServerError {
+                            code: error[..pos].to_owned(),
+                            message: error[pos + 2..].to_owned(),
+                        }
+                    }
+                    None => {
+                        UploadServerError {
+                            code: error.to_owned(),
+                            message: String::new(),
+                        }
+                    }
+                }
+            }
+            None => {
+                UploadServerError {
+                    code: String::new(),
+                    message: value.to_string(),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for UploadServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.code.is_empty(), self.message.is_empty()) {
+            (false, false) => write!(f, "upload server error {}: {}", self.code, self.message),
+            (false, true) => write!(f, "upload server error {}", self.code),
+            _ => write!(f, "upload server error: {}", self.message),
+        }
+    }
+}
+
+/// Upload error, tagged with the pipeline stage it happened at
+#[derive(Debug)]
+pub enum UploadError {
+    Io(io::Error),
+    /// File was rejected by local checks before upload
+    Rejected(String),
+    /// File has no bytes to upload
+    Empty,
+    GetServer(Error),
+    Upload(Error),
+    Save(Error),
+    Move(Error),
+    NotSaved,
+    /// File was already sent by previous `upload()` call
+    AlreadySent,
+    /// Upload server kept acknowledging no new bytes
+    Stalled,
+    Cancelled,
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UploadError::Io(ref err) => err.fmt(f),
+            UploadError::Rejected(ref reason) => write!(f, "file rejected: {}", reason),
+            UploadError::Empty => f.write_str("file is empty"),
+            UploadError::GetServer(ref err) => write!(f, "failed to get upload server: {}", err),
+            UploadError::Upload(ref err) => write!(f, "failed to upload file: {}", err),
+            UploadError::Save(ref err) => write!(f, "failed to save uploaded file: {}", err),
+            UploadError::Move(ref err) => write!(f, "failed to move saved file to album: {}", err),
+            UploadError::NotSaved => f.write_str("uploaded file is missing after save"),
+            UploadError::AlreadySent => f.write_str("file was already sent"),
+            UploadError::Stalled => f.write_str("upload server acknowledged no new bytes"),
+            UploadError::Cancelled => f.write_str("upload cancelled"),
+        }
+    }
+}
+
+impl From<io::Error> for UploadError {
+    fn from(err: io::Error) -> UploadError {
+        UploadError::Io(err)
+    }
+}
+
+pub type UploadResult<T> = StdResult<T, UploadError>;
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read};
+    use std::sync::mpsc;
+    use serde_json;
+    use super::*;
+
+    #[test]
+    fn progress_is_zeroed_between_uploads() {
+        let progress = Progress::new();
+        for _ in 0..2 {
+            progress.begin();
+            let file = UploadFile::from_reader("test.txt", Cursor::new(vec![0u8; 10])).unwrap();
+            let UploadFile { body, len, .. } = file;
+            progress.0.total.fetch_add(len, Ordering::SeqCst);
+            io::copy(&mut progress.reader(body), &mut io::sink()).unwrap();
+            assert_eq!((progress.sent(), progress.total()), (10, 10));
+        }
+    }
+
+    #[test]
+    fn cancelled_reader_fails() {
+        let progress = Progress::new();
+        progress.cancel();
+        let err = progress.reader(Cursor::new(vec![0u8; 10])).read(&mut [0; 10]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn reader_counts_against_total() {
+        let (sender, receiver) = mpsc::channel();
+        let progress = Progress::with_callback(move |sent, total| sender.send((sent, total)).unwrap());
+        progress.reset(4, 20);
+
+        let mut reader = progress.reader(Cursor::new(vec![0u8; 16]));
+        let mut buf = [0u8; 6];
+        while reader.read(&mut buf).unwrap() > 0 {}
+        assert_eq!((progress.sent(), progress.total()), (20, 20));
+
+        let calls: Vec<(u64, u64)> = receiver.try_iter().collect();
+        assert_eq!(calls, [(4, 20), (10, 20), (16, 20), (20, 20), (20, 20)]);
+    }
+
+    #[test]
+    fn upload_server_errors() {
+        let error = |json: &str| UploadServerError::from(&serde_json::from_str::<Value>(json).unwrap());
+
+        let err = error(r#""ERR_UPLOAD_BAD_IMAGE_SIZE: market photo min size 400x400""#);
+        assert_eq!(err.code, "ERR_UPLOAD_BAD_IMAGE_SIZE");
+        assert_eq!(err.message, "market photo min size 400x400");
+        assert_eq!(err.to_string(), "upload server error ERR_UPLOAD_BAD_IMAGE_SIZE: market photo min size 400x400");
+
+        let err = error(r#""invalid_session""#);
+        assert_eq!((&*err.code, &*err.message), ("invalid_session", ""));
+        assert_eq!(err.to_string(), "upload server error invalid_session");
+
+        let err = error(r#"{"code": 8}"#);
+        assert_eq!((&*err.code, &*err.message), ("", r#"{"code":8}"#));
+        assert_eq!(err.to_string(), r#"upload server error: {"code":8}"#);
+    }
+}
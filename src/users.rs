@@ -1,21 +1,272 @@
 use std::borrow::Borrow;
+use std::collections::HashMap;
 use std::convert::AsRef;
-use serde::de;
+use serde::{de, ser};
+use adapters::{self, Flag, Link, Time};
 use api::{Bool, Collection, Id};
 
 use audio::Audio;
 
-// TODO: maybe move to api?
-// TODO: must be much more fields
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+/// User profile, optional fields are set only if requested with `UserOptionField`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct User {
     pub id: i64, // String
-    pub photo: Option<String>,
+    #[serde(default)]
+    pub first_name: String,
+    #[serde(default)]
+    pub last_name: String,
+    pub deactivated: Option<String>, // "deleted" or "banned"
+    #[serde(default, with = "adapters::option_flag")]
+    pub hidden: Option<Flag>,
+    // Community name, as returned by `GetSubscriptions`
     pub name: Option<String>,
     pub name_gen: Option<String>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo: Option<Link>,
+
+    #[serde(default, with = "adapters::option_flag")]
+    pub verified: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub blacklisted: Option<Flag>,
+    pub sex: Option<Sex>,
+    pub bdate: Option<String>, // "D.M.YYYY" or "D.M"
+    pub city: Option<City>,
+    pub country: Option<Country>,
+    pub home_town: Option<String>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_50: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_100: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_200_orig: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_200: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_400_orig: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_max: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_max_orig: Option<Link>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub online: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub online_mobile: Option<Flag>,
+    pub online_app: Option<Id>,
+    pub lists: Option<Vec<Id>>,
+    pub domain: Option<String>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub has_mobile: Option<Flag>,
+    #[serde(flatten)]
+    pub contacts: Contacts,
+    pub site: Option<String>,
+    #[serde(flatten)]
+    pub education: Education,
+    pub universities: Option<Vec<University>>,
+    pub schools: Option<Vec<School>>,
+    pub status: Option<String>,
     pub status_audio: Option<Audio>,
+    pub last_seen: Option<LastSeen>,
+    pub followers_count: Option<u32>,
+    pub common_count: Option<u32>,
+    pub counters: Option<Counters>,
+    pub occupation: Option<Occupation>,
+    pub career: Option<Vec<Career>>,
+    pub nickname: Option<String>,
+    pub relatives: Option<Vec<Relative>>,
+    pub relation: Option<Status>,
+    pub relation_partner: Option<RelationPartner>,
+    pub personal: Option<Personal>,
+    #[serde(flatten)]
+    pub connections: Connections,
+    pub exports: Option<HashMap<String, Bool>>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub wall_comments: Option<Flag>,
+    pub activities: Option<String>,
+    pub interests: Option<String>,
+    pub music: Option<String>,
+    pub movies: Option<String>,
+    pub tv: Option<String>,
+    pub books: Option<String>,
+    pub games: Option<String>,
+    pub about: Option<String>,
+    pub quotes: Option<String>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub can_post: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub can_see_all_posts: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub can_see_audio: Option<Flag>,
+    #[serde(default, with = "adapters::option_flag")]
+    pub can_write_private_message: Option<Flag>,
+    pub timezone: Option<i32>,
+    pub screen_name: Option<String>,
 }
 
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct City {
+    pub id: Id,
+    pub title: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Country {
+    pub id: Id,
+    pub title: String,
+}
+
+/// Phone numbers, as returned for `UserOptionField::Contacts`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Default)]
+pub struct Contacts {
+    pub mobile_phone: Option<String>,
+    pub home_phone: Option<String>,
+}
+
+/// Higher education, as returned for `UserOptionField::Education`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Default)]
+pub struct Education {
+    pub university: Option<Id>,
+    pub university_name: Option<String>,
+    pub faculty: Option<Id>,
+    pub faculty_name: Option<String>,
+    pub graduation: Option<u16>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct University {
+    pub id: Id,
+    pub country: Option<Id>,
+    pub city: Option<Id>,
+    pub name: String,
+    pub faculty: Option<Id>,
+    pub faculty_name: Option<String>,
+    pub chair: Option<Id>,
+    pub chair_name: Option<String>,
+    pub graduation: Option<u16>,
+    pub education_form: Option<String>,
+    pub education_status: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct School {
+    pub id: String, // Id
+    pub country: Option<Id>,
+    pub city: Option<Id>,
+    pub name: String,
+    pub year_from: Option<u16>,
+    pub year_to: Option<u16>,
+    pub year_graduated: Option<u16>,
+    pub class: Option<String>,
+    pub speciality: Option<String>,
+    #[serde(rename="type")]
+    pub kind: Option<u8>,
+    pub type_str: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct LastSeen {
+    #[serde(with = "adapters::time")]
+    pub time: Time,
+    pub platform: Option<u8>, // 1 - mobile, 2 - iPhone, 3 - iPad, 4 - Android, 5 - Windows Phone, 6 - Windows 8, 7 - web
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Counters {
+    #[serde(default)]
+    pub albums: u32,
+    #[serde(default)]
+    pub videos: u32,
+    #[serde(default)]
+    pub audios: u32,
+    #[serde(default)]
+    pub photos: u32,
+    #[serde(default)]
+    pub notes: u32,
+    #[serde(default)]
+    pub friends: u32,
+    #[serde(default)]
+    pub groups: u32,
+    #[serde(default)]
+    pub online_friends: u32,
+    #[serde(default)]
+    pub mutual_friends: u32,
+    #[serde(default)]
+    pub user_videos: u32,
+    #[serde(default)]
+    pub followers: u32,
+    #[serde(default)]
+    pub pages: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Occupation {
+    #[serde(rename="type")]
+    pub kind: String, // "work", "school" or "university"
+    pub id: Option<Id>,
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Career {
+    pub group_id: Option<Id>,
+    pub company: Option<String>,
+    pub country_id: Option<Id>,
+    pub city_id: Option<Id>,
+    pub city_name: Option<String>,
+    pub from: Option<u16>,
+    pub until: Option<u16>,
+    pub position: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Relative {
+    /// Negative or missing for relatives without VK profile
+    pub id: Option<i64>,
+    pub name: Option<String>,
+    #[serde(rename="type")]
+    pub kind: RelativeKind,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct RelationPartner {
+    pub id: i64,
+    #[serde(default)]
+    pub first_name: String,
+    #[serde(default)]
+    pub last_name: String,
+}
+
+/// Life position, as set in user profile
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Personal {
+    pub political: Option<u8>, // 1 - communist ... 9 - libertarian
+    #[serde(default)]
+    pub langs: Vec<String>,
+    pub religion: Option<String>,
+    pub inspired_by: Option<String>,
+    pub people_main: Option<u8>, // 1 - intellect and creativity ... 6 - humor and love for life
+    pub life_main: Option<u8>, // 1 - family and children ... 8 - fame and influence
+    pub smoking: Option<u8>, // 1 - very negative ... 5 - positive
+    pub alcohol: Option<u8>, // 1 - very negative ... 5 - positive
+}
+
+/// Other services accounts, as returned for `UserOptionField::Connections`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Default)]
+pub struct Connections {
+    pub skype: Option<String>,
+    pub facebook: Option<String>,
+    pub facebook_name: Option<String>,
+    pub twitter: Option<String>,
+    pub livejournal: Option<String>,
+    pub instagram: Option<String>,
+}
+
+enum_str! { RelativeKind {
+    Child = "child",
+    Sibling = "sibling",
+    Parent = "parent",
+    Grandparent = "grandparent",
+    Grandchild = "grandchild",
+}}
 
 enum_str! { NameCase {
     Nominative = "nom",
@@ -73,7 +324,7 @@ enum_str! { UserOptionField {
     Games = "games",
     About = "about",
     Quotes = "quotes",
-    CanPost = "canPost",
+    CanPost = "can_post",
     CanSeeAllPosts = "can_see_all_posts",
     CanSeeAudio = "can_see_audio",
     CanWritePrivateMessage = "can_write_private_message",
@@ -217,6 +468,9 @@ request_ref! {
     }
 }
 
+fields_request_impl!(UserOptionField; Get<'a> => Vec, Search<'a> => Collection, GetSubscriptions<'a> => Collection,
+                     GetFollowers<'a> => Collection, GetNearby<'a> => Collection);
+
 #[derive(Debug, Eq, PartialEq, Copy, Clone, Ord, PartialOrd)]
 pub enum Radius {
     R300 = 1,
@@ -284,6 +538,12 @@ impl<'de> de::Deserialize<'de> for Sex {
     }
 }
 
+impl ser::Serialize for Sex {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u8(*self as u8)
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 #[repr(u8)]
 pub enum Status {
@@ -295,6 +555,7 @@ pub enum Status {
     ItsComplicated = 5,
     ActiveSearch = 6,
     InLove = 7,
+    CivilUnion = 8,
 }
 
 impl AsRef<str> for Status {
@@ -309,6 +570,7 @@ impl AsRef<str> for Status {
             ItsComplicated => "5",
             ActiveSearch => "6",
             InLove => "7",
+            CivilUnion => "8",
         }
     }
 }
@@ -326,15 +588,110 @@ impl<'de> de::Deserialize<'de> for Status {
                 5 => Ok(ItsComplicated),
                 6 => Ok(ActiveSearch),
                 7 => Ok(InLove),
-                _ => Err(de::Error::custom("integer value in range 0...7 expected")),
+                8 => Ok(CivilUnion),
+                _ => Err(de::Error::custom("integer value in range 0...8 expected")),
             }
         })
     }
 }
 
+impl ser::Serialize for Status {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u8(*self as u8)
+    }
+}
+
 enum_str! { ReportKind {
     Porn = "porn",
     Spam = "spam",
     Insult = "insult",
     Ads = "advertisment",
 }}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+    use api::{Fields, Request, Typed};
+    use super::*;
+
+    const USER: &str = r#"{
+        "id": 1,
+        "first_name": "Pavel",
+        "last_name": "Durov",
+        "sex": 2,
+        "bdate": "10.10",
+        "city": {"id": 2, "title": "Saint Petersburg"},
+        "photo_100": "https://vk.com/images/camera_100.png",
+        "online": 0,
+        "domain": "durov",
+        "has_mobile": 1,
+        "mobile_phone": "",
+        "university": 1,
+        "university_name": "SPbSU",
+        "last_seen": {"time": 1500000000, "platform": 7},
+        "counters": {"friends": 10, "followers": 5000000},
+        "relation": 0,
+        "skype": "durov",
+        "exports": {"twitter": 1},
+        "timezone": 3,
+        "screen_name": "durov"
+    }"#;
+
+    #[test]
+    fn user_round_trip() {
+        let user: User = serde_json::from_str(USER).unwrap();
+        assert_eq!(user.contacts.mobile_phone, Some(String::new()));
+        assert_eq!(user.education.university_name, Some("SPbSU".to_owned()));
+        assert_eq!(user.connections.skype, Some("durov".to_owned()));
+
+        let value = serde_json::to_value(&user).unwrap();
+        assert_eq!(value["sex"], 2);
+        assert_eq!(value["relation"], 0);
+        assert_eq!(value["university"], 1);
+        assert_eq!(value["skype"], "durov");
+        assert_eq!(value["last_seen"]["time"], 1500000000);
+        assert_eq!(value["online"], 0);
+        assert_eq!(value["photo_100"], "https://vk.com/images/camera_100.png");
+        assert_eq!(value["verified"], Value::Null);
+        assert_eq!(value["status_audio"], Value::Null);
+        assert_eq!(serde_json::from_value::<User>(value).unwrap(), user);
+    }
+
+    #[test]
+    fn civil_union_and_can_post() {
+        let user: User = serde_json::from_str(r#"{"id": 2, "first_name": "Alexandra", "last_name": "Vladimirova", "relation": 8, "can_post": 1}"#).unwrap();
+        assert_eq!(user.relation, Some(Status::CivilUnion));
+        assert_eq!(user.can_post, Some(adapters::flag::from_bool(true)));
+        assert_eq!(UserOptionField::CanPost.as_ref(), "can_post");
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Named {
+        id: i64,
+        first_name: String,
+        domain: String,
+        city: Option<City>,
+    }
+
+    impl Fields for Named {
+        type Field = UserOptionField;
+        fn fields() -> &'static [UserOptionField] {
+            &[UserOptionField::Domain, UserOptionField::City]
+        }
+    }
+
+    #[test]
+    fn typed_request() {
+        let request = Typed::<_, Named>::new(Get::new().user_ids(&[1, 2]));
+        assert!(request.to_query_string().contains("fields=domain%2Ccity"));
+
+        // City is omitted for users who haven't filled it in
+        let users: <Typed<Get, Named> as Request>::Response = serde_json::from_str(r#"[
+            {"id": 1, "first_name": "Pavel", "last_name": "Durov", "domain": "durov", "city": {"id": 2, "title": "Saint Petersburg"}},
+            {"id": 2, "first_name": "Alexandra", "last_name": "Vladimirova", "domain": "id2"}
+        ]"#).unwrap();
+        assert_eq!((users[0].id, &*users[0].first_name, &*users[0].domain), (1, "Pavel", "durov"));
+        assert_eq!(users[0].city.as_ref().map(|city| city.id), Some(2));
+        assert!(users[1].city.is_none());
+    }
+}
@@ -1,12 +1,14 @@
+use adapters::{self, Link};
 use api::{Id, Timestamp};
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct LinkInfo {
     pub status: LinkStatus,
-    pub link: String, // URL
+    #[serde(with = "adapters::link")]
+    pub link: Link,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct ScreenNameInfo {
     #[serde(rename="type")]
     pub kind: String,
@@ -2,148 +2,251 @@ use std::borrow::Borrow;
 use std::convert::AsRef;
 use std::string::ToString;
 //use std::error::Error;
-use api::{AlbumId, Attachment, Bool, Collection, Comment, Duration, FullId, Id, LikesCount, OwnerId, ReportReason, Sort, SortOrder,
-          Timestamp};
-
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+use std::cmp;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration as StdDuration, SystemTime, UNIX_EPOCH};
+use reqwest::{Body, StatusCode};
+use reqwest::header::{CONTENT_DISPOSITION, CONTENT_RANGE, CONTENT_TYPE};
+use serde_json::{self, Value};
+use adapters::{self, EmptyLink, Flag, Link, Time};
+use api::{self, AlbumId, Attachment, Author, Authored, Bool, Client, Collection, Comment, Duration, Error, ExtendedRequest, FullId,
+          Group, HttpClient, Id, LikesCount, Owner, ReportReason, RichCollection, Sort, SortOrder};
+use auth::AccessToken;
+use users::User;
+use upload::{Progress, UploadError, UploadResult, UploadServerError};
+
+/// Default chunk size for chunked video upload
+pub const UPLOAD_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Default timeout for one chunk request, enough for `UPLOAD_CHUNK_SIZE` at about 30 KB/s
+pub const UPLOAD_CHUNK_TIMEOUT: u64 = 300;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Video {
     pub id: Id,
-    pub owner_id: OwnerId,
+    pub owner_id: Owner,
     pub album_id: Option<Id>,
     pub user_id: Option<Id>,
     pub title: String,
     pub duration: Duration,
-    pub description: String,
-    pub date: Timestamp,
-    pub views: u32,
-    pub comments: u32,
-    pub photo_130: String, // URL
-    pub photo_320: String, // URL
-    pub photo_640: Option<String>, // URL
-    pub photo_800: Option<String>, // URL
-    pub adding_date: Option<Timestamp>,
-    pub player: String, // URL
-    pub can_add: Bool, // bool
-    #[serde(default)]
-    pub can_edit: Bool, // bool
-    #[serde(default)]
-    pub can_report: Bool, // bool
     #[serde(default)]
-    pub can_comment: Bool, // bool
+    pub description: String,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
     #[serde(default)]
-    pub converting: Bool, // bool
+    pub views: u32,
     #[serde(default)]
-    pub repeat: Bool, // bool
+    pub comments: u32,
+    #[serde(default, with = "adapters::empty_link")]
+    pub photo_130: EmptyLink,
+    #[serde(default, with = "adapters::empty_link")]
+    pub photo_320: EmptyLink,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_640: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_800: Option<Link>,
+    #[serde(default, with = "adapters::option_time")]
+    pub adding_date: Option<Time>,
+    #[serde(default, with = "adapters::empty_link")]
+    pub player: EmptyLink,
+    #[serde(default, with = "adapters::flag")]
+    pub can_add: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub can_edit: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub can_report: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub can_comment: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub converting: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub repeat: Flag,
     pub files: Option<VideoFiles>,
     pub likes: Option<LikesCount>,
 
     pub placer_id: Option<Id>,
-    pub tag_created: Option<Timestamp>,
+    #[serde(default, with = "adapters::option_time")]
+    pub tag_created: Option<Time>,
     pub tag_id: Option<Id>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
-pub struct VideoFiles {
-    pub mp4_240: Option<String>,
-    pub mp4_360: Option<String>,
-    pub mp4_480: Option<String>,
-    pub mp4_720: Option<String>,
-    pub external: Option<String>,
+impl Authored for Video {
+    fn author_id(&self) -> Owner {
+        self.owner_id
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VideoFiles {
+    #[serde(default, with = "adapters::option_link")]
+    pub mp4_240: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub mp4_360: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub mp4_480: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub mp4_720: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub external: Option<Link>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SavedVideo {
     pub upload_url: String,
     pub vid: Id,
-    pub owner_id: OwnerId,
+    pub owner_id: Owner,
     pub name: String,
     pub description: String,
     pub access_key: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+/// Upload server response for the last uploaded video chunk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadedVideo {
+    pub owner_id: Owner,
+    pub video_id: Id,
+    pub size: u64,
+    pub video_hash: String,
+}
+
+/// Chunked video upload state.
+///
+/// It can be stored and used later to resume interrupted upload with `Upload::resume()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub upload_url: String,
+    pub session_id: String,
+    pub owner_id: Owner,
+    pub video_id: Id,
+    /// Number of bytes acknowledged by upload server
+    pub offset: u64,
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Album {
     pub id: Id,
-    pub owner_id: OwnerId,
+    pub owner_id: Owner,
     pub title: String,
     pub count: usize,
-    pub updated_time: Timestamp,
-
-    pub photo_130: Option<String>, // URL
-    pub photo_160: Option<String>, // URL
-    pub photo_320: Option<String>, // URL
-    pub photo_640: Option<String>, // URL
-    pub photo_800: Option<String>, // URL
-
-    #[serde(default)]
-    pub is_system: Bool,
-    #[serde(default)]
-    pub can_comment: Bool,
-    #[serde(default)]
-    pub can_repost: Bool,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+    #[serde(with = "adapters::time")]
+    pub updated_time: Time,
+
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_130: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_160: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_320: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_640: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_800: Option<Link>,
+
+    #[serde(default, with = "adapters::flag")]
+    pub is_system: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub can_comment: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub can_repost: Flag,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Tag {
     user_id: Id,
     tag_id: Id,
     placer_id: Id,
     tagged_name: String,
-    date: Timestamp,
-    viewed: Bool,
+    #[serde(with = "adapters::time")]
+    date: Time,
+    #[serde(with = "adapters::flag")]
+    viewed: Flag,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Page<T> {
-    items: Vec<T>,
-    next: Option<String>,
+    pub items: Vec<T>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RichPage<T> {
+    pub items: Vec<T>,
+    pub next: Option<String>,
+    #[serde(default)]
+    pub profiles: Vec<User>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+}
+
+impl<T> RichPage<T> {
+    /// Profile or community of `owner`
+    pub fn author<'a>(&'a self, owner: Owner) -> Option<Author<'a>> {
+        api::find_author(&self.profiles, &self.groups, owner)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CatalogBlock {
-    name: String,
-    id: Id,
-    items: Vec<CatalogItem>,
-    next: Option<String>,
-    view: String, // TODO: make enum, known variant: "vertical"
-    can_hide: Bool,
+    pub name: String,
+    pub id: Id,
+    pub items: Vec<CatalogItem>,
+    pub next: Option<String>,
+    pub view: String, // TODO: make enum, known variant: "vertical"
+    #[serde(with = "adapters::flag")]
+    pub can_hide: Flag,
     #[serde(rename="type")]
-    kind: CatalogBlockKind,
+    pub kind: CatalogBlockKind,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CatalogItem {
-    id: Id,
-    owner_id: OwnerId,
-    title: String,
+    pub id: Id,
+    pub owner_id: Owner,
+    pub title: String,
     #[serde(rename="type")]
-    kind: CatalogItemKind,
+    pub kind: CatalogItemKind,
 
     // kind=Video
     #[serde(default)]
-    duration: Duration,
-    description: Option<String>,
-    #[serde(default)]
-    date: Timestamp,
-    #[serde(default)]
-    views: u32,
-    #[serde(default)]
-    comments: u32,
-    photo_130: Option<String>,
-    photo_160: Option<String>,
-    photo_320: Option<String>,
-    photo_640: Option<String>,
-    photo_800: Option<String>,
+    pub duration: Duration,
+    pub description: Option<String>,
+    #[serde(default, with = "adapters::time")]
+    pub date: Time,
     #[serde(default)]
-    can_add: Bool,
+    pub views: u32,
     #[serde(default)]
-    can_edit: Bool,
+    pub comments: u32,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_130: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_160: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_320: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_640: Option<Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_800: Option<Link>,
+    #[serde(default, with = "adapters::flag")]
+    pub can_add: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub can_edit: Flag,
 
     // kind=Album
     #[serde(default)]
-    count: u32,
-    #[serde(default)]
-    updated_time: Timestamp,
+    pub count: u32,
+    #[serde(default, with = "adapters::time")]
+    pub updated_time: Time,
+}
+
+impl Authored for CatalogItem {
+    fn author_id(&self) -> Owner {
+        self.owner_id
+    }
 }
 
 enum_str! { CatalogBlockKind {
@@ -160,7 +263,7 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct Get for ["video.get"](v => 5.44) -> Collection<Video> [Video] {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             album_id: Option<Id> = () => {Option},
             offset: usize = (0) => {},
             count: usize = (30) => {},
@@ -195,7 +298,7 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct Edit for ["video.edit"](v => 5.44) -> Bool [Video] {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             video_id: Id = () => {},
             no_comments: bool = () => {bool},
             repeat: bool = () => {bool},
@@ -212,9 +315,9 @@ request_ref! {
 request! {
     #[derive(Eq, Copy)]
     struct Add for ["video.add"](v => 5.44) -> Bool [Video] {
-        target_id: OwnerId = () => {},
+        target_id: Owner = () => {},
         video_id: Id = () => {},
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
     }
 }
 
@@ -243,8 +346,8 @@ request! {
     #[derive(Eq, Copy)]
     struct Delete for ["video.delete"](v => 5.44) -> Bool [Video] {
         video_id: Id = () => {},
-        owner_id: Option<OwnerId> = () => {Option},
-        target_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
+        target_id: Option<Owner> = {} => {Option},
     }
 }
 
@@ -252,23 +355,27 @@ request! {
     #[derive(Eq, Copy)]
     struct Restore for ["video.restore"](v => 5.44) -> Bool [Video] {
         video_id: Id = () => {},
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
     }
 }
 
 request! {
     #[derive(Eq, Copy)]
-    struct GetUserVideos for ["video.getUserVideos"](v => 5.44, extended => 0) -> Collection<Video> [Video] {
+    struct GetUserVideos for ["video.getUserVideos"](v => 5.44) -> Collection<Video> [Video] {
         user_id: Option<Id> = () => {Option},
         offset: usize = (0) => {},
         count: usize = (30) => {},
     }
 }
 
+impl ExtendedRequest for GetUserVideos {
+    type Extended = RichCollection<Video>;
+}
+
 request! {
     #[derive(Eq, Copy)]
     struct GetAlbums for ["video.getAlbums"](v => 5.44) -> Collection<Album> [Video] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         offset: usize = (0) => {},
         count: usize = (30) => {},
         need_system: bool = () => {bool},
@@ -279,7 +386,7 @@ request! {
 request! {
     #[derive(Eq, Copy)]
     struct GetAlbumById for ["video.getAlbumById"](v => 5.44) -> Album [Video] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         album_id: Id = () => {},
     }
 }
@@ -332,15 +439,15 @@ request! {
 request! {
     #[derive(Eq, Copy)]
     struct ReorderVideos for ["video.reorderVideos"](v => 5.44) -> Bool [Video] {
-        target_id: Option<OwnerId> = () => {Option},
+        target_id: Option<Owner> = {} => {Option},
         album_id: Option<Id> = () => {Option},
 
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         video_id: Id = () => {},
 
-        before_owner_id: Option<OwnerId> = () => {Option},
+        before_owner_id: Option<Owner> = {} => {Option},
         before_video_id: Option<Id> = () => {Option},
-        after_owner_id: Option<OwnerId> = () => {Option},
+        after_owner_id: Option<Owner> = {} => {Option},
         after_video_id: Option<Id> = () => {Option},
     }
 }
@@ -349,9 +456,9 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct AddToAlbum for ["video.addToAlbum"](v => 5.44) -> Bool [Video] {
         sized {
-            target_id: Option<OwnerId> = () => {Option},
+            target_id: Option<Owner> = {} => {Option},
             album_id: Option<Id> = () => {Option},
-            owner_id: OwnerId = () => {},
+            owner_id: Owner = () => {},
             video_id: Id = () => {},
         }
         unsized {
@@ -364,9 +471,9 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct RemoveFromAlbum for ["video.removeFromAlbum"](v => 5.44) -> Bool [Video] {
         sized {
-            target_id: Option<OwnerId> = () => {Option},
+            target_id: Option<Owner> = {} => {Option},
             album_id: Option<Id> = () => {Option},
-            owner_id: OwnerId = () => {},
+            owner_id: Owner = () => {},
             video_id: Id = () => {},
         }
         unsized {
@@ -378,8 +485,8 @@ request_ref! {
 request! {
     #[derive(Eq, Copy)]
     struct GetAlbumsByVideo for ["video.getAlbumsByVideo"](v => 5.44) -> Collection<Album> [Video] {
-        target_id: Option<OwnerId> = () => {Option},
-        owner_id: OwnerId = () => {},
+        target_id: Option<Owner> = {} => {Option},
+        owner_id: Owner = () => {},
         video_id: Id = () => {},
         extended: bool = (true) => {bool},
     }
@@ -387,8 +494,8 @@ request! {
 
 request! {
     #[derive(Eq, Copy)]
-    struct GetComments for ["video.getComments"](v => 5.44, extended => 0) -> Collection<Comment> [Video] {
-        owner_id: Option<OwnerId> = () => {Option},
+    struct GetComments for ["video.getComments"](v => 5.44) -> Collection<Comment> [Video] {
+        owner_id: Option<Owner> = {} => {Option},
         video_id: Id = () => {},
         need_likes: bool = () => {bool},
         start_comment_id: Option<Id> = () => {Option},
@@ -398,11 +505,15 @@ request! {
     }
 }
 
+impl ExtendedRequest for GetComments {
+    type Extended = RichCollection<Comment>;
+}
+
 request_ref! {
     #[derive(Eq, Copy)]
     struct CreateComment for ["video.createComment"](v => 5.44) -> Id [Video] {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             video_id: Id = () => {},
             from_group: bool = () => {bool},
             reply_to_comment: Option<Id> = () => {Option},
@@ -418,7 +529,7 @@ request_ref! {
 request! {
     #[derive(Eq, Copy)]
     struct DeleteComment for ["video.deleteComment"](v => 5.44) -> Bool [Video] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         comment_id: Id = () => {},
     }
 }
@@ -426,7 +537,7 @@ request! {
 request! {
     #[derive(Eq, Copy)]
     struct RestoreComment for ["video.restoreComment"](v => 5.44) -> Bool [Video] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         comment_id: Id = () => {},
     }
 }
@@ -435,7 +546,7 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct EditComment for ["video.editComment"](v => 5.44) -> Bool [Video] {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             comment_id: Id = () => {},
         }
         unsized {
@@ -448,7 +559,7 @@ request_ref! {
 request! {
     #[derive(Eq, Copy)]
     struct GetTags for ["video.getTags"](v => 5.44) -> Vec<Tag> [Video] {
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         video_id: Id = () => {},
     }
 }
@@ -457,7 +568,7 @@ request_ref! {
     #[derive(Eq, Copy)]
     struct PutTag for ["video.putTag"](v => 5.44) -> Id [Video] {
         sized {
-            owner_id: Option<OwnerId> = () => {Option},
+            owner_id: Option<Owner> = {} => {Option},
             user_id: Id = () => {},
             video_id: Id = () => {},
         }
@@ -471,7 +582,7 @@ request! {
     #[derive(Eq, Copy)]
     struct RemoveTag for ["video.removeTag"](v => 5.44) -> Bool [Video] {
         tag_id: Id = () => {},
-        owner_id: Option<OwnerId> = () => {Option},
+        owner_id: Option<Owner> = {} => {Option},
         video_id: Id = () => {},
     }
 }
@@ -488,7 +599,7 @@ request_ref! {
     #[derive(Copy, Eq)]
     struct Report for ["video.report"](v => 5.44) -> Bool [Video] {
         sized {
-            owner_id: OwnerId = () => {},
+            owner_id: Owner = () => {},
             video_id: Id = () => {},
             reason: ReportReason = (ReportReason::Spam) => {AsRef},
         }
@@ -502,7 +613,7 @@ request_ref! {
 request! {
     #[derive(Copy, Eq)]
     struct ReportComment for ["video.reportComment"](v => 5.44) -> Bool [Video] {
-        owner_id: OwnerId = () => {},
+        owner_id: Owner = () => {},
         comment_id: Id = () => {},
         reason: ReportReason = (ReportReason::Spam) => {AsRef},
     }
@@ -510,7 +621,7 @@ request! {
 
 request_ref! {
     #[derive(Eq, Copy)]
-    struct GetCatalog for ["video.getCatalog"](v => 5.44, extended => 0) -> Page<CatalogBlock> {
+    struct GetCatalog for ["video.getCatalog"](v => 5.44) -> Page<CatalogBlock> {
         sized {
             count: usize = (10) => {},
             items_count: usize = (10) => {},
@@ -522,6 +633,10 @@ request_ref! {
     }
 }
 
+impl<'a> ExtendedRequest for GetCatalog<'a> {
+    type Extended = RichPage<CatalogBlock>;
+}
+
 request_ref! {
     #[derive(Eq, Copy)]
     // TODO: not sure about return type
@@ -569,3 +684,335 @@ enum_str! { Filter {
     Short = "short",
     Long = "long",
 }}
+
+enum Chunk {
+    Partial(u64),
+    Done,
+}
+
+/// Chunked video upload pipeline: `video.save`, upload file in byte ranges and `video.get`.
+///
+/// File is streamed from disk chunk by chunk, and a failed chunk is resent
+/// from the last acknowledged byte up to `retries` times, waiting `retry_delay`
+/// doubled after each consecutive failure. A chunk the server acknowledged
+/// no new bytes of counts as failed.
+#[derive(Debug)]
+pub struct Upload {
+    file: File,
+    name: String,
+    chunk_size: u64,
+    timeout: StdDuration,
+    retries: u32,
+    retry_delay: StdDuration,
+    progress: Progress,
+}
+
+impl Upload {
+    pub fn open<P: AsRef<Path>>(path: P) -> UploadResult<Upload> {
+        let path = path.as_ref();
+        Ok(Upload {
+            file: File::open(path)?,
+            name: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            chunk_size: UPLOAD_CHUNK_SIZE,
+            timeout: StdDuration::from_secs(UPLOAD_CHUNK_TIMEOUT),
+            retries: 3,
+            retry_delay: StdDuration::from_secs(1),
+            progress: Progress::new(),
+        })
+    }
+
+    pub fn chunk_size(&mut self, value: u64) -> &mut Self {
+        self.chunk_size = cmp::max(value, 1);
+        self
+    }
+
+    /// Timeout for one chunk request, should fit `chunk_size` at the slowest expected speed
+    pub fn timeout(&mut self, value: StdDuration) -> &mut Self {
+        self.timeout = value;
+        self
+    }
+
+    pub fn retries(&mut self, value: u32) -> &mut Self {
+        self.retries = value;
+        self
+    }
+
+    pub fn retry_delay(&mut self, value: StdDuration) -> &mut Self {
+        self.retry_delay = value;
+        self
+    }
+
+    pub fn progress(&mut self, value: Progress) -> &mut Self {
+        self.progress = value;
+        self
+    }
+
+    /// Run the whole pipeline for the video described by `save` request
+    pub fn upload(&mut self, api: &Client, token: Option<&AccessToken>, save: &Save) -> UploadResult<Video> {
+        let mut session = self.start(api, token, save)?;
+        self.resume(api, token, &mut session)
+    }
+
+    /// Call `video.save` and start new upload session without sending any data.
+    ///
+    /// Empty file is rejected with `UploadError::Empty` before `video.save` is called.
+    pub fn start(&mut self, api: &Client, token: Option<&AccessToken>, save: &Save) -> UploadResult<UploadSession> {
+        let total = file_size(&self.file)?;
+        let saved = api.call(token, save).map_err(UploadError::GetServer)?;
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos()).unwrap_or(0);
+
+        Ok(UploadSession {
+            upload_url: saved.upload_url,
+            session_id: format!("{:x}{:x}", nanos, process::id()),
+            owner_id: saved.owner_id,
+            video_id: saved.vid,
+            offset: 0,
+            total,
+        })
+    }
+
+    /// Send the rest of the file starting from `session.offset`, keeping session up to date
+    pub fn resume(&mut self, api: &Client, token: Option<&AccessToken>, session: &mut UploadSession) -> UploadResult<Video> {
+        let client = HttpClient::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(|err| UploadError::Upload(Error::Http(err)))?;
+
+        let mut failures = 0;
+        while session.offset < session.total {
+            // Bytes of failed or partially acknowledged chunk are not counted as sent
+            self.progress.reset(session.offset, session.total);
+            if self.progress.is_cancelled() {
+                return Err(UploadError::Cancelled);
+            }
+
+            let sent = self.send_chunk(&client, session);
+            let err = match advance(session, sent) {
+                Ok(()) => {
+                    failures = 0;
+                    continue;
+                }
+                Err(UploadError::Cancelled) => return Err(UploadError::Cancelled),
+                Err(err) => err,
+            };
+
+            failures += 1;
+            if failures > self.retries {
+                return Err(err);
+            }
+            thread::sleep(self.retry_delay * (1 << cmp::min(failures - 1, 5)));
+        }
+        self.progress.reset(session.total, session.total);
+
+        let videos = api.call(token, Get::new().videos(&[FullId(session.owner_id, session.video_id)]))
+            .map_err(UploadError::Save)?;
+        videos.items.into_iter().next().ok_or(UploadError::NotSaved)
+    }
+
+    fn send_chunk(&mut self, client: &HttpClient, session: &UploadSession) -> UploadResult<Chunk> {
+        let len = cmp::min(self.chunk_size, session.total - session.offset);
+        self.file.seek(SeekFrom::Start(session.offset))?;
+        let body = Body::sized(self.progress.reader(self.file.try_clone()?.take(len)), len);
+
+        let mut resp = client
+            .post(&*session.upload_url)
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header(CONTENT_DISPOSITION, content_disposition(&self.name))
+            .header(CONTENT_RANGE, content_range(session, len))
+            .header("Session-ID", &*session.session_id)
+            .body(body)
+            .send()
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|err| self.progress.error(Error::Http(err)))?;
+
+        if resp.status() == StatusCode::CREATED {
+            let text = resp.text().map_err(|err| self.progress.error(Error::Http(err)))?;
+            Ok(Chunk::Partial(received_offset(&text, session.offset)))
+        } else {
+            let value: Value = serde_json::from_reader(resp).map_err(|err| UploadError::Upload(Error::Json(err)))?;
+            uploaded(value).map(|_| Chunk::Done)
+        }
+    }
+}
+
+/// Move `session` forward by the result of sending one chunk, failing if no new bytes were acknowledged
+fn advance(session: &mut UploadSession, sent: UploadResult<Chunk>) -> UploadResult<()> {
+    match sent? {
+        Chunk::Done => session.offset = session.total,
+        Chunk::Partial(offset) if offset > session.offset => session.offset = offset,
+        Chunk::Partial(_) => return Err(UploadError::Stalled),
+    }
+    Ok(())
+}
+
+fn file_size(file: &File) -> UploadResult<u64> {
+    match file.metadata()?.len() {
+        0 => Err(UploadError::Empty),
+        total => Ok(total),
+    }
+}
+
+fn content_range(session: &UploadSession, len: u64) -> String {
+    format!("bytes {}-{}/{}", session.offset, session.offset + len - 1, session.total)
+}
+
+/// `Content-Disposition` with ASCII fallback `filename` and percent-encoded UTF-8 `filename*` (RFC 6266)
+fn content_disposition(name: &str) -> String {
+    let fallback: String = name.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' { c } else { '_' })
+        .collect();
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
+
+/// Offset after the first byte range in a 201 response body like "0-1048575/5242880",
+/// `offset` if the body can't be parsed
+fn received_offset(text: &str, offset: u64) -> u64 {
+    text.trim()
+        .split('/')
+        .next()
+        .and_then(|ranges| ranges.split(',').next())
+        .and_then(|range| range.split('-').nth(1))
+        .and_then(|end| end.parse::<u64>().ok())
+        .map_or(offset, |end| end + 1)
+}
+
+/// Parse upload server response to the last chunk
+fn uploaded(value: Value) -> UploadResult<UploadedVideo> {
+    if let Some(error) = value.get("error") {
+        return Err(UploadError::Upload(Error::Upload(UploadServerError::from(error))));
+    }
+    serde_json::from_value(value).map_err(|err| UploadError::Upload(Error::Json(err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io};
+    use serde_json::{self, Value};
+    use super::*;
+
+    const VIDEO: &str = r#"{
+        "id": 30,
+        "owner_id": 1,
+        "title": "Demo",
+        "duration": 60,
+        "description": "",
+        "date": 1500000000,
+        "views": 100,
+        "comments": 2,
+        "photo_130": "https://pp.userapi.com/130.jpg",
+        "photo_320": "",
+        "adding_date": 1500000100,
+        "player": "https://vk.com/video_ext.php?oid=1&id=30",
+        "can_add": 1,
+        "can_edit": 0,
+        "can_comment": 1,
+        "files": {"mp4_240": "https://cs.vk.me/240.mp4"},
+        "likes": {"user_likes": 1, "count": 3}
+    }"#;
+
+    #[test]
+    fn video_round_trip() {
+        let video: Video = serde_json::from_str(VIDEO).unwrap();
+        assert_eq!(adapters::empty_link::as_str(&video.photo_320), "");
+
+        let value = serde_json::to_value(&video).unwrap();
+        assert_eq!(value["date"], 1500000000);
+        assert_eq!(value["adding_date"], 1500000100);
+        assert_eq!(value["tag_created"], Value::Null);
+        assert_eq!(value["can_add"], 1);
+        assert_eq!(value["can_edit"], 0);
+        assert_eq!(value["photo_130"], "https://pp.userapi.com/130.jpg");
+        assert_eq!(value["photo_320"], "");
+        assert_eq!(value["player"], "https://vk.com/video_ext.php?oid=1&id=30");
+        assert_eq!(value["files"]["mp4_240"], "https://cs.vk.me/240.mp4");
+        assert_eq!(value["files"]["mp4_720"], Value::Null);
+        assert_eq!(serde_json::from_value::<Video>(value).unwrap(), video);
+    }
+
+    fn session(offset: u64, total: u64) -> UploadSession {
+        UploadSession {
+            upload_url: "https://pu.vk.com/upload".into(),
+            session_id: "abc".into(),
+            owner_id: Owner::User(1),
+            video_id: 30,
+            offset,
+            total,
+        }
+    }
+
+    #[test]
+    fn chunk_headers() {
+        assert_eq!(content_range(&session(0, 100), 40), "bytes 0-39/100");
+        assert_eq!(content_range(&session(80, 100), 20), "bytes 80-99/100");
+        assert_eq!(content_disposition("video.mp4"), "attachment; filename=\"video.mp4\"; filename*=UTF-8''video.mp4");
+        assert_eq!(content_disposition("my \"best\" видео.mp4"),
+                   "attachment; filename=\"my _best_ _____.mp4\"; \
+                    filename*=UTF-8''my%20%22best%22%20%D0%B2%D0%B8%D0%B4%D0%B5%D0%BE.mp4");
+    }
+
+    #[test]
+    fn partial_responses() {
+        assert_eq!(received_offset("0-1048575/5242880", 0), 1048576);
+        assert_eq!(received_offset("0-99,200-299/5242880\n", 0), 100);
+        assert_eq!(received_offset("", 40), 40);
+        assert_eq!(received_offset("garbage", 40), 40);
+    }
+
+    #[test]
+    fn final_responses() {
+        let value = serde_json::from_str(r#"{"owner_id": 1, "video_id": 30, "size": 100, "video_hash": "f00"}"#).unwrap();
+        assert_eq!(uploaded(value).unwrap().video_id, 30);
+
+        let value = serde_json::from_str(r#"{"error": "invalid_session", "error_code": 8}"#).unwrap();
+        match uploaded(value) {
+            Err(UploadError::Upload(Error::Upload(_))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        match uploaded(Value::Null) {
+            Err(UploadError::Upload(Error::Json(_))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn empty_file_is_rejected() {
+        let path = env::temp_dir().join(format!("vkrs-empty-video-{}.mp4", process::id()));
+        File::create(&path).unwrap();
+        let upload = Upload::open(&path);
+        fs::remove_file(&path).unwrap();
+        match file_size(&upload.unwrap().file) {
+            Err(UploadError::Empty) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resume_after_failed_chunk() {
+        let mut session = session(0, 100);
+        advance(&mut session, Ok(Chunk::Partial(40))).unwrap();
+        assert_eq!(session.offset, 40);
+
+        // Failed chunk is resent from the last acknowledged byte
+        assert!(advance(&mut session, Err(UploadError::Io(io::ErrorKind::TimedOut.into()))).is_err());
+        assert_eq!(session.offset, 40);
+        match advance(&mut session, Ok(Chunk::Partial(40))) {
+            Err(UploadError::Stalled) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(content_range(&session, 40), "bytes 40-79/100");
+
+        advance(&mut session, Ok(Chunk::Partial(80))).unwrap();
+        assert_eq!(content_range(&session, 20), "bytes 80-99/100");
+        advance(&mut session, Ok(Chunk::Done)).unwrap();
+        assert_eq!(session.offset, 100);
+    }
+}
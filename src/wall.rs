@@ -1,68 +1,556 @@
-use users::UserOptionField;
-use api::{Bool, Collection, Id, LikesCount, OwnerId, Timestamp};
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use serde::{de, ser};
+use serde_json::{self, Map, Value};
+use adapters::{self, Flag, Time};
+use api::{self, Author, Authored, Bool, Client, Collection, ExtendedRequest, FullId, Group, Id, LikesCount, Owner, ReportReason, Result,
+          RichCollection, SortOrder};
+use audio::Audio;
+use auth::AccessToken;
+use newsfeed::Note;
+use photos::Photo;
+use users::{User, UserOptionField};
+use video::Video;
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct WallPost {
-    id: Id,
-    owner_id: OwnerId,
-    from_id: OwnerId,
-    date: Timestamp,
-    text: String,
-    reply_owner_id: OwnerId,
-    reply_post_id: Id,
-    friends_only: Bool,
-    comments: CommentsCount,
-    likes: LikesCount,
-    reposts: RepostsCount,
-    post_type: PostType,
-    post_source: PostSource, // TODO
-    attachments: Vec<Attachment>, // TODO
-    geo: GeoLocation, // TODO
-    signer_id: Id,
-    copy_history: Option<Vec<RepostInfo>>, // TODO
-    can_pin: Bool,
-    is_pinned: Bool,
-}
-
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy)]
+    pub id: Id,
+    pub owner_id: Owner,
+    pub from_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    pub text: String,
+    #[serde(default)]
+    pub reply_owner_id: Owner,
+    #[serde(default)]
+    pub reply_post_id: Id,
+    #[serde(default, with = "adapters::flag")]
+    pub friends_only: Flag,
+    pub comments: CommentsCount,
+    pub likes: LikesCount,
+    pub reposts: RepostsCount,
+    pub post_type: PostType,
+    pub post_source: Option<PostSource>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    pub geo: Option<GeoLocation>,
+    #[serde(default)]
+    pub signer_id: Id,
+    pub copy_history: Option<Vec<RepostInfo>>,
+    #[serde(default, with = "adapters::flag")]
+    pub can_pin: Flag,
+    #[serde(default, with = "adapters::flag")]
+    pub is_pinned: Flag,
+}
+
+impl Authored for WallPost {
+    fn author_id(&self) -> Owner {
+        self.from_id
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
 pub struct CommentsCount {
-    count: u32,
-    can_post: Bool,
+    pub count: u32,
+    #[serde(default, with = "adapters::flag")]
+    pub can_post: Flag,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
 pub struct RepostsCount {
-    count: u32,
-    user_reposted: Bool,
+    pub count: u32,
+    #[serde(with = "adapters::flag")]
+    pub user_reposted: Flag,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
 pub enum PostType {
+    #[serde(rename="post")]
     Post,
+    #[serde(rename="copy")]
     Copy,
+    #[serde(rename="reply")]
     Reply,
+    #[serde(rename="postpone")]
     Postpone,
-    Suggest
+    #[serde(rename="suggest")]
+    Suggest,
+    /// Post of type not known to this library
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct PostSource {
+    #[serde(rename="type")]
+    pub kind: PostSourceKind,
+    pub platform: Option<String>, // "android", "iphone" or "wphone" for `PostSourceKind::Api`
+    pub data: Option<String>,
+    #[serde(default, with = "adapters::option_link")]
+    pub url: Option<adapters::Link>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub enum PostSourceKind {
+    #[serde(rename="vk")]
+    Vk,
+    #[serde(rename="widget")]
+    Widget,
+    #[serde(rename="api")]
+    Api,
+    #[serde(rename="rss")]
+    Rss,
+    #[serde(rename="sms")]
+    Sms,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct GeoLocation {
+    #[serde(rename="type")]
+    pub kind: String,
+    /// Latitude and longitude separated by space
+    pub coordinates: String,
+    pub place: Option<Place>,
+}
+
+impl GeoLocation {
+    /// Parsed latitude and longitude
+    pub fn lat_long(&self) -> Option<(f64, f64)> {
+        let mut parts = self.coordinates.split_whitespace().map(str::parse);
+        match (parts.next(), parts.next()) {
+            (Some(Ok(lat)), Some(Ok(long))) => Some((lat, long)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Place {
+    pub id: Option<Id>,
+    pub title: Option<String>,
+    pub country: Option<Id>,
+    pub city: Option<Id>,
+    #[serde(default, with = "adapters::option_time")]
+    pub created: Option<Time>,
+    #[serde(default, with = "adapters::option_link")]
+    pub icon: Option<adapters::Link>,
+    pub checkins: Option<u32>,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy)]
-pub struct PostSource;
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy)]
-pub struct Attachment;
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy)]
-pub struct GeoLocation;
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy)]
-pub struct RepostInfo;
+/// Original post in `copy_history` of a repost
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct RepostInfo {
+    pub id: Id,
+    pub owner_id: Owner,
+    pub from_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    pub post_type: PostType,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    pub post_source: Option<PostSource>,
+    pub geo: Option<GeoLocation>,
+    pub signer_id: Option<Id>,
+}
+
+/// Media attached to post, comment or message
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Attachment {
+    Photo(Photo),
+    PostedPhoto(PostedPhoto),
+    Video(Box<Video>),
+    Audio(Audio),
+    Doc(Doc),
+    Graffiti(Graffiti),
+    Link(Box<Link>),
+    Note(Note),
+    App(App),
+    Poll(Poll),
+    Page(Page),
+    Album(Box<PhotoAlbum>),
+    PhotosList(Vec<String>),
+    Market(Box<MarketItem>),
+    MarketAlbum(Box<MarketAlbum>),
+    Sticker(Sticker),
+    /// Attachment of type not known to this library, or which failed to parse
+    Unknown { kind: String, object: Value },
+}
+
+impl Attachment {
+    /// Build attachment from its `type` and object under the key of the same name.
+    ///
+    /// Unlike deserialization, fails if the object doesn't match its known type.
+    pub fn from_object(kind: &str, object: Value) -> serde_json::Result<Attachment> {
+        use self::Attachment::*;
+        use serde_json::from_value as from;
+
+        Ok(match kind {
+            "photo" => Photo(from(object)?),
+            "posted_photo" => PostedPhoto(from(object)?),
+            "video" => Video(Box::new(from(object)?)),
+            "audio" => Audio(from(object)?),
+            "doc" => Doc(from(object)?),
+            "graffiti" => Graffiti(from(object)?),
+            "link" => Link(Box::new(from(object)?)),
+            "note" => Note(from(object)?),
+            "app" => App(from(object)?),
+            "poll" => Poll(from(object)?),
+            "page" => Page(from(object)?),
+            "album" => Album(Box::new(from(object)?)),
+            "photos_list" => PhotosList(from(object)?),
+            "market" => Market(Box::new(from(object)?)),
+            "market_album" => MarketAlbum(Box::new(from(object)?)),
+            "sticker" => Sticker(from(object)?),
+            _ => Unknown { kind: kind.to_owned(), object },
+        })
+    }
+}
+
+impl Attachment {
+    /// Attachment type, as in its `type` field
+    pub fn kind(&self) -> &str {
+        use self::Attachment::*;
+
+        match *self {
+            Photo(_) => "photo",
+            PostedPhoto(_) => "posted_photo",
+            Video(_) => "video",
+            Audio(_) => "audio",
+            Doc(_) => "doc",
+            Graffiti(_) => "graffiti",
+            Link(_) => "link",
+            Note(_) => "note",
+            App(_) => "app",
+            Poll(_) => "poll",
+            Page(_) => "page",
+            Album(_) => "album",
+            PhotosList(_) => "photos_list",
+            Market(_) => "market",
+            MarketAlbum(_) => "market_album",
+            Sticker(_) => "sticker",
+            Unknown { ref kind, .. } => kind,
+        }
+    }
+}
+
+impl ser::Serialize for Attachment {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error> {
+        use self::Attachment::*;
+        use serde::ser::SerializeMap;
+
+        let kind = self.kind();
+        let mut map = s.serialize_map(Some(2))?;
+        map.serialize_entry("type", kind)?;
+        match *self {
+            Photo(ref object) => map.serialize_entry(kind, object),
+            PostedPhoto(ref object) => map.serialize_entry(kind, object),
+            Video(ref object) => map.serialize_entry(kind, object),
+            Audio(ref object) => map.serialize_entry(kind, object),
+            Doc(ref object) => map.serialize_entry(kind, object),
+            Graffiti(ref object) => map.serialize_entry(kind, object),
+            Link(ref object) => map.serialize_entry(kind, object),
+            Note(ref object) => map.serialize_entry(kind, object),
+            App(ref object) => map.serialize_entry(kind, object),
+            Poll(ref object) => map.serialize_entry(kind, object),
+            Page(ref object) => map.serialize_entry(kind, object),
+            Album(ref object) => map.serialize_entry(kind, object),
+            PhotosList(ref object) => map.serialize_entry(kind, object),
+            Market(ref object) => map.serialize_entry(kind, object),
+            MarketAlbum(ref object) => map.serialize_entry(kind, object),
+            Sticker(ref object) => map.serialize_entry(kind, object),
+            Unknown { ref object, .. } => map.serialize_entry(kind, object),
+        }?;
+        map.end()
+    }
+}
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Clone, Copy)]
+impl<'de> de::Deserialize<'de> for Attachment {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> ::std::result::Result<Attachment, D::Error> {
+        let mut object: Map<String, Value> = de::Deserialize::deserialize(d)?;
+        let kind = match object.remove("type") {
+            Some(Value::String(kind)) => kind,
+            _ => return Err(de::Error::missing_field("type")),
+        };
+        let value = object.remove(&kind).unwrap_or(Value::Null);
+        // One malformed attachment shouldn't fail the whole post or message
+        Ok(Attachment::from_object(&kind, value.clone()).unwrap_or(Attachment::Unknown { kind, object: value }))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct PostedPhoto {
+    pub id: Id,
+    pub owner_id: Owner,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_130: Option<adapters::Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_604: Option<adapters::Link>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Doc {
+    pub id: Id,
+    pub owner_id: Owner,
+    pub title: String,
+    pub size: u64,
+    pub ext: String,
+    #[serde(default, with = "adapters::option_link")]
+    pub url: Option<adapters::Link>,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    #[serde(rename="type")]
+    pub kind: u8, // 1 text, 2 archive, 3 gif, 4 image, 5 audio, 6 video, 7 ebook, 8 unknown
+    pub access_key: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Graffiti {
+    pub id: Id,
+    pub owner_id: Owner,
+    #[serde(default, with = "adapters::option_link")]
+    pub url: Option<adapters::Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_130: Option<adapters::Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_604: Option<adapters::Link>,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    pub access_key: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Link {
+    #[serde(with = "adapters::link")]
+    pub url: adapters::Link,
+    #[serde(default)]
+    pub title: String,
+    pub caption: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    pub photo: Option<Photo>,
+    pub preview_page: Option<String>,
+    #[serde(default, with = "adapters::option_link")]
+    pub preview_url: Option<adapters::Link>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct App {
+    pub id: Id,
+    pub name: String,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_130: Option<adapters::Link>,
+    #[serde(default, with = "adapters::option_link")]
+    pub photo_604: Option<adapters::Link>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Poll {
+    pub id: Id,
+    pub owner_id: Owner,
+    #[serde(with = "adapters::time")]
+    pub created: Time,
+    pub question: String,
+    pub votes: u32,
+    pub answer_id: Option<Id>, // 0 if current user has not voted
+    #[serde(default)]
+    pub answers: Vec<PollAnswer>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct PollAnswer {
+    pub id: Id,
+    pub text: String,
+    pub votes: u32,
+}
+
+/// Wiki page
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Page {
+    pub id: Id,
+    pub group_id: Id,
+    pub title: String,
+    pub creator_id: Option<Id>,
+    #[serde(default, with = "adapters::option_time")]
+    pub created: Option<Time>,
+    #[serde(default, with = "adapters::option_time")]
+    pub edited: Option<Time>,
+    pub views: Option<u32>,
+    #[serde(default, with = "adapters::option_link")]
+    pub view_url: Option<adapters::Link>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct PhotoAlbum {
+    pub id: Id,
+    pub owner_id: Owner,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub thumb: Option<Photo>,
+    #[serde(with = "adapters::time")]
+    pub created: Time,
+    #[serde(with = "adapters::time")]
+    pub updated: Time,
+    pub size: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct MarketItem {
+    pub id: Id,
+    pub owner_id: Owner,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub price: Price,
+    #[serde(default, with = "adapters::option_link")]
+    pub thumb_photo: Option<adapters::Link>,
+    #[serde(default, with = "adapters::option_time")]
+    pub date: Option<Time>,
+    #[serde(default)]
+    pub availability: u8, // 0 available, 1 deleted, 2 unavailable
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Price {
+    pub amount: String, // in hundredths of currency unit
+    pub currency: Currency,
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Currency {
+    pub id: Id,
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct MarketAlbum {
+    pub id: Id,
+    pub owner_id: Owner,
+    pub title: String,
+    pub photo: Option<Photo>,
+    pub count: u32,
+    #[serde(default, with = "adapters::option_time")]
+    pub updated_time: Option<Time>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Sticker {
+    pub product_id: Option<Id>,
+    pub sticker_id: Id,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct WallComment {
+    pub id: Id,
+    pub from_id: Owner,
+    #[serde(default)]
+    pub owner_id: Owner,
+    #[serde(default)]
+    pub post_id: Id,
+    #[serde(with = "adapters::time")]
+    pub date: Time,
+    #[serde(default)]
+    pub text: String,
+    pub reply_to_user: Option<Owner>,
+    pub reply_to_comment: Option<Id>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// Ids of parent comments, empty for top level comments
+    #[serde(default)]
+    pub parents_stack: Vec<Id>,
+    pub likes: Option<LikesCount>,
+    /// Replies to top level comment
+    pub thread: Option<Thread>,
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+impl Authored for WallComment {
+    fn author_id(&self) -> Owner {
+        self.from_id
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Thread {
+    pub count: u32,
+    #[serde(default)]
+    pub items: Vec<WallComment>,
+    #[serde(default)]
+    pub can_post: bool,
+    #[serde(default)]
+    pub show_reply_button: bool,
+    #[serde(default)]
+    pub groups_can_post: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Comments {
+    /// Total number of comments, including replies
+    pub count: u32,
+    /// Number of comments on requested level
+    #[serde(default)]
+    pub current_level_count: u32,
+    #[serde(default)]
+    pub can_post: bool,
+    #[serde(default)]
+    pub show_reply_button: bool,
+    #[serde(default)]
+    pub groups_can_post: bool,
+    pub items: Vec<WallComment>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct CommentItems {
+    pub items: Vec<WallComment>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct CommentId {
+    pub comment_id: Id,
+    #[serde(default)]
+    pub parents_stack: Vec<Id>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
 pub struct PostId {
-    post_id: Id,
+    pub post_id: Id,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub struct RepostResult {
+    #[serde(with = "adapters::flag")]
+    pub success: Flag,
+    pub post_id: Id,
+    pub reposts_count: u32,
+    pub likes_count: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Reposts {
+    pub items: Vec<WallPost>,
+    #[serde(default)]
+    pub profiles: Vec<User>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+}
+
+impl Reposts {
+    /// Profile or community of `owner`
+    pub fn author<'a>(&'a self, owner: Owner) -> Option<Author<'a>> {
+        api::find_author(&self.profiles, &self.groups, owner)
+    }
 }
 
 request_ref! {
-    struct Get for ["wall.get"](v => 5.44, extended => 0) -> Collection<WallPost> {
+    struct Get for ["wall.get"](v => 5.44) -> Collection<WallPost> {
         sized {
-            owner_id: OwnerId = () => {},
+            owner_id: Owner = () => {},
             filter: Filter = (Filter::All) => {AsRef},
             offset: usize = (0) => {},
             count: usize = (100) => {},
@@ -74,29 +562,502 @@ request_ref! {
     }
 }
 
+impl<'a> ExtendedRequest for Get<'a> {
+    type Extended = RichCollection<WallPost>;
+}
+
 request_ref! {
     struct Post for ["wall.post"](v => 5.44) -> PostId [Wall] {
         sized {
-            owner_id: OwnerId = () => {},
+            owner_id: Owner = () => {},
             friend_only: bool = (true) => {bool},
             from_group: bool = (false) => {bool},
             signed: bool = (false) => {bool},
-            publish_date: Timestamp = (0) => {},
-            lat: f32 = () => {},
-            long: f32 = () => {},
-            place_id: Id = () => {},
+            publish_date: Time = () => {Time},
+            lat: Option<f32> = () => {Option},
+            long: Option<f32> = () => {Option},
+            place_id: Option<Id> = () => {Option},
+            post_id: Id = () => {},
+        }
+        unsized {
+            message: str = ("") => {=},
+            attachments: [api::Attachment] = (&[][..]) => {Vec},
+            services: str = ("") => {=},
+        }
+    }
+}
+
+request_ref! {
+    struct Edit for ["wall.edit"](v => 5.44) -> Bool [Wall] {
+        sized {
+            owner_id: Owner = () => {},
             post_id: Id = () => {},
+            friends_only: bool = (false) => {bool},
+            signed: bool = (false) => {bool},
+            publish_date: Time = () => {Time},
+            lat: Option<f32> = () => {Option},
+            long: Option<f32> = () => {Option},
+            place_id: Option<Id> = () => {Option},
         }
         unsized {
             message: str = ("") => {=},
+            attachments: [api::Attachment] = (&[][..]) => {Vec},
             services: str = ("") => {=},
         }
     }
 }
 
+request! {
+    #[derive(Eq, Copy)]
+    struct Delete for ["wall.delete"](v => 5.44) -> Bool [Wall] {
+        owner_id: Owner = () => {},
+        post_id: Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct Restore for ["wall.restore"](v => 5.44) -> Bool [Wall] {
+        owner_id: Owner = () => {},
+        post_id: Id = () => {},
+    }
+}
+
+request_ref! {
+    struct GetById for ["wall.getById"](v => 5.44, extended => 0) -> Vec<WallPost> {
+        sized {
+            copy_history_depth: u32 = (2) => {},
+        }
+        unsized {
+            posts: [FullId] = (&[][..]) => {Vec},
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request_ref! {
+    struct Repost for ["wall.repost"](v => 5.44) -> RepostResult [Wall] {
+        sized {
+            group_id: Option<Id> = () => {Option},
+        }
+        unsized {
+            // Object to share, like "wall-123_456" or "photo123_456"
+            object: str = ("") => {=},
+            message: str = ("") => {=},
+        }
+    }
+}
+
+request_ref! {
+    struct Search for ["wall.search"](v => 5.44, extended => 0) -> Collection<WallPost> {
+        sized {
+            owner_id: Option<Owner> = {} => {Option},
+            owners_only: bool = (false) => {bool},
+            offset: usize = (0) => {},
+            count: usize = (20) => {},
+        }
+        unsized {
+            domain: str = ("") => {=},
+            query: str = ("") => {=},
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct Pin for ["wall.pin"](v => 5.44) -> Bool [Wall] {
+        owner_id: Option<Owner> = {} => {Option},
+        post_id: Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct Unpin for ["wall.unpin"](v => 5.44) -> Bool [Wall] {
+        owner_id: Option<Owner> = {} => {Option},
+        post_id: Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct GetReposts for ["wall.getReposts"](v => 5.44) -> Reposts [Wall] {
+        owner_id: Option<Owner> = {} => {Option},
+        post_id: Id = () => {},
+        offset: usize = (0) => {},
+        count: usize = (20) => {},
+    }
+}
+
+request_ref! {
+    /// Create hidden post to be used in ads, is not shown on community wall
+    struct PostAdsStealth for ["wall.postAdsStealth"](v => 5.44) -> PostId [Wall] {
+        sized {
+            owner_id: Owner = () => {},
+            signed: bool = (false) => {bool},
+            lat: Option<f32> = () => {Option},
+            long: Option<f32> = () => {Option},
+            place_id: Option<Id> = () => {Option},
+        }
+        unsized {
+            message: str = ("") => {=},
+            attachments: [api::Attachment] = (&[][..]) => {Vec},
+            guid: str = ("") => {=},
+            link_button: str = ("") => {=},
+            link_title: str = ("") => {=},
+            link_image: str = ("") => {=},
+        }
+    }
+}
+
+request_ref! {
+    /// Set `thread_items_count` to get replies to top level comments in their `thread`,
+    /// or `comment_id` to get replies to given comment only.
+    struct GetComments for ["wall.getComments"](v => 5.92, extended => 0) -> Comments {
+        sized {
+            owner_id: Option<Owner> = {} => {Option},
+            post_id: Id = () => {},
+            need_likes: bool = (false) => {bool},
+            start_comment_id: Option<Id> = () => {Option},
+            offset: usize = (0) => {},
+            count: usize = (100) => {},
+            sort: SortOrder = () => {AsRef},
+            preview_length: u32 = (0) => {},
+            comment_id: Option<Id> = () => {Option},
+            thread_items_count: u8 = (0) => {},
+        }
+        unsized {
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request_ref! {
+    struct GetComment for ["wall.getComment"](v => 5.92, extended => 0) -> CommentItems {
+        sized {
+            owner_id: Option<Owner> = {} => {Option},
+            comment_id: Id = () => {},
+        }
+        unsized {
+            fields: [UserOptionField] = (&[][..]) => {AsRef<Vec>},
+        }
+    }
+}
+
+request_ref! {
+    struct CreateComment for ["wall.createComment"](v => 5.92) -> CommentId [Wall] {
+        sized {
+            owner_id: Option<Owner> = {} => {Option},
+            post_id: Id = () => {},
+            from_group: Option<Id> = () => {Option},
+            reply_to_comment: Option<Id> = () => {Option},
+            sticker_id: Option<Id> = () => {Option},
+        }
+        unsized {
+            message: str = ("") => {=},
+            attachments: [api::Attachment] = (&[][..]) => {Vec},
+            guid: str = ("") => {=},
+        }
+    }
+}
+
+request_ref! {
+    struct EditComment for ["wall.editComment"](v => 5.92) -> Bool [Wall] {
+        sized {
+            owner_id: Option<Owner> = {} => {Option},
+            comment_id: Id = () => {},
+        }
+        unsized {
+            message: str = ("") => {=},
+            attachments: [api::Attachment] = (&[][..]) => {Vec},
+        }
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct DeleteComment for ["wall.deleteComment"](v => 5.92) -> Bool [Wall] {
+        owner_id: Option<Owner> = {} => {Option},
+        comment_id: Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct RestoreComment for ["wall.restoreComment"](v => 5.92) -> Bool [Wall] {
+        owner_id: Option<Owner> = {} => {Option},
+        comment_id: Id = () => {},
+    }
+}
+
+request! {
+    #[derive(Eq, Copy)]
+    struct ReportComment for ["wall.reportComment"](v => 5.92) -> Bool [Wall] {
+        owner_id: Owner = () => {},
+        comment_id: Id = () => {},
+        reason: ReportReason = () => {AsRef},
+    }
+}
+
 enum_str! { Filter {
     Owner = "owner",
     Others = "others",
     All = "all",
     Suggests = "suggests",
 }}
+
+enum Pending {
+    Comment(WallComment),
+    /// Replies to comment not loaded yet, starting from offset
+    Thread(Id, usize),
+}
+
+/// Sends `wall.getComments` for `CommentTree`, can be replaced by a stub in tests
+type CallComments<'a> = Box<dyn FnMut(&GetComments<'a>) -> Result<Comments> + 'a>;
+
+/// Iterator over all comments to a post, see `comment_tree()`
+pub struct CommentTree<'a> {
+    call: CallComments<'a>,
+    request: GetComments<'a>,
+    pending: VecDeque<Pending>,
+    offset: usize,
+    done: bool,
+}
+
+/// Walk all comments to a post depth first: every top level comment is followed
+/// by its replies, loading more of them if `thread` doesn't contain all of them.
+///
+/// Comments are loaded in pages as defined by `request`'s `count` and `offset`,
+/// set its `thread_items_count` to save requests on posts with few replies.
+/// Iteration stops after the first error.
+pub fn comment_tree<'a>(api: &'a Client, token: Option<&'a AccessToken>, request: &GetComments<'a>) -> CommentTree<'a> {
+    CommentTree::with_call(request, Box::new(move |request| api.call(token, request)))
+}
+
+impl<'a> CommentTree<'a> {
+    fn with_call(request: &GetComments<'a>, call: CallComments<'a>) -> CommentTree<'a> {
+        CommentTree {
+            call,
+            request: request.clone(),
+            pending: VecDeque::new(),
+            offset: request.offset,
+            done: false,
+        }
+    }
+
+    fn load_comments(&mut self) -> Result<()> {
+        let comments = (self.call)(self.request.clone().comment_id(None).offset(self.offset))?;
+        self.offset += comments.items.len();
+        if comments.items.is_empty() || self.offset >= comments.current_level_count as usize {
+            self.done = true;
+        }
+
+        for mut comment in comments.items {
+            let replies = comment.thread.as_mut().map(|thread| (thread.count as usize, thread.items.split_off(0)));
+            let comment_id = comment.id;
+            self.pending.push_back(Pending::Comment(comment));
+            if let Some((count, replies)) = replies {
+                let loaded = replies.len();
+                self.pending.extend(replies.into_iter().map(Pending::Comment));
+                if loaded < count {
+                    self.pending.push_back(Pending::Thread(comment_id, loaded));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn load_replies(&mut self, comment_id: Id, offset: usize) -> Result<()> {
+        let replies = (self.call)(self.request.clone()
+                                      .comment_id(Some(comment_id))
+                                      .start_comment_id(None)
+                                      .thread_items_count(0)
+                                      .offset(offset))?;
+        let loaded = offset + replies.items.len();
+        if !replies.items.is_empty() && loaded < replies.current_level_count as usize {
+            self.pending.push_front(Pending::Thread(comment_id, loaded));
+        }
+        for reply in replies.items.into_iter().rev() {
+            self.pending.push_front(Pending::Comment(reply));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for CommentTree<'a> {
+    type Item = Result<WallComment>;
+
+    fn next(&mut self) -> Option<Result<WallComment>> {
+        loop {
+            let loaded = match self.pending.pop_front() {
+                Some(Pending::Comment(comment)) => return Some(Ok(comment)),
+                Some(Pending::Thread(comment_id, offset)) => self.load_replies(comment_id, offset),
+                None if self.done => return None,
+                None => self.load_comments(),
+            };
+
+            if let Err(err) = loaded {
+                self.pending.clear();
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use serde_json::{self, Value};
+    use api::{Error, Request};
+    use super::*;
+
+    const POST: &str = r#"{
+        "id": 10,
+        "owner_id": -1,
+        "from_id": -1,
+        "date": 1500000000,
+        "text": "Release notes",
+        "comments": {"count": 2, "can_post": 1},
+        "likes": {"user_likes": 0, "count": 15},
+        "reposts": {"count": 1, "user_reposted": 0},
+        "post_type": "post",
+        "post_source": {"type": "api", "platform": "android", "url": ""},
+        "attachments": [
+            {"type": "photo", "photo": {
+                "id": 20, "album_id": -7, "owner_id": -1, "date": 1500000001,
+                "sizes": [{"src": "https://pp.userapi.com/s.jpg", "width": 75, "height": 50, "type": "s"}]
+            }},
+            {"type": "video", "video": {
+                "id": 30, "owner_id": -1, "title": "Demo", "duration": 60, "date": 1500000002,
+                "photo_130": "https://pp.userapi.com/130.jpg", "photo_320": "", "can_add": 1
+            }},
+            {"type": "link", "link": {"url": "https://vk.com/dev", "title": "Docs"}},
+            {"type": "story", "story": {"id": 40}}
+        ],
+        "can_pin": 1,
+        "is_pinned": 0
+    }"#;
+
+    #[test]
+    fn wall_post_round_trip() {
+        let post: WallPost = serde_json::from_str(POST).unwrap();
+        let kinds: Vec<&str> = post.attachments.iter().map(Attachment::kind).collect();
+        assert_eq!(kinds, ["photo", "video", "link", "story"]);
+        assert_eq!((post.can_pin, post.is_pinned), (adapters::flag::from_bool(true), adapters::flag::from_bool(false)));
+        assert_eq!(post.comments.can_post, adapters::flag::from_bool(true));
+        assert_eq!(post.post_source.as_ref().and_then(|source| source.url.as_ref()), None);
+        match post.attachments[3] {
+            Attachment::Unknown { ref object, .. } => assert_eq!(object["id"], 40),
+            ref other => panic!("unexpected attachment {:?}", other),
+        }
+
+        let value = serde_json::to_value(&post).unwrap();
+        assert_eq!(value["date"], 1500000000);
+        assert_eq!(value["can_pin"], 1);
+        assert_eq!(value["reposts"]["user_reposted"], 0);
+        assert_eq!(value["attachments"][0]["type"], "photo");
+        assert_eq!(value["attachments"][0]["photo"]["date"], 1500000001);
+        assert_eq!(value["attachments"][0]["photo"]["sizes"][0]["type"], "s");
+        assert_eq!(value["attachments"][1]["video"]["date"], 1500000002);
+        assert_eq!(value["attachments"][1]["video"]["can_add"], 1);
+        assert_eq!(value["attachments"][1]["video"]["photo_130"], "https://pp.userapi.com/130.jpg");
+        assert_eq!(value["attachments"][1]["video"]["photo_320"], "");
+        assert_eq!(value["attachments"][2]["link"]["url"], "https://vk.com/dev");
+        assert_eq!(value["attachments"][3]["story"]["id"], 40);
+        assert_eq!(serde_json::from_value::<WallPost>(value).unwrap(), post);
+    }
+
+    #[test]
+    fn unknown_post_type() {
+        let post: WallPost = serde_json::from_str(&POST.replace(r#""post_type": "post""#, r#""post_type": "clip""#)).unwrap();
+        assert_eq!(post.post_type, PostType::Unknown);
+    }
+
+    #[test]
+    fn malformed_attachment_is_unknown() {
+        let post: WallPost = serde_json::from_str(&POST.replace(r#""title": "Docs""#, r#""title": 42"#)).unwrap();
+        match post.attachments[2] {
+            Attachment::Unknown { ref kind, ref object } => {
+                assert_eq!(kind, "link");
+                assert_eq!(object["title"], 42);
+            }
+            ref other => panic!("unexpected attachment {:?}", other),
+        }
+        assert!(Attachment::from_object("link", json_value(r#"{"title": 42}"#)).is_err());
+    }
+
+    #[test]
+    fn unset_place_is_not_sent() {
+        let query = Edit::new().owner_id(Owner::Group(1)).post_id(10).to_query_string();
+        assert!(!query.contains("lat=") && !query.contains("long=") && !query.contains("place_id="));
+
+        let query = PostAdsStealth::new().owner_id(Owner::Group(1)).lat(Some(59.9)).long(Some(30.3)).to_query_string();
+        assert!(query.contains("lat=59.9") && query.contains("long=30.3") && !query.contains("place_id="));
+    }
+
+    fn json_value(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn comment(id: Id, thread: &str) -> String {
+        format!(r#"{{"id": {}, "from_id": 5, "date": 1500000000, "thread": {}}}"#, id, thread)
+    }
+
+    fn page(level_count: u32, items: &[String]) -> Result<Comments> {
+        let json = format!(r#"{{"count": 10, "current_level_count": {}, "items": [{}]}}"#, level_count, items.join(","));
+        Ok(serde_json::from_str(&json).unwrap())
+    }
+
+    fn ids(tree: CommentTree) -> Vec<Result<Id>> {
+        tree.map(|comment| comment.map(|comment| comment.id)).collect()
+    }
+
+    #[test]
+    fn comment_tree_depth_first() {
+        let calls = RefCell::new(Vec::new());
+        let mut request = GetComments::new();
+        request.post_id(10).count(2).thread_items_count(1);
+
+        let tree = CommentTree::with_call(&request, Box::new(|request| {
+            calls.borrow_mut().push((request.comment_id, request.offset, request.thread_items_count));
+            match (request.comment_id, request.offset) {
+                (None, 0) => page(3, &[comment(1, &format!(r#"{{"count": 4, "items": [{}]}}"#, comment(11, "null"))),
+                                       comment(2, r#"{"count": 0}"#)]),
+                (None, 2) => page(3, &[comment(3, &format!(r#"{{"count": 1, "items": [{}]}}"#, comment(31, "null")))]),
+                // Two more pages of replies to the first comment
+                (Some(1), 1) => page(4, &[comment(12, "null"), comment(13, "null")]),
+                (Some(1), 3) => page(4, &[comment(14, "null")]),
+                other => panic!("unexpected request {:?}", other),
+            }
+        }));
+
+        let expected: Vec<Result<Id>> = vec![Ok(1), Ok(11), Ok(12), Ok(13), Ok(14), Ok(2), Ok(3), Ok(31)];
+        assert_eq!(format!("{:?}", ids(tree)), format!("{:?}", expected));
+        assert_eq!(*calls.borrow(), [(None, 0, 1), (Some(1), 1, 0), (Some(1), 3, 0), (None, 2, 1)]);
+    }
+
+    #[test]
+    fn comment_tree_stops_after_error() {
+        let calls = RefCell::new(0);
+        let mut request = GetComments::new();
+        request.post_id(10).thread_items_count(1);
+
+        let tree = CommentTree::with_call(&request, Box::new(|request| {
+            *calls.borrow_mut() += 1;
+            match request.comment_id {
+                None => page(2, &[comment(1, &format!(r#"{{"count": 2, "items": [{}]}}"#, comment(11, "null"))),
+                                  comment(2, "null")]),
+                Some(_) => Err(Error::Json(serde_json::from_str::<Comments>("{").unwrap_err())),
+            }
+        }));
+
+        let comments = ids(tree);
+        assert_eq!(comments.len(), 3);
+        assert_eq!(comments[0].as_ref().ok(), Some(&1));
+        assert_eq!(comments[1].as_ref().ok(), Some(&11));
+        assert!(comments[2].is_err());
+        assert_eq!(*calls.borrow(), 2);
+    }
+}